@@ -0,0 +1,122 @@
+//! Encoding of keys and values into page bytes
+//!
+//! The tree never decodes keys to compare them: keys are ordered by their
+//! encoded bytes, so only types whose encoding preserves their order can
+//! be keys. Integers are therefore stored big-endian, with the sign bit
+//! of signed ones flipped.
+
+use std::convert::TryInto;
+
+use crate::bptree::error::StorageError;
+
+/// A value with a binary encoding
+pub trait Encoding: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Decodes a value from all of `bytes`
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError>;
+}
+
+/// A value whose encodings compare like the values themselves
+pub trait KeyEncoding: Encoding + Ord {}
+
+fn fixed<const LEN: usize>(bytes: &[u8]) -> Result<[u8; LEN], StorageError> {
+    bytes.try_into().map_err(|_| {
+        StorageError::InvalidValue(format!("expected {} bytes, got {}", LEN, bytes.len()))
+    })
+}
+
+macro_rules! impl_encoding_for_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl Encoding for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+                    Ok(<$t>::from_be_bytes(fixed(bytes)?))
+                }
+            }
+
+            impl KeyEncoding for $t {}
+        )*
+    };
+}
+
+macro_rules! impl_encoding_for_signed {
+    ($($t:ty => $unsigned:ty),*) => {
+        $(
+            impl Encoding for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    // Negative numbers have to come first
+                    let flipped = (*self as $unsigned) ^ (1 << (<$unsigned>::BITS - 1));
+                    flipped.encode(out);
+                }
+
+                fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+                    let flipped = <$unsigned>::decode(bytes)?;
+                    Ok((flipped ^ (1 << (<$unsigned>::BITS - 1))) as $t)
+                }
+            }
+
+            impl KeyEncoding for $t {}
+        )*
+    };
+}
+
+impl_encoding_for_unsigned!(u8, u16, u32, u64, u128);
+impl_encoding_for_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+impl Encoding for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl KeyEncoding for Vec<u8> {}
+
+impl Encoding for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| StorageError::InvalidValue(e.to_string()))
+    }
+}
+
+/// UTF-8 preserves the order of code points
+impl KeyEncoding for String {}
+
+pub(crate) fn encode<T: Encoding>(value: &T) -> Vec<u8> {
+    let mut out = vec![];
+    value.encode(&mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_preserved() {
+        let ints = [i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX];
+        for pair in ints.windows(2) {
+            assert!(encode(&pair[0]) < encode(&pair[1]));
+        }
+        for &int in ints.iter() {
+            assert_eq!(i64::decode(&encode(&int)).unwrap(), int);
+        }
+        let strings = ["", "a", "ab", "b", "é"];
+        for pair in strings.windows(2) {
+            assert!(encode(&pair[0].to_string()) < encode(&pair[1].to_string()));
+        }
+        assert!(u32::decode(&[1, 2]).is_err());
+        assert!(String::decode(&[0xff]).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::bptree::page::PageId;
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// The data file or the log failed
+    Io(io::Error),
+    /// The data file does not start with the expected magic bytes
+    BadMagic([u8; 8]),
+    /// The data file was written with a different page size
+    PageSizeMismatch(u32),
+    /// A page does not hold a valid node
+    CorruptPage { page: PageId, reason: String },
+    /// An encoded key and value do not fit the limit of `page::MAX_ENTRY_LEN`
+    EntryTooLarge(usize),
+    /// A key or value could not be decoded
+    InvalidValue(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "I/O error: {}", e),
+            StorageError::BadMagic(magic) => write!(f, "bad magic bytes {:?}", magic),
+            StorageError::PageSizeMismatch(size) => {
+                write!(f, "file uses pages of {} bytes", size)
+            }
+            StorageError::CorruptPage { page, reason } => {
+                write!(f, "page {} is corrupt: {}", page, reason)
+            }
+            StorageError::EntryTooLarge(len) => {
+                write!(f, "entry of {} bytes does not fit into a page", len)
+            }
+            StorageError::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StorageError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
@@ -0,0 +1,13 @@
+//! A B+ tree stored in a file of fixed-size pages
+//!
+//! `tree::BPlusTree` is the entry point. Pages are laid out as described
+//! in `page`, cached by `pool::BufferPool` and made crash safe by the
+//! write-ahead log in `wal`. Keys and values are converted to bytes by
+//! the traits in `codec`.
+
+pub mod codec;
+pub mod error;
+pub mod page;
+pub mod pool;
+pub mod tree;
+pub mod wal;
@@ -0,0 +1,274 @@
+//! Page layout of the data file
+//!
+//! The file is a sequence of `PAGE_SIZE` byte pages, addressed by their
+//! index. All integers are little-endian.
+//!
+//! Page 0 is the header:
+//!
+//! ```text
+//! magic: b"DBPTREE1" | page size: u32 | root: u32 | page count: u32
+//! ```
+//!
+//! Every other page holds one node, starting with
+//!
+//! ```text
+//! kind: u8 | entry count: u16 | link: u32
+//! ```
+//!
+//! and followed by its entries. In a leaf (kind 1) `link` is the next
+//! leaf to the right, or 0 for the last one, and the entries are sorted
+//! key-value pairs:
+//!
+//! ```text
+//! key length: u16 | value length: u16 | key | value
+//! ```
+//!
+//! In an internal node (kind 2) `link` is the leftmost child, holding
+//! the keys less than the first separator, and every entry is a separator
+//! key with the child holding the keys from it up to the next separator:
+//!
+//! ```text
+//! key length: u16 | child: u32 | key
+//! ```
+//!
+//! The rest of a page is zero.
+
+use std::convert::TryInto;
+
+use crate::bptree::error::StorageError;
+
+pub type PageId = u32;
+
+pub const PAGE_SIZE: usize = 4096;
+
+pub type Page = [u8; PAGE_SIZE];
+
+pub const HEADER_PAGE: PageId = 0;
+
+const MAGIC: [u8; 8] = *b"DBPTREE1";
+
+const LEAF: u8 = 1;
+const INTERNAL: u8 = 2;
+const NODE_HEADER_LEN: usize = 7;
+const LEAF_ENTRY_HEADER_LEN: usize = 4;
+const INTERNAL_ENTRY_HEADER_LEN: usize = 6;
+
+/// The longest key and value, encoded, which can be stored together
+///
+/// Keeping entries under a quarter of a page guarantees that both halves
+/// of a split node fit into their pages.
+pub const MAX_ENTRY_LEN: usize = (PAGE_SIZE - NODE_HEADER_LEN) / 4 - INTERNAL_ENTRY_HEADER_LEN;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Header {
+    pub root: PageId,
+    pub page_count: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    Leaf {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        /// The next leaf to the right, 0 for none
+        next: PageId,
+    },
+    Internal {
+        keys: Vec<Vec<u8>>,
+        /// One more than `keys`
+        children: Vec<PageId>,
+    },
+}
+
+fn u16_at(page: &Page, offset: usize) -> usize {
+    u16::from_le_bytes(page[offset..offset + 2].try_into().unwrap()) as usize
+}
+
+fn u32_at(page: &Page, offset: usize) -> u32 {
+    u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap())
+}
+
+impl Header {
+    pub fn read(page: &Page) -> Result<Self, StorageError> {
+        let magic: [u8; 8] = page[..8].try_into().unwrap();
+        if magic != MAGIC {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let page_size = u32_at(page, 8);
+        if page_size as usize != PAGE_SIZE {
+            return Err(StorageError::PageSizeMismatch(page_size));
+        }
+        Ok(Header {
+            root: u32_at(page, 12),
+            page_count: u32_at(page, 16),
+        })
+    }
+
+    pub fn write(&self, page: &mut Page) {
+        page.fill(0);
+        page[..8].copy_from_slice(&MAGIC);
+        page[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        page[12..16].copy_from_slice(&self.root.to_le_bytes());
+        page[16..20].copy_from_slice(&self.page_count.to_le_bytes());
+    }
+}
+
+impl Node {
+    pub fn empty_leaf() -> Self {
+        Node::Leaf {
+            entries: vec![],
+            next: 0,
+        }
+    }
+
+    /// Number of bytes `write` takes
+    pub fn encoded_len(&self) -> usize {
+        NODE_HEADER_LEN
+            + match self {
+                Node::Leaf { entries, .. } => entries
+                    .iter()
+                    .map(|(key, value)| LEAF_ENTRY_HEADER_LEN + key.len() + value.len())
+                    .sum::<usize>(),
+                Node::Internal { keys, .. } => keys
+                    .iter()
+                    .map(|key| INTERNAL_ENTRY_HEADER_LEN + key.len())
+                    .sum(),
+            }
+    }
+
+    pub fn fits(&self) -> bool {
+        self.encoded_len() <= PAGE_SIZE
+    }
+
+    pub fn read(page: &Page, id: PageId) -> Result<Self, StorageError> {
+        let corrupt = |reason: &str| StorageError::CorruptPage {
+            page: id,
+            reason: reason.to_string(),
+        };
+        let count = u16_at(page, 1);
+        let link = u32_at(page, 3);
+        let mut offset = NODE_HEADER_LEN;
+        // Every length is checked before slicing, so that a corrupt page
+        // ends in an error rather than a panic
+        let mut take = |len: usize| {
+            if offset + len > PAGE_SIZE {
+                return Err(corrupt("entries overflow the page"));
+            }
+            offset += len;
+            Ok(&page[offset - len..offset])
+        };
+        match page[0] {
+            LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let lens = take(LEAF_ENTRY_HEADER_LEN)?;
+                    let key_len = u16::from_le_bytes([lens[0], lens[1]]) as usize;
+                    let value_len = u16::from_le_bytes([lens[2], lens[3]]) as usize;
+                    let key = take(key_len)?.to_vec();
+                    let value = take(value_len)?.to_vec();
+                    entries.push((key, value));
+                }
+                Ok(Node::Leaf {
+                    entries,
+                    next: link,
+                })
+            }
+            INTERNAL => {
+                let mut keys = Vec::with_capacity(count);
+                let mut children = Vec::with_capacity(count + 1);
+                children.push(link);
+                for _ in 0..count {
+                    let header = take(INTERNAL_ENTRY_HEADER_LEN)?;
+                    let key_len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                    children.push(u32::from_le_bytes(header[2..6].try_into().unwrap()));
+                    keys.push(take(key_len)?.to_vec());
+                }
+                Ok(Node::Internal { keys, children })
+            }
+            kind => Err(corrupt(&format!("unknown node kind {}", kind))),
+        }
+    }
+
+    /// Writes the node into `page`, which it has to fit
+    pub fn write(&self, page: &mut Page) {
+        assert!(self.fits(), "Node does not fit into a page");
+        page.fill(0);
+        let mut offset = NODE_HEADER_LEN;
+        let mut put = |bytes: &[u8]| {
+            page[offset..offset + bytes.len()].copy_from_slice(bytes);
+            offset += bytes.len();
+        };
+        match self {
+            Node::Leaf { entries, next } => {
+                for (key, value) in entries.iter() {
+                    put(&(key.len() as u16).to_le_bytes());
+                    put(&(value.len() as u16).to_le_bytes());
+                    put(key);
+                    put(value);
+                }
+                page[0] = LEAF;
+                page[1..3].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+                page[3..7].copy_from_slice(&next.to_le_bytes());
+            }
+            Node::Internal { keys, children } => {
+                for (key, child) in keys.iter().zip(children[1..].iter()) {
+                    put(&(key.len() as u16).to_le_bytes());
+                    put(&child.to_le_bytes());
+                    put(key);
+                }
+                page[0] = INTERNAL;
+                page[1..3].copy_from_slice(&(keys.len() as u16).to_le_bytes());
+                page[3..7].copy_from_slice(&children[0].to_le_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut page = [0; PAGE_SIZE];
+        let header = Header {
+            root: 3,
+            page_count: 7,
+        };
+        header.write(&mut page);
+        assert_eq!(Header::read(&page).unwrap(), header);
+
+        let leaf = Node::Leaf {
+            entries: vec![
+                (b"a".to_vec(), b"".to_vec()),
+                (b"bc".to_vec(), b"def".to_vec()),
+            ],
+            next: 5,
+        };
+        leaf.write(&mut page);
+        assert_eq!(&page[..7], &[1, 2, 0, 5, 0, 0, 0]);
+        assert_eq!(Node::read(&page, 1).unwrap(), leaf);
+        assert_eq!(leaf.encoded_len(), 7 + 5 + 9);
+
+        let internal = Node::Internal {
+            keys: vec![b"m".to_vec()],
+            children: vec![1, 2],
+        };
+        internal.write(&mut page);
+        assert_eq!(Node::read(&page, 1).unwrap(), internal);
+        assert!(Header::read(&page).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_page() {
+        let mut page = [0; PAGE_SIZE];
+        page[0] = LEAF;
+        page[1] = 1;
+        page[7..9].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(matches!(
+            Node::read(&page, 4),
+            Err(StorageError::CorruptPage { page: 4, .. })
+        ));
+        page[0] = 9;
+        assert!(Node::read(&page, 4).is_err());
+    }
+}
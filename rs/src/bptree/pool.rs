@@ -0,0 +1,182 @@
+//! A buffer pool caching pages of the data file
+//!
+//! Changed pages stay in the pool until `commit` logs them and only then
+//! writes them to the data file, so the pool may grow beyond its capacity
+//! while an operation is in progress. Clean pages are evicted least
+//! recently used first.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::bptree::page::{Page, PageId, PAGE_SIZE};
+use crate::bptree::wal::Wal;
+
+/// The log gets folded into the data file once it grows past this
+const CHECKPOINT_LEN: u64 = 4 << 20;
+
+struct Frame {
+    page: Box<Page>,
+    dirty: bool,
+    last_used: u64,
+}
+
+pub struct BufferPool {
+    data: File,
+    wal: Wal,
+    frames: HashMap<PageId, Frame>,
+    capacity: usize,
+    clock: u64,
+    /// Leaves committed pages out of the data file, as if every commit
+    /// crashed right after logging
+    #[cfg(test)]
+    pub(crate) crash_after_logging: bool,
+}
+
+impl BufferPool {
+    /// Opens the data file and its log, redoing the operations the log
+    /// holds, in case the last run crashed before they reached the file
+    pub fn open(data_path: &Path, wal_path: &Path, capacity: usize) -> io::Result<Self> {
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(data_path)?;
+        let wal = Wal::open(wal_path)?;
+        let mut pool = Self {
+            data,
+            wal,
+            frames: HashMap::new(),
+            capacity,
+            clock: 0,
+            #[cfg(test)]
+            crash_after_logging: false,
+        };
+        for frame in pool.wal.frames()? {
+            for (id, page) in frame {
+                pool.write_through(id, &page)?;
+            }
+        }
+        pool.checkpoint()?;
+        Ok(pool)
+    }
+
+    /// Whether the data file has no pages yet
+    pub fn is_new(&self) -> io::Result<bool> {
+        Ok(self.data.metadata()?.len() == 0)
+    }
+
+    fn write_through(&mut self, id: PageId, page: &Page) -> io::Result<()> {
+        self.data
+            .seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+        self.data.write_all(page)
+    }
+
+    fn frame(&mut self, id: PageId) -> io::Result<&mut Frame> {
+        self.clock += 1;
+        if !self.frames.contains_key(&id) {
+            self.evict()?;
+            let mut page = Box::new([0; PAGE_SIZE]);
+            // Pages past the end of the file are new, and zero
+            let file_len = self.data.metadata()?.len();
+            let offset = id as u64 * PAGE_SIZE as u64;
+            if offset < file_len {
+                self.data.seek(SeekFrom::Start(offset))?;
+                self.data.read_exact(&mut page[..])?;
+            }
+            self.frames.insert(
+                id,
+                Frame {
+                    page,
+                    dirty: false,
+                    last_used: 0,
+                },
+            );
+        }
+        let frame = self.frames.get_mut(&id).unwrap();
+        frame.last_used = self.clock;
+        Ok(frame)
+    }
+
+    /// Makes room for one more page, if there is a clean one to drop
+    fn evict(&mut self) -> io::Result<()> {
+        if self.frames.len() < self.capacity {
+            return Ok(());
+        }
+        let victim = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| !frame.dirty)
+            .min_by_key(|(_, frame)| frame.last_used)
+            .map(|(id, _)| *id);
+        if let Some(victim) = victim {
+            self.frames.remove(&victim);
+        }
+        Ok(())
+    }
+
+    pub fn page(&mut self, id: PageId) -> io::Result<&Page> {
+        Ok(&self.frame(id)?.page)
+    }
+
+    /// The page to change, which becomes part of the next commit
+    pub fn page_mut(&mut self, id: PageId) -> io::Result<&mut Page> {
+        let frame = self.frame(id)?;
+        frame.dirty = true;
+        Ok(&mut frame.page)
+    }
+
+    /// Makes the changes since the last commit durable
+    pub fn commit(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<PageId> = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| frame.dirty)
+            .map(|(id, _)| *id)
+            .collect();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        dirty.sort_unstable();
+        let frames = &self.frames;
+        self.wal
+            .append(dirty.iter().map(|id| (*id, &*frames[id].page)))?;
+
+        #[cfg(test)]
+        {
+            if self.crash_after_logging {
+                return Ok(());
+            }
+        }
+        for id in dirty {
+            let frame = self.frames.get_mut(&id).unwrap();
+            frame.dirty = false;
+            let offset = id as u64 * PAGE_SIZE as u64;
+            self.data.seek(SeekFrom::Start(offset))?;
+            self.data.write_all(&frame.page[..])?;
+        }
+        if self.wal.len() >= CHECKPOINT_LEN {
+            self.checkpoint()?;
+        }
+        while self.frames.len() > self.capacity && self.frames.values().any(|f| !f.dirty) {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// Drops the changes since the last commit
+    pub fn rollback(&mut self) {
+        self.frames.retain(|_, frame| !frame.dirty);
+    }
+
+    /// Syncs the data file, after which the log is not needed anymore
+    fn checkpoint(&mut self) -> io::Result<()> {
+        if self.wal.is_empty() {
+            return Ok(());
+        }
+        self.data.sync_all()?;
+        self.wal.truncate()
+    }
+}
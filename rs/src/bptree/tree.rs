@@ -0,0 +1,510 @@
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+use crate::bptree::codec::{encode, Encoding, KeyEncoding};
+use crate::bptree::error::StorageError;
+use crate::bptree::page::{Header, Node, PageId, HEADER_PAGE, MAX_ENTRY_LEN};
+use crate::bptree::pool::BufferPool;
+
+/// Pages cached by `BPlusTree::open`
+const DEFAULT_POOL_CAPACITY: usize = 256;
+
+/// A node that split, returned to its parent
+struct Split {
+    /// The smallest key in `right`
+    separator: Vec<u8>,
+    right: PageId,
+}
+
+/// A B+ tree stored in a file, mapping keys to values
+///
+/// Values live in the leaves only, which are linked left to right for
+/// range scans. Every `insert` and `remove` is atomic: the log written
+/// next to the data file, at its path with a `.wal` suffix, lets the
+/// tree recover from a crash at any point. Removing never merges nodes,
+/// so the file does not shrink.
+pub struct BPlusTree<Key, Value> {
+    pool: BufferPool,
+    _types: PhantomData<(Key, Value)>,
+}
+
+fn wal_path(path: &Path) -> PathBuf {
+    let mut wal_path = path.as_os_str().to_owned();
+    wal_path.push(".wal");
+    PathBuf::from(wal_path)
+}
+
+/// Splits `len` entries of `sizes` bytes so that the left part holds
+/// about half of the bytes, returning the number of entries in it
+fn split_point<I: Iterator<Item = usize>>(len: usize, sizes: I) -> usize {
+    let sizes: Vec<usize> = sizes.collect();
+    let total: usize = sizes.iter().sum();
+    let mut left = 0;
+    let mut count = 0;
+    for size in sizes {
+        if left >= total / 2 {
+            break;
+        }
+        left += size;
+        count += 1;
+    }
+    count.clamp(1, len - 1)
+}
+
+impl<Key: KeyEncoding, Value: Encoding> BPlusTree<Key, Value> {
+    /// Opens the tree in the file at `path`, creating it if necessary
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        Self::with_pool_capacity(path, DEFAULT_POOL_CAPACITY)
+    }
+
+    /// Like `open`, but caching up to `capacity` pages
+    pub fn with_pool_capacity<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+    ) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+        let mut pool = BufferPool::open(path, &wal_path(path), capacity)?;
+        if pool.is_new()? {
+            Header {
+                root: 1,
+                page_count: 2,
+            }
+            .write(pool.page_mut(HEADER_PAGE)?);
+            Node::empty_leaf().write(pool.page_mut(1)?);
+            pool.commit()?;
+        }
+        Header::read(pool.page(HEADER_PAGE)?)?;
+        Ok(Self {
+            pool,
+            _types: PhantomData,
+        })
+    }
+
+    fn header(&mut self) -> Result<Header, StorageError> {
+        Header::read(self.pool.page(HEADER_PAGE)?)
+    }
+
+    fn read_node(&mut self, id: PageId) -> Result<Node, StorageError> {
+        Node::read(self.pool.page(id)?, id)
+    }
+
+    fn write_node(&mut self, id: PageId, node: &Node) -> Result<(), StorageError> {
+        node.write(self.pool.page_mut(id)?);
+        Ok(())
+    }
+
+    fn allocate(&mut self, node: &Node) -> Result<PageId, StorageError> {
+        let mut header = self.header()?;
+        let id = header.page_count;
+        header.page_count += 1;
+        header.write(self.pool.page_mut(HEADER_PAGE)?);
+        self.write_node(id, node)?;
+        Ok(id)
+    }
+
+    /// Commits the changes of a successful operation, or drops the partial
+    /// changes of a failed one
+    fn finish<T>(&mut self, result: Result<T, StorageError>) -> Result<T, StorageError> {
+        match result {
+            Ok(value) => {
+                self.pool.commit()?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.pool.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    /// The leaf which `key` belongs to
+    fn find_leaf(&mut self, key: &[u8]) -> Result<PageId, StorageError> {
+        let mut id = self.header()?.root;
+        loop {
+            match self.read_node(id)? {
+                Node::Leaf { .. } => return Ok(id),
+                Node::Internal { keys, children } => {
+                    id = children[keys.partition_point(|k| k.as_slice() <= key)];
+                }
+            }
+        }
+    }
+
+    pub fn get(&mut self, key: &Key) -> Result<Option<Value>, StorageError> {
+        let key = encode(key);
+        let leaf = self.find_leaf(&key)?;
+        match self.read_node(leaf)? {
+            Node::Leaf { entries, .. } => {
+                match entries.binary_search_by(|(k, _)| k.as_slice().cmp(&key)) {
+                    Ok(index) => Ok(Some(Value::decode(&entries[index].1)?)),
+                    Err(_) => Ok(None),
+                }
+            }
+            Node::Internal { .. } => unreachable!(),
+        }
+    }
+
+    pub fn contains_key(&mut self, key: &Key) -> Result<bool, StorageError> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Inserts `value` at `key`, returning the value it replaces
+    pub fn insert(&mut self, key: &Key, value: &Value) -> Result<Option<Value>, StorageError> {
+        let (key, value) = (encode(key), encode(value));
+        if key.len() + value.len() > MAX_ENTRY_LEN {
+            return Err(StorageError::EntryTooLarge(key.len() + value.len()));
+        }
+        let result = self.insert_encoded(key, value);
+        self.finish(result)
+    }
+
+    fn insert_encoded(
+        &mut self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Result<Option<Value>, StorageError> {
+        let root = self.header()?.root;
+        let (replaced, split) = self.insert_into(root, key, value)?;
+        if let Some(Split { separator, right }) = split {
+            let new_root = self.allocate(&Node::Internal {
+                keys: vec![separator],
+                children: vec![root, right],
+            })?;
+            let mut header = self.header()?;
+            header.root = new_root;
+            header.write(self.pool.page_mut(HEADER_PAGE)?);
+        }
+        replaced.map(|bytes| Value::decode(&bytes)).transpose()
+    }
+
+    fn insert_into(
+        &mut self,
+        id: PageId,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Result<(Option<Vec<u8>>, Option<Split>), StorageError> {
+        let mut node = self.read_node(id)?;
+        let mut replaced = None;
+        match &mut node {
+            Node::Leaf { entries, .. } => match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                Ok(index) => {
+                    replaced = Some(std::mem::replace(&mut entries[index].1, value));
+                }
+                Err(index) => entries.insert(index, (key, value)),
+            },
+            Node::Internal { keys, children } => {
+                let index = keys.partition_point(|k| *k <= key);
+                let (child_replaced, split) = self.insert_into(children[index], key, value)?;
+                replaced = child_replaced;
+                match split {
+                    Some(Split { separator, right }) => {
+                        keys.insert(index, separator);
+                        children.insert(index + 1, right);
+                    }
+                    None => return Ok((replaced, None)),
+                }
+            }
+        }
+        if node.fits() {
+            self.write_node(id, &node)?;
+            return Ok((replaced, None));
+        }
+        let (left, right, separator) = match node {
+            Node::Leaf { mut entries, next } => {
+                let len = entries.len();
+                let at = split_point(len, entries.iter().map(|(k, v)| k.len() + v.len()));
+                let right_entries = entries.split_off(at);
+                let separator = right_entries[0].0.clone();
+                let right = Node::Leaf {
+                    entries: right_entries,
+                    next,
+                };
+                (Node::Leaf { entries, next: 0 }, right, separator)
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let at = split_point(keys.len(), keys.iter().map(|k| k.len()));
+                let right_keys = keys.split_off(at + 1);
+                let right_children = children.split_off(at + 1);
+                let separator = keys.pop().unwrap();
+                let right = Node::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                };
+                (Node::Internal { keys, children }, right, separator)
+            }
+        };
+        let right = self.allocate(&right)?;
+        let left = match left {
+            Node::Leaf { entries, .. } => Node::Leaf {
+                entries,
+                next: right,
+            },
+            internal => internal,
+        };
+        self.write_node(id, &left)?;
+        Ok((replaced, Some(Split { separator, right })))
+    }
+
+    /// Removes `key`, returning its value if it was present
+    pub fn remove(&mut self, key: &Key) -> Result<Option<Value>, StorageError> {
+        let key = encode(key);
+        let result = self.remove_encoded(&key);
+        self.finish(result)
+    }
+
+    fn remove_encoded(&mut self, key: &[u8]) -> Result<Option<Value>, StorageError> {
+        let leaf = self.find_leaf(key)?;
+        let mut node = self.read_node(leaf)?;
+        let removed = match &mut node {
+            Node::Leaf { entries, .. } => {
+                match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+                    Ok(index) => entries.remove(index).1,
+                    Err(_) => return Ok(None),
+                }
+            }
+            Node::Internal { .. } => unreachable!(),
+        };
+        self.write_node(leaf, &node)?;
+        Ok(Some(Value::decode(&removed)?))
+    }
+
+    /// Scans the entries with keys in `range` in order, following the
+    /// links between leaves
+    pub fn range<R: RangeBounds<Key>>(
+        &mut self,
+        range: R,
+    ) -> Result<Range<'_, Key, Value>, StorageError> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Included(encode(key)),
+            Bound::Excluded(key) => Bound::Excluded(encode(key)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => Bound::Included(encode(key)),
+            Bound::Excluded(key) => Bound::Excluded(encode(key)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let leaf = match &start {
+            Bound::Included(key) | Bound::Excluded(key) => self.find_leaf(key)?,
+            Bound::Unbounded => self.find_leaf(&[])?,
+        };
+        let (entries, index, next) = match self.read_node(leaf)? {
+            Node::Leaf { entries, next } => {
+                let index = entries.partition_point(|(k, _)| match &start {
+                    Bound::Included(start) => k < start,
+                    Bound::Excluded(start) => k <= start,
+                    Bound::Unbounded => false,
+                });
+                (entries, index, next)
+            }
+            Node::Internal { .. } => unreachable!(),
+        };
+        Ok(Range {
+            tree: self,
+            entries,
+            index,
+            next,
+            end,
+        })
+    }
+
+    pub fn iter(&mut self) -> Result<Range<'_, Key, Value>, StorageError> {
+        self.range(..)
+    }
+}
+
+/// Iterator over a range of a `BPlusTree`, in key order
+pub struct Range<'a, Key, Value> {
+    tree: &'a mut BPlusTree<Key, Value>,
+    /// The entries of the current leaf
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    index: usize,
+    /// The leaf after the current one, 0 for none
+    next: PageId,
+    end: Bound<Vec<u8>>,
+}
+
+impl<'a, Key: KeyEncoding, Value: Encoding> Range<'a, Key, Value> {
+    fn next_entry(&mut self) -> Result<Option<(Key, Value)>, StorageError> {
+        while self.index == self.entries.len() {
+            if self.next == 0 {
+                return Ok(None);
+            }
+            match self.tree.read_node(self.next)? {
+                Node::Leaf { entries, next } => {
+                    self.entries = entries;
+                    self.next = next;
+                    self.index = 0;
+                }
+                Node::Internal { .. } => {
+                    return Err(StorageError::CorruptPage {
+                        page: self.next,
+                        reason: "leaf links to an internal node".to_string(),
+                    })
+                }
+            }
+        }
+        let (key, value) = &self.entries[self.index];
+        let in_range = match &self.end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        if !in_range {
+            self.entries.clear();
+            self.index = 0;
+            self.next = 0;
+            return Ok(None);
+        }
+        self.index += 1;
+        Ok(Some((Key::decode(key)?, Value::decode(value)?)))
+    }
+}
+
+impl<'a, Key: KeyEncoding, Value: Encoding> Iterator for Range<'a, Key, Value> {
+    type Item = Result<(Key, Value), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_path;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    fn remove_files(path: &Path) {
+        fs::remove_file(path).ok();
+        fs::remove_file(wal_path(path)).ok();
+    }
+
+    fn contents(tree: &mut BPlusTree<u64, String>) -> Vec<(u64, String)> {
+        tree.iter().unwrap().map(|entry| entry.unwrap()).collect()
+    }
+
+    /// A value long enough to make the tree a few levels deep
+    fn value_of(key: u64) -> String {
+        format!("{:0>100}", key)
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let path = temp_path("bptree-basic");
+        let mut tree: BPlusTree<String, i64> = BPlusTree::open(&path).unwrap();
+        assert_eq!(tree.insert(&"b".to_string(), &2).unwrap(), None);
+        assert_eq!(tree.insert(&"a".to_string(), &1).unwrap(), None);
+        assert_eq!(tree.insert(&"a".to_string(), &-1).unwrap(), Some(1));
+        assert_eq!(tree.get(&"a".to_string()).unwrap(), Some(-1));
+        assert_eq!(tree.get(&"c".to_string()).unwrap(), None);
+        assert_eq!(tree.remove(&"b".to_string()).unwrap(), Some(2));
+        assert_eq!(tree.remove(&"b".to_string()).unwrap(), None);
+        assert!(!tree.contains_key(&"b".to_string()).unwrap());
+        assert!(matches!(
+            tree.insert(&"x".repeat(MAX_ENTRY_LEN), &0),
+            Err(StorageError::EntryTooLarge(_))
+        ));
+        drop(tree);
+
+        let mut reopened: BPlusTree<String, i64> = BPlusTree::open(&path).unwrap();
+        assert_eq!(reopened.get(&"a".to_string()).unwrap(), Some(-1));
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_matches_btreemap() {
+        let path = temp_path("bptree-model");
+        let mut rng = StdRng::seed_from_u64(0);
+        // A small pool makes pages go back and forth to the file
+        let mut tree = BPlusTree::with_pool_capacity(&path, 8).unwrap();
+        let mut model = BTreeMap::new();
+        for _ in 0..3_000 {
+            let key = rng.gen_range(0..1_000);
+            if rng.gen_range(0..4) == 0 {
+                assert_eq!(tree.remove(&key).unwrap(), model.remove(&key));
+            } else {
+                let value = value_of(rng.gen_range(0..1_000_000));
+                assert_eq!(tree.insert(&key, &value).unwrap(), model.insert(key, value));
+            }
+        }
+        let expected: Vec<(u64, String)> = model.clone().into_iter().collect();
+        assert_eq!(contents(&mut tree), expected);
+        for (lo, hi) in [(0, 1_000), (10, 20), (500, 501), (999, 2_000)].iter() {
+            let actual: Vec<(u64, String)> = tree
+                .range(lo..hi)
+                .unwrap()
+                .map(|entry| entry.unwrap())
+                .collect();
+            let expected: Vec<(u64, String)> =
+                model.range(lo..hi).map(|(k, v)| (*k, v.clone())).collect();
+            assert_eq!(actual, expected);
+        }
+        assert!(tree.header().unwrap().page_count > 30);
+        drop(tree);
+
+        let mut reopened = BPlusTree::open(&path).unwrap();
+        assert_eq!(contents(&mut reopened), expected);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_crash_recovery() {
+        let path = temp_path("bptree-crash");
+        let mut keys: Vec<u64> = (0..300).collect();
+        keys.shuffle(&mut StdRng::seed_from_u64(1));
+        let (before, during) = keys.split_at(200);
+
+        let mut tree = BPlusTree::open(&path).unwrap();
+        for key in before {
+            tree.insert(key, &value_of(*key)).unwrap();
+        }
+        // None of the following inserts reaches the data file, only the
+        // log, and some of them split leaves
+        tree.pool.crash_after_logging = true;
+        let mut log_lens = vec![];
+        for key in during {
+            tree.insert(key, &value_of(*key)).unwrap();
+            log_lens.push(fs::metadata(wal_path(&path)).unwrap().len());
+        }
+        drop(tree);
+        let data = fs::read(&path).unwrap();
+        let log = fs::read(wal_path(&path)).unwrap();
+
+        // Cut the log at frame boundaries and in the middle of frames
+        let mut cuts = vec![0, 1, log.len() as u64];
+        for (i, len) in log_lens.iter().enumerate().step_by(7) {
+            cuts.extend([*len - 1, *len, *len + 5].iter());
+            cuts.push(if i == 0 { 0 } else { log_lens[i - 1] + 4_100 });
+        }
+        for cut in cuts {
+            let cut = cut.min(log.len() as u64) as usize;
+            fs::write(&path, &data).unwrap();
+            fs::write(wal_path(&path), &log[..cut]).unwrap();
+            let mut recovered = BPlusTree::open(&path).unwrap();
+            // Exactly the inserts whose frames survived are there
+            let survived = log_lens.iter().filter(|&&len| len as usize <= cut).count();
+            let mut expected: Vec<u64> =
+                before.iter().chain(&during[..survived]).cloned().collect();
+            expected.sort_unstable();
+            let actual: Vec<u64> = contents(&mut recovered)
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect();
+            assert_eq!(actual, expected, "log cut at {} bytes", cut);
+            assert!(fs::metadata(wal_path(&path)).unwrap().len() == 0);
+            for key in expected {
+                assert_eq!(recovered.get(&key).unwrap(), Some(value_of(key)));
+            }
+        }
+        remove_files(&path);
+    }
+}
@@ -0,0 +1,159 @@
+//! Write-ahead log of page images
+//!
+//! Every committed operation appends one frame holding the new contents
+//! of all pages it changed, and the log is synced before any of those
+//! pages is written to the data file. A crash can then leave the data
+//! file with a half-applied operation, but never without the complete
+//! frame to redo it from, and a frame torn by the crash is ignored as if
+//! the operation never happened.
+//!
+//! Frame format (all integers little-endian):
+//!
+//! ```text
+//! magic: b"WALF" | page count: u32 | (page id: u32 | page) * count | checksum: u64
+//! ```
+//!
+//! The checksum is the FNV-1a hash of everything before it in the frame.
+
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::bptree::page::{Page, PageId, PAGE_SIZE};
+
+const MAGIC: [u8; 4] = *b"WALF";
+const FRAME_HEADER_LEN: usize = 8;
+const CHECKSUM_LEN: usize = 8;
+
+/// The pages of one operation, with their ids
+pub type Frame = Vec<(PageId, Box<Page>)>;
+
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub struct Wal {
+    file: File,
+    len: u64,
+}
+
+impl Wal {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { file, len })
+    }
+
+    /// Number of bytes in the log
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Durably appends a frame with `pages`
+    pub fn append<'a, I>(&mut self, pages: I) -> io::Result<()>
+    where
+        I: ExactSizeIterator<Item = (PageId, &'a Page)>,
+    {
+        let mut frame =
+            Vec::with_capacity(FRAME_HEADER_LEN + pages.len() * (4 + PAGE_SIZE) + CHECKSUM_LEN);
+        frame.extend_from_slice(&MAGIC);
+        frame.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        for (id, page) in pages {
+            frame.extend_from_slice(&id.to_le_bytes());
+            frame.extend_from_slice(page);
+        }
+        frame.extend_from_slice(&checksum(&frame).to_le_bytes());
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.write_all(&frame)?;
+        self.file.sync_data()?;
+        self.len += frame.len() as u64;
+        Ok(())
+    }
+
+    /// The frames up to the first incomplete or corrupt one, with the
+    /// pages in every frame
+    pub fn frames(&mut self) -> io::Result<Vec<Frame>> {
+        let mut bytes = vec![];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut bytes)?;
+        let mut frames = vec![];
+        let mut rest = &bytes[..];
+        while rest.len() >= FRAME_HEADER_LEN && rest[..4] == MAGIC {
+            let count = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            let frame_len = FRAME_HEADER_LEN + count * (4 + PAGE_SIZE) + CHECKSUM_LEN;
+            if rest.len() < frame_len {
+                break;
+            }
+            let (body, stored) = rest[..frame_len].split_at(frame_len - CHECKSUM_LEN);
+            if checksum(body) != u64::from_le_bytes(stored.try_into().unwrap()) {
+                break;
+            }
+            let pages = body[FRAME_HEADER_LEN..]
+                .chunks(4 + PAGE_SIZE)
+                .map(|chunk| {
+                    let id = u32::from_le_bytes(chunk[..4].try_into().unwrap());
+                    let page: Box<Page> = Box::new(chunk[4..].try_into().unwrap());
+                    (id, page)
+                })
+                .collect();
+            frames.push(pages);
+            rest = &rest[frame_len..];
+        }
+        Ok(frames)
+    }
+
+    /// Empties the log, once all of it has reached the data file
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_path;
+
+    #[test]
+    fn test_torn_frames_ignored() {
+        let path = temp_path("wal");
+        let mut wal = Wal::open(&path).unwrap();
+        let (a, b) = ([1; PAGE_SIZE], [2; PAGE_SIZE]);
+        wal.append(vec![(3, &a), (4, &b)].into_iter()).unwrap();
+        let first_len = wal.len();
+        wal.append(vec![(3, &b)].into_iter()).unwrap();
+        let frames = wal.frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0][1].0, frames[0][1].1[0]), (4, 2));
+
+        // A bit flip breaks the checksum of the last frame
+        let mut bytes = std::fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(Wal::open(&path).unwrap().frames().unwrap().len(), 1);
+        for len in [first_len - 1, 3, 0].iter() {
+            bytes.truncate(*len as usize);
+            std::fs::write(&path, &bytes).unwrap();
+            assert!(Wal::open(&path).unwrap().frames().unwrap().is_empty());
+        }
+
+        let mut wal = Wal::open(&path).unwrap();
+        wal.truncate().unwrap();
+        assert!(wal.is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,437 @@
+extern crate dibrova;
+extern crate rand;
+
+use dibrova::bst::base::Bst;
+use dibrova::bst::lazy::LazyTreap;
+use dibrova::bst::persistent::PersistentSet;
+use dibrova::bst::simple::{SimpleBst, SimpleBstNode};
+use dibrova::btree::BTree;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: bst-benchmark [--size N] [--ops N] [--mix INSERT:FIND:DELETE]
+                     [--workload sorted|reverse|random|zipf|all] [--seed N]";
+
+/// The interface every benchmarked implementation is driven through
+trait OrderedSet {
+    fn insert(&mut self, value: u64);
+    fn find(&self, value: &u64) -> bool;
+    fn delete(&mut self, value: &u64) -> bool;
+    fn values(&self) -> Vec<u64>;
+    /// Implementation specific remarks, printed after the timings
+    fn remarks(&self) -> Option<String> {
+        None
+    }
+}
+
+impl OrderedSet for SimpleBst<SimpleBstNode<u64>> {
+    fn insert(&mut self, value: u64) {
+        Bst::insert(self, value)
+    }
+
+    fn find(&self, value: &u64) -> bool {
+        Bst::find(self, value).is_some()
+    }
+
+    fn delete(&mut self, value: &u64) -> bool {
+        Bst::delete(self, value)
+            .expect("The benchmark holds no links into the tree")
+            .is_some()
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.iter().cloned().collect()
+    }
+
+    fn remarks(&self) -> Option<String> {
+        let stats = self.stats();
+        Some(format!(
+            "height {} (optimal {}), avg leaf depth {:.1}",
+            stats.height,
+            stats.optimal_height(),
+            stats.avg_leaf_depth
+        ))
+    }
+}
+
+impl OrderedSet for BTreeSet<u64> {
+    fn insert(&mut self, value: u64) {
+        BTreeSet::insert(self, value);
+    }
+
+    fn find(&self, value: &u64) -> bool {
+        self.contains(value)
+    }
+
+    fn delete(&mut self, value: &u64) -> bool {
+        self.remove(value)
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<const FANOUT: usize> OrderedSet for BTree<u64, FANOUT> {
+    fn insert(&mut self, value: u64) {
+        BTree::insert(self, value)
+    }
+
+    fn find(&self, value: &u64) -> bool {
+        self.contains(value)
+    }
+
+    fn delete(&mut self, value: &u64) -> bool {
+        BTree::delete(self, value).is_some()
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.iter().cloned().collect()
+    }
+
+    fn remarks(&self) -> Option<String> {
+        Some(format!("fanout {}, height {}", FANOUT, self.height()))
+    }
+}
+
+/// Every update makes a new version, which replaces the old one
+impl OrderedSet for PersistentSet<u64> {
+    fn insert(&mut self, value: u64) {
+        *self = PersistentSet::insert(self, value);
+    }
+
+    fn find(&self, value: &u64) -> bool {
+        self.contains(value)
+    }
+
+    fn delete(&mut self, value: &u64) -> bool {
+        let next = self.remove(value);
+        // Removing a missing value gives back the very same version
+        let removed = !next.ptr_eq(self);
+        *self = next;
+        removed
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.iter().cloned().collect()
+    }
+}
+
+/// A set as a map to zeros, which pays for the summaries it never reads
+impl OrderedSet for LazyTreap<u64, u64> {
+    fn insert(&mut self, value: u64) {
+        LazyTreap::insert(self, value, 0);
+    }
+
+    fn find(&self, value: &u64) -> bool {
+        self.get(value).is_some()
+    }
+
+    fn delete(&mut self, value: &u64) -> bool {
+        self.remove(value).is_some()
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.iter().map(|(key, _)| *key).collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Workload {
+    Sorted,
+    Reverse,
+    Random,
+    Zipf,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Kind {
+    Insert,
+    Find,
+    Delete,
+}
+
+const KINDS: [Kind; 3] = [Kind::Insert, Kind::Find, Kind::Delete];
+
+struct Config {
+    size: usize,
+    ops: usize,
+    mix: [u32; 3],
+    workloads: Vec<Workload>,
+    seed: u64,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        size: 10_000,
+        ops: 100_000,
+        mix: [20, 70, 10],
+        workloads: vec![
+            Workload::Sorted,
+            Workload::Reverse,
+            Workload::Random,
+            Workload::Zipf,
+        ],
+        seed: 0,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        let number = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|e| format!("bad value {:?} for {}: {}", value, flag, e))
+        };
+        match flag.as_str() {
+            "--size" => config.size = number(&value)? as usize,
+            "--ops" => config.ops = number(&value)? as usize,
+            "--seed" => config.seed = number(&value)?,
+            "--mix" => {
+                let parts: Vec<&str> = value.split(':').collect();
+                if parts.len() != 3 {
+                    return Err(format!("--mix expects three ratios, got {:?}", value));
+                }
+                for (ratio, part) in config.mix.iter_mut().zip(parts) {
+                    *ratio = number(part)? as u32;
+                }
+                if config.mix.iter().sum::<u32>() == 0 {
+                    return Err("--mix ratios cannot all be zero".to_string());
+                }
+            }
+            "--workload" => {
+                config.workloads = match value.as_str() {
+                    "sorted" => vec![Workload::Sorted],
+                    "reverse" => vec![Workload::Reverse],
+                    "random" => vec![Workload::Random],
+                    "zipf" => vec![Workload::Zipf],
+                    "all" => config.workloads,
+                    _ => return Err(format!("unknown workload {:?}", value)),
+                }
+            }
+            _ => return Err(format!("unknown flag {:?}", flag)),
+        }
+    }
+    if config.size == 0 {
+        return Err("--size must be positive".to_string());
+    }
+    Ok(config)
+}
+
+/// Samples ranks `0..n` with probability proportional to `1 / (rank + 1)`
+struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize) -> Self {
+        let mut cdf = Vec::with_capacity(n);
+        let mut total = 0.0;
+        for rank in 0..n {
+            total += 1.0 / (rank + 1) as f64;
+            cdf.push(total);
+        }
+        for p in cdf.iter_mut() {
+            *p /= total;
+        }
+        Self { cdf }
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        let u: f64 = rng.gen();
+        self.cdf.partition_point(|&p| p < u).min(self.cdf.len() - 1)
+    }
+}
+
+/// Values to prefill the trees with, and the operations to time afterwards
+fn generate(config: &Config, workload: Workload) -> (Vec<u64>, Vec<(Kind, u64)>) {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    // Half of the key space is prefilled, so that finds and deletes
+    // hit in about half of the cases
+    let key_space = 2 * config.size as u64;
+    let mut prefill: Vec<u64> = (0..config.size as u64).map(|i| 2 * i).collect();
+    match workload {
+        Workload::Sorted => {}
+        Workload::Reverse => prefill.reverse(),
+        Workload::Random | Workload::Zipf => prefill.shuffle(&mut rng),
+    }
+
+    let zipf = Zipf::new(key_space as usize);
+    let total: u32 = config.mix.iter().sum();
+    let ops = (0..config.ops)
+        .map(|i| {
+            let mut pick = rng.gen_range(0..total);
+            let mut kind = Kind::Insert;
+            for (k, ratio) in KINDS.iter().zip(config.mix.iter()) {
+                if pick < *ratio {
+                    kind = *k;
+                    break;
+                }
+                pick -= ratio;
+            }
+            let key = match workload {
+                Workload::Sorted => i as u64 % key_space,
+                Workload::Reverse => key_space - 1 - i as u64 % key_space,
+                Workload::Random => rng.gen_range(0..key_space),
+                // Scatter the popular ranks over the key space, so that the
+                // hot keys do not all sit in the same corner of the tree
+                Workload::Zipf => (zipf.sample(&mut rng) as u64 * 2_654_435_761) % key_space,
+            };
+            (kind, key)
+        })
+        .collect();
+    (prefill, ops)
+}
+
+struct Measurement {
+    prefill: Duration,
+    per_kind: [(usize, Duration); 3],
+    results: Vec<bool>,
+    values: Vec<u64>,
+    remarks: Option<String>,
+}
+
+fn run<S: OrderedSet>(mut set: S, prefill: &[u64], ops: &[(Kind, u64)]) -> Measurement {
+    let start = Instant::now();
+    for value in prefill {
+        set.insert(*value);
+    }
+    let prefill_duration = start.elapsed();
+
+    let mut per_kind = [(0, Duration::new(0, 0)); 3];
+    let mut results = Vec::with_capacity(ops.len());
+    for (kind, key) in ops {
+        let start = Instant::now();
+        let result = match kind {
+            Kind::Insert => {
+                set.insert(*key);
+                true
+            }
+            Kind::Find => set.find(key),
+            Kind::Delete => set.delete(key),
+        };
+        let elapsed = start.elapsed();
+        let slot = &mut per_kind[KINDS.iter().position(|k| k == kind).unwrap()];
+        slot.0 += 1;
+        slot.1 += elapsed;
+        results.push(result);
+    }
+    Measurement {
+        prefill: prefill_duration,
+        per_kind,
+        results,
+        values: set.values(),
+        remarks: set.remarks(),
+    }
+}
+
+fn report(name: &str, m: &Measurement, prefill_len: usize) {
+    println!("  {}", name);
+    println!(
+        "    prefill: {:>10.1} ns/op {:>12.0} ops/s",
+        m.prefill.as_nanos() as f64 / prefill_len as f64,
+        prefill_len as f64 / m.prefill.as_secs_f64()
+    );
+    for (kind, (count, duration)) in KINDS.iter().zip(m.per_kind.iter()) {
+        if *count == 0 {
+            continue;
+        }
+        println!(
+            "    {:<8} {:>10.1} ns/op {:>12.0} ops/s",
+            format!("{:?}:", kind).to_lowercase(),
+            duration.as_nanos() as f64 / *count as f64,
+            *count as f64 / duration.as_secs_f64()
+        );
+    }
+    if let Some(remarks) = m.remarks.as_ref() {
+        println!("    {}", remarks);
+    }
+}
+
+/// Times looking up every key in `ops` in a `SimpleBst` against the same
+/// values frozen, which only makes sense once the tree stops changing
+fn compare_lookups(prefill: &[u64], ops: &[(Kind, u64)]) {
+    let bst: SimpleBst<SimpleBstNode<u64>> = prefill.iter().cloned().collect();
+    let keys: Vec<u64> = ops.iter().map(|(_, key)| *key).collect();
+    let time = |find: &dyn Fn(&u64) -> bool| {
+        let start = Instant::now();
+        let hits = keys.iter().filter(|key| find(key)).count();
+        (start.elapsed(), hits)
+    };
+
+    let start = Instant::now();
+    let frozen = bst.freeze();
+    let freeze_duration = start.elapsed();
+    let (bst_duration, bst_hits) = time(&|key| Bst::find(&bst, key).is_some());
+    let (frozen_duration, frozen_hits) = time(&|key| frozen.contains(key));
+    let frozen = frozen.with_prefetch(false);
+    let (no_prefetch_duration, no_prefetch_hits) = time(&|key| frozen.contains(key));
+    assert!(
+        bst_hits == frozen_hits && bst_hits == no_prefetch_hits,
+        "FrozenBst disagrees with SimpleBst"
+    );
+
+    println!("  lookups only, after freezing in {:.1?}", freeze_duration);
+    let timings: [(&str, Duration); 3] = [
+        ("SimpleBst::find", bst_duration),
+        ("FrozenBst::contains", frozen_duration),
+        ("without prefetch", no_prefetch_duration),
+    ];
+    for (name, duration) in timings.iter() {
+        println!(
+            "    {:<20} {:>10.1} ns/op {:>12.0} ops/s",
+            format!("{}:", name),
+            duration.as_nanos() as f64 / keys.len() as f64,
+            keys.len() as f64 / duration.as_secs_f64()
+        );
+    }
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    for workload in config.workloads.iter() {
+        let (prefill, ops) = generate(&config, *workload);
+        println!(
+            "{:?} workload: {} prefilled values, {} operations (insert:find:delete = {}:{}:{})",
+            workload, config.size, config.ops, config.mix[0], config.mix[1], config.mix[2]
+        );
+        let measurements = [
+            ("BTreeSet", run(BTreeSet::new(), &prefill, &ops)),
+            ("BTree<4>", run(BTree::<u64, 4>::new(), &prefill, &ops)),
+            ("BTree<16>", run(BTree::<u64, 16>::new(), &prefill, &ops)),
+            (
+                "SimpleBst",
+                run(SimpleBst::<SimpleBstNode<u64>>::new(), &prefill, &ops),
+            ),
+            ("PersistentSet", run(PersistentSet::new(), &prefill, &ops)),
+            (
+                "LazyTreap",
+                run(LazyTreap::<u64, u64>::new(), &prefill, &ops),
+            ),
+        ];
+        let (reference_name, reference) = &measurements[0];
+        for (name, m) in measurements.iter() {
+            report(name, m, prefill.len());
+            assert!(
+                m.results == reference.results && m.values == reference.values,
+                "{} disagrees with {}",
+                name,
+                reference_name
+            );
+        }
+        compare_lookups(&prefill, &ops);
+    }
+}
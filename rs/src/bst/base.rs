@@ -1,6 +1,11 @@
 use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
 use std::rc::Rc;
 
+use crate::bst::frozen::{self, FrozenBst};
+use crate::bst::handle::{Handle, HandleError};
+use crate::bst::stats::{self, TreeStats};
+
 /// A direction of a child relative to a parent
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Direction {
@@ -18,18 +23,9 @@ pub fn flip_direction(d: Direction) -> Direction {
 
 pub type Link<Node> = Rc<Node>;
 
-/// Represents the basic structure of a BST Node
-pub trait BstNode: PartialEq {
-    type Value: PartialEq + PartialOrd;
-
-    fn new(value: Self::Value) -> Self;
-
-    /// Returns the reference to the stored value
-    fn as_value(&self) -> &Self::Value;
-
-    /// Consumes the node, returning contained value
-    fn into_value(self) -> Self::Value;
-
+/// Represents the links of a node of a binary tree, whether the tree is
+/// searched by value or not
+pub trait TreeNode {
     /// Returns an `Option` with a reference to a child if present
     fn get_child(&self, direction: Direction) -> Option<Link<Self>>;
 
@@ -42,6 +38,18 @@ pub trait BstNode: PartialEq {
     /// Sets `self`'s parent
     fn set_parent(&self, parent: Option<Link<Self>>);
 
+    /// Hands updates pending for the subtree down to the children, which
+    /// has to happen before they are relinked
+    ///
+    /// Nodes without lazy updates have nothing to push.
+    fn push_down(&self) {}
+
+    /// Recomputes what the node keeps about its subtree, after its
+    /// children have been relinked
+    ///
+    /// Nodes without such augmentation have nothing to recompute.
+    fn pull_up(&self) {}
+
     /// Checks whether the node is a leaf
     fn is_leaf(&self) -> bool {
         self.get_child(Direction::Left).is_none() && self.get_child(Direction::Right).is_none()
@@ -51,6 +59,19 @@ pub trait BstNode: PartialEq {
     fn is_root(&self) -> bool {
         self.get_parent().is_none()
     }
+}
+
+/// Represents the basic structure of a BST Node
+pub trait BstNode: TreeNode + PartialEq {
+    type Value: PartialEq + PartialOrd;
+
+    fn new(value: Self::Value) -> Self;
+
+    /// Returns the reference to the stored value
+    fn as_value(&self) -> &Self::Value;
+
+    /// Consumes the node, returning contained value
+    fn into_value(self) -> Self::Value;
 
     /// Checks whether the node is a `direction` child of its parent
     fn is_child(&self, direction: Direction) -> bool {
@@ -120,24 +141,207 @@ pub trait Bst {
 
     fn insert(&mut self, value: <Self::Node as BstNode>::Value);
 
+    /// Removes `node`, which has to belong to this tree, returning its value
+    ///
+    /// Fails with `HandleError::Shared`, leaving the tree untouched, if
+    /// `node` is referenced by any other `Link` than the tree's own ones.
+    fn delete_node(
+        &mut self,
+        node: Link<Self::Node>,
+    ) -> Result<<Self::Node as BstNode>::Value, HandleError>;
+
+    /// Removes `value` from the tree, returning it if it was present
+    ///
+    /// Fails with `HandleError::Shared`, leaving the tree untouched, if the
+    /// node of `value` is referenced through a raw `Link`.
     fn delete(
         &mut self,
         value: &<Self::Node as BstNode>::Value,
-    ) -> Option<<Self::Node as BstNode>::Value>;
-
-    fn find(&self, value: &<Self::Node as BstNode>::Value) -> Option<Link<Self::Node>> {
-        let mut maybe_node = self.get_root();
-        loop {
-            match maybe_node.take() {
-                None => return None,
-                Some(node) => match node.get_direction_of_value(value) {
-                    None => return Some(node),
-                    Some(dir) => {
-                        maybe_node = node.get_child(dir);
-                    }
-                },
+    ) -> Result<Option<<Self::Node as BstNode>::Value>, HandleError> {
+        find_node(self.get_root(), value)
+            .map(|node| self.delete_node(node))
+            .transpose()
+    }
+
+    /// Removes the value `handle` refers to, returning it
+    fn delete_by_handle(
+        &mut self,
+        handle: &Handle<Self::Node>,
+    ) -> Result<<Self::Node as BstNode>::Value, HandleError> {
+        let node = handle.upgrade().ok_or(HandleError::Stale)?;
+        if !is_in_tree(self, &node) {
+            return Err(HandleError::Stale);
+        }
+        self.delete_node(node)
+    }
+
+    /// Returns a handle to the node holding `value`, if present
+    fn find(&self, value: &<Self::Node as BstNode>::Value) -> Option<Handle<Self::Node>> {
+        find_node(self.get_root(), value).map(|node| Handle::new(&node))
+    }
+
+    fn contains(&self, value: &<Self::Node as BstNode>::Value) -> bool {
+        find_node(self.get_root(), value).is_some()
+    }
+
+    /// Returns the value `handle` refers to, or `None` if it is stale
+    ///
+    /// Takes O(depth) time to make sure the node is still in the tree.
+    fn get(&self, handle: &Handle<Self::Node>) -> Option<&<Self::Node as BstNode>::Value> {
+        let node = handle.upgrade()?;
+        if is_in_tree(self, &node) {
+            Some(unsafe { value_in_tree(self, &node) })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the lowest common ancestor of `a` and `b`, or `None` if
+    /// either of them is not in the tree
+    ///
+    /// A value is its own ancestor, so the LCA of a value and anything
+    /// below it is the value itself.
+    fn lca(
+        &self,
+        a: &<Self::Node as BstNode>::Value,
+        b: &<Self::Node as BstNode>::Value,
+    ) -> Option<&<Self::Node as BstNode>::Value> {
+        let node = lca_node(self.get_root(), a, b)?;
+        Some(unsafe { value_in_tree(self, &node) })
+    }
+
+    /// Returns the values on the way from the root down to `value`, both
+    /// included, or `None` if `value` is not in the tree
+    fn path_to(
+        &self,
+        value: &<Self::Node as BstNode>::Value,
+    ) -> Option<Vec<&<Self::Node as BstNode>::Value>> {
+        let mut maybe_node = find_node(self.get_root(), value);
+        let mut path = vec![];
+        while let Some(node) = maybe_node {
+            path.push(unsafe { value_in_tree(self, &node) });
+            maybe_node = node.get_parent();
+        }
+        if path.is_empty() {
+            return None;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Returns the number of edges between `a` and `b`, or `None` if
+    /// either of them is not in the tree
+    fn distance(
+        &self,
+        a: &<Self::Node as BstNode>::Value,
+        b: &<Self::Node as BstNode>::Value,
+    ) -> Option<usize> {
+        let lca = lca_node(self.get_root(), a, b)?;
+        // Both values are below `lca`, so they are found from there
+        let depth_below = |value| {
+            let mut depth = 0;
+            let mut node = find_node(Some(lca.clone()), value)?;
+            while !Link::ptr_eq(&node, &lca) {
+                node = node.get_parent()?;
+                depth += 1;
+            }
+            Some(depth)
+        };
+        Some(depth_below(a)? + depth_below(b)?)
+    }
+
+    /// Removes the values in `range`, returning them in order
+    ///
+    /// Fails with `HandleError::Shared`, leaving the tree untouched, if
+    /// the node of any of the values is referenced through a raw `Link`.
+    fn drain_range<R>(
+        &mut self,
+        range: R,
+    ) -> Result<Vec<<Self::Node as BstNode>::Value>, HandleError>
+    where
+        R: RangeBounds<<Self::Node as BstNode>::Value>,
+        Self: Sized,
+    {
+        let mut handles = vec![];
+        let mut maybe_node = first_not_before(self.get_root(), range.start_bound());
+        while let Some(node) = maybe_node {
+            if !range.contains(node.as_value()) {
+                break;
+            }
+            handles.push(Handle::new(&node));
+            maybe_node = next_inorder(node);
+        }
+        delete_all(self, handles)
+    }
+
+    /// Removes the values in `range`, returning how many there were
+    ///
+    /// Fails like `drain_range` does.
+    fn remove_range<R>(&mut self, range: R) -> Result<usize, HandleError>
+    where
+        R: RangeBounds<<Self::Node as BstNode>::Value>,
+        Self: Sized,
+    {
+        Ok(self.drain_range(range)?.len())
+    }
+
+    /// Removes the values `predicate` does not hold for
+    ///
+    /// Fails like `drain_range` does, before removing anything.
+    fn retain<F>(&mut self, mut predicate: F) -> Result<(), HandleError>
+    where
+        F: FnMut(&<Self::Node as BstNode>::Value) -> bool,
+        Self: Sized,
+    {
+        let mut handles = vec![];
+        let mut maybe_node = self
+            .get_root()
+            .map(|root| get_extreme(root, Direction::Left));
+        while let Some(node) = maybe_node {
+            if !predicate(node.as_value()) {
+                handles.push(Handle::new(&node));
             }
+            maybe_node = next_inorder(node);
         }
+        delete_all(self, handles)?;
+        Ok(())
+    }
+
+    /// Returns the smallest value, or `None` if the tree is empty
+    fn peek_min(&self) -> Option<&<Self::Node as BstNode>::Value> {
+        peek_extreme(self, Direction::Left)
+    }
+
+    /// Returns the largest value, or `None` if the tree is empty
+    fn peek_max(&self) -> Option<&<Self::Node as BstNode>::Value> {
+        peek_extreme(self, Direction::Right)
+    }
+
+    /// Removes the smallest value, returning it, or `None` if the tree is
+    /// empty
+    ///
+    /// Unlike `delete`, this walks down to the value once instead of
+    /// searching for it again. Fails like `delete` does.
+    fn pop_min(&mut self) -> Result<Option<<Self::Node as BstNode>::Value>, HandleError> {
+        pop_extreme(self, Direction::Left)
+    }
+
+    /// Removes the largest value, returning it, like `pop_min` does
+    fn pop_max(&mut self) -> Result<Option<<Self::Node as BstNode>::Value>, HandleError> {
+        pop_extreme(self, Direction::Right)
+    }
+
+    /// Computes the shape statistics of the tree
+    fn stats(&self) -> TreeStats {
+        stats::compute(self.get_root())
+    }
+
+    /// Copies the values into a static tree, which is faster to search
+    fn freeze(&self) -> FrozenBst<<Self::Node as BstNode>::Value>
+    where
+        <Self::Node as BstNode>::Value: Clone,
+    {
+        frozen::freeze(self)
     }
 
     fn iter(&self) -> BstIterator<Self> {
@@ -151,7 +355,338 @@ pub trait Bst {
     }
 }
 
-pub fn get_extreme<Node: BstNode>(mut node: Link<Node>, direction: Direction) -> Link<Node> {
+/// Descends from `root` to the node holding `value`, if any
+pub(crate) fn find_node<Node: BstNode>(
+    root: Option<Link<Node>>,
+    value: &Node::Value,
+) -> Option<Link<Node>> {
+    let mut maybe_node = root;
+    loop {
+        match maybe_node.take() {
+            None => return None,
+            Some(node) => match node.get_direction_of_value(value) {
+                None => return Some(node),
+                Some(dir) => {
+                    maybe_node = node.get_child(dir);
+                }
+            },
+        }
+    }
+}
+
+/// Descends from `root` while `a` and `b` lie on the same side, to the
+/// node separating them, if both of them are in the tree
+pub(crate) fn lca_node<Node: BstNode>(
+    root: Option<Link<Node>>,
+    a: &Node::Value,
+    b: &Node::Value,
+) -> Option<Link<Node>> {
+    let mut node = root?;
+    loop {
+        match (
+            node.get_direction_of_value(a),
+            node.get_direction_of_value(b),
+        ) {
+            (Some(direction_a), Some(direction_b)) if direction_a == direction_b => {
+                node = node.get_child(direction_a)?;
+            }
+            _ => break,
+        }
+    }
+    find_node(Some(node.clone()), a)?;
+    find_node(Some(node.clone()), b)?;
+    Some(node)
+}
+
+/// Deletes the nodes of `handles`, which are only weak references, so
+/// that no node counts as shared because of the others waiting in line
+fn delete_all<Tree: Bst>(
+    tree: &mut Tree,
+    handles: Vec<Handle<Tree::Node>>,
+) -> Result<Vec<<Tree::Node as BstNode>::Value>, HandleError> {
+    // Checking all the nodes first keeps the tree whole if one is shared
+    for handle in handles.iter() {
+        if let Some(node) = handle.upgrade() {
+            check_unshared(&node)?;
+        }
+    }
+    handles
+        .iter()
+        .map(|handle| tree.delete_by_handle(handle))
+        .collect()
+}
+
+fn pop_extreme<Tree: Bst + ?Sized>(
+    tree: &mut Tree,
+    direction: Direction,
+) -> Result<Option<<Tree::Node as BstNode>::Value>, HandleError> {
+    match tree.get_root() {
+        Some(root) => tree.delete_node(get_extreme(root, direction)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Descends from `root` to the first node, whose value satisfies `start`
+pub(crate) fn first_not_before<Node: BstNode>(
+    root: Option<Link<Node>>,
+    start: Bound<&Node::Value>,
+) -> Option<Link<Node>> {
+    let mut maybe_node = root;
+    let mut found = None;
+    while let Some(node) = maybe_node {
+        let satisfies = match start {
+            Bound::Included(start) => node.as_value() >= start,
+            Bound::Excluded(start) => node.as_value() > start,
+            Bound::Unbounded => true,
+        };
+        if satisfies {
+            maybe_node = node.get_child(Direction::Left);
+            found = Some(node);
+        } else {
+            maybe_node = node.get_child(Direction::Right);
+        }
+    }
+    found
+}
+
+/// Checks that `node` is referenced only by the caller's `Link` and by
+/// the tree itself, so that unlinking it makes the caller the only owner
+///
+/// Besides the caller, a node is owned by its parent (or the root slot)
+/// and by each of its children through their parent pointers. Anything
+/// beyond that is an outstanding `Link`, which would keep the node alive
+/// after the deletion, so it has to be checked before the tree is touched.
+pub(crate) fn check_unshared<Node: BstNode>(node: &Link<Node>) -> Result<(), HandleError> {
+    let children = [Direction::Left, Direction::Right]
+        .iter()
+        .filter(|&&direction| node.get_child(direction).is_some())
+        .count();
+    if Rc::strong_count(node) > 2 + children {
+        Err(HandleError::Shared)
+    } else {
+        Ok(())
+    }
+}
+
+/// Links `child` into the `direction` slot of `parent`, both ways
+pub(crate) fn link<Node: TreeNode>(
+    parent: &Link<Node>,
+    direction: Direction,
+    child: Option<Link<Node>>,
+) {
+    if let Some(child) = child.as_ref() {
+        child.set_parent(Some(parent.clone()));
+    }
+    parent.set_child(direction, child);
+}
+
+/// The side of `parent` that `child` hangs on
+pub(crate) fn child_direction<Node: TreeNode>(
+    parent: &Link<Node>,
+    child: &Link<Node>,
+) -> Direction {
+    if parent
+        .get_child(Direction::Left)
+        .is_some_and(|left_child| Link::ptr_eq(&left_child, child))
+    {
+        Direction::Left
+    } else {
+        Direction::Right
+    }
+}
+
+/// Rotates `node` up into the place of its parent, which becomes its
+/// child on the returned side
+///
+/// Pending updates are pushed down from both nodes before anything is
+/// relinked, and both are pulled up afterwards, the former parent first.
+/// The ancestors keep their subtrees, so they need no pulling; if `node`
+/// becomes the root, the caller has to store it as such.
+pub(crate) fn rotate_up<Node: TreeNode>(node: &Link<Node>) -> Direction {
+    let parent = node.get_parent().expect("Only a child can be rotated up");
+    parent.push_down();
+    node.push_down();
+    let direction = flip_direction(child_direction(&parent, node));
+    link(
+        &parent,
+        flip_direction(direction),
+        node.get_child(direction),
+    );
+    match parent.get_parent() {
+        Some(grandparent) => {
+            let side = child_direction(&grandparent, &parent);
+            link(&grandparent, side, Some(node.clone()));
+        }
+        None => node.set_parent(None),
+    }
+    link(node, direction, Some(parent.clone()));
+    parent.pull_up();
+    node.pull_up();
+    direction
+}
+
+/// Rotates `node` down until it is a leaf, lifting the child of the
+/// higher `priority` every time, which keeps a heap order of priorities,
+/// and returns the child that took the place of `node`, if any
+///
+/// Every lifted child is passed to `on_rotation` with the side `node`
+/// went to. If `node` was the root, the returned child is the new one.
+pub(crate) fn sink_to_leaf<Node: TreeNode, Priority: PartialOrd>(
+    node: &Link<Node>,
+    priority: impl Fn(&Node) -> Priority,
+    mut on_rotation: impl FnMut(&Link<Node>, Direction),
+) -> Option<Link<Node>> {
+    let mut replacement = None;
+    loop {
+        let child = match (
+            node.get_child(Direction::Left),
+            node.get_child(Direction::Right),
+        ) {
+            (None, None) => return replacement,
+            (Some(child), None) | (None, Some(child)) => child,
+            (Some(left_child), Some(right_child)) => {
+                if priority(&left_child) > priority(&right_child) {
+                    left_child
+                } else {
+                    right_child
+                }
+            }
+        };
+        let direction = rotate_up(&child);
+        on_rotation(&child, direction);
+        replacement.get_or_insert(child);
+    }
+}
+
+/// Detaches the `direction` child of `node`, returning it as a tree of
+/// its own
+pub(crate) fn take_child<Node: TreeNode>(
+    node: &Link<Node>,
+    direction: Direction,
+) -> Option<Link<Node>> {
+    let child = node.get_child(direction);
+    if let Some(child) = child.as_ref() {
+        child.set_parent(None);
+    }
+    node.set_child(direction, None);
+    child
+}
+
+/// Splits the treap `tree` into its nodes, for which `goes_left` holds,
+/// and the rest, returning both as trees of their own
+///
+/// `goes_left` has to hold for a prefix of the nodes in order. It is
+/// called on the nodes along a single path down, top to bottom, each with
+/// its pending updates pushed, so it may keep track of a position on the
+/// way. Touches O(log n) nodes in expectation and rotates none.
+pub(crate) fn split_by<Node: TreeNode>(
+    tree: Option<Link<Node>>,
+    goes_left: &mut impl FnMut(&Link<Node>) -> bool,
+) -> (Option<Link<Node>>, Option<Link<Node>>) {
+    match tree {
+        None => (None, None),
+        Some(node) => {
+            node.push_down();
+            if goes_left(&node) {
+                let (left, right) = split_by(take_child(&node, Direction::Right), goes_left);
+                link(&node, Direction::Right, left);
+                node.pull_up();
+                (Some(node), right)
+            } else {
+                let (left, right) = split_by(take_child(&node, Direction::Left), goes_left);
+                link(&node, Direction::Left, right);
+                node.pull_up();
+                (left, Some(node))
+            }
+        }
+    }
+}
+
+/// Concatenates the treaps `left` and `right`, keeping the heap order of
+/// `priority`
+pub(crate) fn merge_by<Node: TreeNode, Priority: PartialOrd>(
+    left: Option<Link<Node>>,
+    right: Option<Link<Node>>,
+    priority: &impl Fn(&Node) -> Priority,
+) -> Option<Link<Node>> {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree,
+        (Some(left), Some(right)) => {
+            if priority(&left) > priority(&right) {
+                left.push_down();
+                let merged = merge_by(take_child(&left, Direction::Right), Some(right), priority);
+                link(&left, Direction::Right, merged);
+                left.pull_up();
+                Some(left)
+            } else {
+                right.push_down();
+                let merged = merge_by(Some(left), take_child(&right, Direction::Left), priority);
+                link(&right, Direction::Left, merged);
+                right.pull_up();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// Pulls up `maybe_node` and all of its ancestors, bottom-up
+pub(crate) fn pull_path<Node: TreeNode>(mut maybe_node: Option<Link<Node>>) {
+    while let Some(node) = maybe_node {
+        node.pull_up();
+        maybe_node = node.get_parent();
+    }
+}
+
+/// Unlinks the nodes under `root` one by one
+///
+/// Parent links make every tree a reference cycle, so dropping the root
+/// alone would free nothing. Goes without recursion, so that degenerate
+/// trees do not overflow the stack.
+pub(crate) fn dismantle<Node: TreeNode>(root: Option<Link<Node>>) {
+    let mut stack: Vec<Link<Node>> = root.into_iter().collect();
+    while let Some(node) = stack.pop() {
+        node.set_parent(None);
+        for &direction in [Direction::Left, Direction::Right].iter() {
+            if let Some(child) = node.get_child(direction) {
+                node.set_child(direction, None);
+                stack.push(child);
+            }
+        }
+    }
+}
+
+/// Checks whether `node` is reachable from the root of `tree`
+pub(crate) fn is_in_tree<Tree: Bst + ?Sized>(tree: &Tree, node: &Link<Tree::Node>) -> bool {
+    let mut top = node.clone();
+    while let Some(parent) = top.get_parent() {
+        top = parent;
+    }
+    tree.get_root()
+        .is_some_and(|root| Link::ptr_eq(&root, &top))
+}
+
+fn peek_extreme<Tree: Bst + ?Sized>(
+    tree: &Tree,
+    direction: Direction,
+) -> Option<&<Tree::Node as BstNode>::Value> {
+    let node = get_extreme(tree.get_root()?, direction);
+    Some(unsafe { value_in_tree(tree, &node) })
+}
+
+/// Borrows the value of `node` for as long as `tree` is borrowed
+///
+/// # Safety
+///
+/// `node` has to be in `tree`, so that the tree keeps it alive for as
+/// long as it is borrowed.
+pub(crate) unsafe fn value_in_tree<'a, Tree: Bst + ?Sized>(
+    _tree: &'a Tree,
+    node: &Link<Tree::Node>,
+) -> &'a <Tree::Node as BstNode>::Value {
+    &*(node.as_value() as *const _)
+}
+
+pub fn get_extreme<Node: TreeNode>(mut node: Link<Node>, direction: Direction) -> Link<Node> {
     loop {
         match node.clone().get_child(direction) {
             Some(child) => {
@@ -163,16 +698,25 @@ pub fn get_extreme<Node: BstNode>(mut node: Link<Node>, direction: Direction) ->
 }
 
 pub fn next_inorder<Node: BstNode>(node: Link<Node>) -> Option<Link<Node>> {
+    step_inorder(node, Direction::Right)
+}
+
+pub fn prev_inorder<Node: BstNode>(node: Link<Node>) -> Option<Link<Node>> {
+    step_inorder(node, Direction::Left)
+}
+
+/// Finds the in-order neighbour of `node` on the `direction` side
+fn step_inorder<Node: BstNode>(node: Link<Node>, direction: Direction) -> Option<Link<Node>> {
     node.clone()
-        .get_child(Direction::Right)
-        .map(|right_subtree_root| get_extreme(right_subtree_root, Direction::Left))
+        .get_child(direction)
+        .map(|subtree_root| get_extreme(subtree_root, flip_direction(direction)))
         .or_else(|| {
             let mut node = node;
-            while node.is_child(Direction::Right) {
+            while node.is_child(direction) {
                 node = node.get_parent().unwrap();
             }
 
-            if node.is_child(Direction::Left) {
+            if node.is_child(flip_direction(direction)) {
                 node.get_parent()
             } else {
                 None
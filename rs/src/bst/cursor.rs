@@ -0,0 +1,348 @@
+use crate::bst::base::{
+    find_node, get_extreme, next_inorder, prev_inorder, Bst, BstNode, Direction, Link, TreeNode,
+};
+use crate::bst::handle::{Handle, HandleError};
+
+/// A read-only position in a tree, which can move between nodes
+///
+/// Besides pointing at a node, a cursor can point at nothing, e.g. after
+/// moving past either end. From there `move_next` wraps around to the
+/// smallest value and `move_prev` to the largest one.
+pub struct Cursor<'a, Tree: Bst + ?Sized> {
+    tree: &'a Tree,
+    current: Option<Link<Tree::Node>>,
+}
+
+/// A position in a tree, which can move between nodes and edit the tree
+///
+/// Navigation works as in `Cursor`.
+pub struct CursorMut<'a, Tree: Bst + ?Sized> {
+    tree: &'a mut Tree,
+    current: Option<Link<Tree::Node>>,
+}
+
+fn extreme_of<Tree: Bst + ?Sized>(tree: &Tree, direction: Direction) -> Option<Link<Tree::Node>> {
+    tree.get_root().map(|root| get_extreme(root, direction))
+}
+
+fn step<Tree: Bst + ?Sized>(
+    tree: &Tree,
+    current: Option<Link<Tree::Node>>,
+    direction: Direction,
+) -> Option<Link<Tree::Node>> {
+    match (current, direction) {
+        (Some(node), Direction::Right) => next_inorder(node),
+        (Some(node), Direction::Left) => prev_inorder(node),
+        (None, Direction::Right) => extreme_of(tree, Direction::Left),
+        (None, Direction::Left) => extreme_of(tree, Direction::Right),
+    }
+}
+
+// Navigation is identical for both cursor types
+macro_rules! impl_cursor_navigation {
+    ($cursor:ident) => {
+        impl<'a, Tree: Bst + ?Sized> $cursor<'a, Tree> {
+            /// Returns the value under the cursor, if any
+            pub fn current(&self) -> Option<&<Tree::Node as BstNode>::Value> {
+                self.current.as_ref().map(|node| node.as_value())
+            }
+
+            /// Moves to the next value in order
+            pub fn move_next(&mut self) {
+                self.current = step(&*self.tree, self.current.take(), Direction::Right);
+            }
+
+            /// Moves to the previous value in order
+            pub fn move_prev(&mut self) {
+                self.current = step(&*self.tree, self.current.take(), Direction::Left);
+            }
+
+            /// Moves to the parent node, or to nothing from the root
+            pub fn move_parent(&mut self) {
+                self.current = self.current.take().and_then(|node| node.get_parent());
+            }
+
+            /// Moves to the `direction` child, or to nothing from a node without one
+            pub fn move_child(&mut self, direction: Direction) {
+                self.current = self
+                    .current
+                    .take()
+                    .and_then(|node| node.get_child(direction));
+            }
+        }
+    };
+}
+
+impl_cursor_navigation!(Cursor);
+impl_cursor_navigation!(CursorMut);
+
+impl<'a, Tree: Bst + ?Sized> Cursor<'a, Tree> {
+    /// A cursor at the root of `tree`
+    pub fn root(tree: &'a Tree) -> Self {
+        Self {
+            current: tree.get_root(),
+            tree,
+        }
+    }
+
+    /// A cursor at the smallest value of `tree`
+    pub fn front(tree: &'a Tree) -> Self {
+        Self {
+            current: extreme_of(tree, Direction::Left),
+            tree,
+        }
+    }
+
+    /// A cursor at the largest value of `tree`
+    pub fn back(tree: &'a Tree) -> Self {
+        Self {
+            current: extreme_of(tree, Direction::Right),
+            tree,
+        }
+    }
+
+    /// A cursor at `value`, or at nothing if `tree` does not hold it
+    pub fn find(tree: &'a Tree, value: &<Tree::Node as BstNode>::Value) -> Self {
+        Self {
+            current: find_node(tree.get_root(), value),
+            tree,
+        }
+    }
+}
+
+impl<'a, Tree: Bst + ?Sized> CursorMut<'a, Tree> {
+    /// A cursor at the root of `tree`
+    pub fn root(tree: &'a mut Tree) -> Self {
+        Self {
+            current: tree.get_root(),
+            tree,
+        }
+    }
+
+    /// A cursor at the smallest value of `tree`
+    pub fn front(tree: &'a mut Tree) -> Self {
+        Self {
+            current: extreme_of(tree, Direction::Left),
+            tree,
+        }
+    }
+
+    /// A cursor at the largest value of `tree`
+    pub fn back(tree: &'a mut Tree) -> Self {
+        Self {
+            current: extreme_of(tree, Direction::Right),
+            tree,
+        }
+    }
+
+    /// A cursor at `value`, or at nothing if `tree` does not hold it
+    pub fn find(tree: &'a mut Tree, value: &<Tree::Node as BstNode>::Value) -> Self {
+        Self {
+            current: find_node(tree.get_root(), value),
+            tree,
+        }
+    }
+
+    /// Removes the value under the cursor and moves to the next one
+    ///
+    /// Returns `None` if the cursor points at nothing. Fails without
+    /// moving if the node under it cannot be deleted (see
+    /// `Bst::delete_node`).
+    pub fn remove_current(
+        &mut self,
+    ) -> Result<Option<<Tree::Node as BstNode>::Value>, HandleError> {
+        let node = match self.current.take() {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        let next = next_inorder(node.clone());
+        // Holding on to another `Link` would make the node undeletable
+        let handle = Handle::new(&node);
+        match self.tree.delete_node(node) {
+            Ok(value) => {
+                self.current = next;
+                Ok(Some(value))
+            }
+            Err(error) => {
+                self.current = handle.upgrade();
+                Err(error)
+            }
+        }
+    }
+
+    /// Inserts `value` right before the value under the cursor, or at the
+    /// very end if the cursor points at nothing
+    ///
+    /// Only possible if `value` falls between the current value and its
+    /// predecessor, otherwise `value` is handed back. The cursor stays
+    /// where it is.
+    pub fn insert_before(
+        &mut self,
+        value: <Tree::Node as BstNode>::Value,
+    ) -> Result<(), <Tree::Node as BstNode>::Value> {
+        let prev = step(&*self.tree, self.current.clone(), Direction::Left);
+        self.insert_between(prev, self.current.clone(), value)
+    }
+
+    /// Inserts `value` right after the value under the cursor, or at the
+    /// very beginning if the cursor points at nothing
+    ///
+    /// Only possible if `value` falls between the current value and its
+    /// successor, otherwise `value` is handed back. The cursor stays
+    /// where it is.
+    pub fn insert_after(
+        &mut self,
+        value: <Tree::Node as BstNode>::Value,
+    ) -> Result<(), <Tree::Node as BstNode>::Value> {
+        let next = step(&*self.tree, self.current.clone(), Direction::Right);
+        self.insert_between(self.current.clone(), next, value)
+    }
+
+    fn insert_between(
+        &mut self,
+        lower: Option<Link<Tree::Node>>,
+        upper: Option<Link<Tree::Node>>,
+        value: <Tree::Node as BstNode>::Value,
+    ) -> Result<(), <Tree::Node as BstNode>::Value> {
+        let above_lower = lower.is_none_or(|lower| lower.as_value() < &value);
+        let below_upper = upper.is_none_or(|upper| &value < upper.as_value());
+        if !(above_lower && below_upper) {
+            return Err(value);
+        }
+        // Going through `insert` rather than linking the node in place
+        // keeps whatever invariants the tree maintains on insertion
+        self.tree.insert(value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+    fn build(values: Vec<u32>) -> SimpleBst<SimpleBstNode<u32>> {
+        values.into_iter().collect()
+    }
+
+    #[test]
+    fn test_cursor_navigation() {
+        //     4
+        //   /   \
+        //  2     6
+        //   \
+        //    3
+        let bst = build(vec![4, 2, 6, 3]);
+        let mut cursor = Cursor::front(&bst);
+        assert_eq!(cursor.current(), Some(&2));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&4));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&6));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&2));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&6));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&4));
+
+        let mut cursor = Cursor::find(&bst, &3);
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_parent();
+        assert_eq!(cursor.current(), Some(&2));
+        cursor.move_parent();
+        assert_eq!(cursor.current(), Some(&4));
+        cursor.move_parent();
+        assert_eq!(cursor.current(), None);
+
+        let mut cursor = Cursor::root(&bst);
+        cursor.move_child(Direction::Left);
+        assert_eq!(cursor.current(), Some(&2));
+        cursor.move_child(Direction::Right);
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_child(Direction::Left);
+        assert_eq!(cursor.current(), None);
+
+        assert_eq!(Cursor::back(&bst).current(), Some(&6));
+        assert_eq!(Cursor::find(&bst, &5).current(), None);
+        let empty = build(vec![]);
+        let mut cursor = Cursor::front(&empty);
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+    }
+
+    #[test]
+    fn test_cursor_remove_current() {
+        let mut bst = build(vec![5, 2, 8, 1, 3, 7, 9, 4]);
+        {
+            // Remove every other value, starting at 2
+            let mut cursor = CursorMut::find(&mut bst, &2);
+            assert_eq!(cursor.remove_current(), Ok(Some(2)));
+            assert_eq!(cursor.current(), Some(&3));
+            cursor.move_next();
+            assert_eq!(cursor.remove_current(), Ok(Some(4)));
+            cursor.move_next();
+            assert_eq!(cursor.remove_current(), Ok(Some(7)));
+            cursor.move_next();
+            assert_eq!(cursor.remove_current(), Ok(Some(9)));
+            assert_eq!(cursor.current(), None);
+            assert_eq!(cursor.remove_current(), Ok(None));
+        }
+        let v: Vec<&u32> = bst.iter().collect();
+        assert_eq!(v, [&1, &3, &5, &8]);
+
+        let mut cursor = CursorMut::back(&mut bst);
+        assert_eq!(cursor.remove_current(), Ok(Some(8)));
+        assert_eq!(cursor.current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), Ok(Some(5)));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), Ok(Some(1)));
+        assert_eq!(cursor.current(), Some(&3));
+        assert_eq!(cursor.remove_current(), Ok(Some(3)));
+        assert!(bst.get_root().is_none());
+    }
+
+    #[test]
+    fn test_cursor_insert() {
+        let mut bst = build(vec![10, 20, 30]);
+        {
+            let mut cursor = CursorMut::find(&mut bst, &20);
+            assert_eq!(cursor.insert_before(15), Ok(()));
+            // 15 is the predecessor of 20 now
+            assert_eq!(cursor.insert_before(12), Err(12));
+            assert_eq!(cursor.insert_before(17), Ok(()));
+            assert_eq!(cursor.insert_before(20), Err(20));
+            assert_eq!(cursor.insert_after(25), Ok(()));
+            assert_eq!(cursor.insert_after(27), Err(27));
+            assert_eq!(cursor.current(), Some(&20));
+            cursor.move_prev();
+            assert_eq!(cursor.current(), Some(&17));
+        }
+        {
+            // At nothing, "before" is the end and "after" is the beginning
+            let mut cursor = CursorMut::back(&mut bst);
+            cursor.move_next();
+            assert_eq!(cursor.insert_before(40), Ok(()));
+            assert_eq!(cursor.insert_before(35), Err(35));
+            assert_eq!(cursor.insert_after(5), Ok(()));
+            assert_eq!(cursor.insert_after(7), Err(7));
+        }
+        let v: Vec<&u32> = bst.iter().collect();
+        assert_eq!(v, [&5, &10, &15, &17, &20, &25, &30, &40]);
+
+        let mut empty = build(vec![]);
+        let mut cursor = CursorMut::root(&mut empty);
+        assert_eq!(cursor.insert_after(1), Ok(()));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&1));
+    }
+}
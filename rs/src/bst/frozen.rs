@@ -0,0 +1,285 @@
+//! A read-only search tree stored in a single array in Eytzinger order
+//!
+//! The values are laid out level by level, like in a binary heap, so the
+//! children of the node at 1-based index `k` are at `2k` and `2k + 1`. A
+//! search touches one array slot per level and the top levels share a few
+//! cache lines, which makes lookups much cheaper than chasing `Link`s.
+
+use std::ops::{Bound, RangeBounds};
+
+use crate::bst::base::{Bst, BstNode};
+
+/// A static search tree, as built by `Bst::freeze`
+pub struct FrozenBst<T> {
+    /// The values in Eytzinger order; slot `k - 1` holds node `k`
+    values: Vec<T>,
+    prefetch: bool,
+}
+
+/// The in-order successor of node `k` in a tree of `len` nodes, or 0
+/// after the last one
+fn next_inorder(mut k: usize, len: usize) -> usize {
+    if 2 * k < len {
+        k = 2 * k + 1;
+        while 2 * k <= len {
+            k *= 2;
+        }
+        k
+    } else {
+        // Climb while `k` is a right child, then once more
+        k >> (k.trailing_ones() + 1)
+    }
+}
+
+impl<T: PartialOrd> FrozenBst<T> {
+    /// Builds the tree out of `sorted` values
+    ///
+    /// The values have to be sorted and free of duplicates.
+    pub fn from_sorted(sorted: Vec<T>) -> Self {
+        let len = sorted.len();
+        let mut slots: Vec<Option<T>> = (0..len).map(|_| None).collect();
+        // Visiting the nodes in order hands out the values in order
+        let mut k = if len == 0 { 0 } else { 1 };
+        while 2 * k <= len && k > 0 {
+            k *= 2;
+        }
+        for value in sorted {
+            slots[k - 1] = Some(value);
+            k = next_inorder(k, len);
+        }
+        Self {
+            values: slots
+                .into_iter()
+                .map(|slot| slot.expect("Every node is visited once"))
+                .collect(),
+            prefetch: true,
+        }
+    }
+
+    /// Enables or disables prefetching of the nodes a few levels below
+    /// the current one during searches, which is on by default
+    pub fn with_prefetch(mut self, prefetch: bool) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[inline]
+    fn prefetch(&self, k: usize) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            // The 16 descendants 4 levels down are next to each other
+            if self.prefetch && 16 * k <= self.values.len() {
+                use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+                // Prefetching is only a hint and the address is in bounds
+                unsafe {
+                    _mm_prefetch(
+                        self.values.as_ptr().add(16 * k - 1) as *const i8,
+                        _MM_HINT_T0,
+                    )
+                };
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = k;
+    }
+
+    /// The first node, for which `goes_right` does not hold, or 0 if it
+    /// holds for all of them
+    ///
+    /// `goes_right` has to hold for a prefix of the values.
+    fn partition_point<F: Fn(&T) -> bool>(&self, goes_right: F) -> usize {
+        let len = self.values.len();
+        let mut k = 1;
+        while k <= len {
+            self.prefetch(k);
+            k = 2 * k + goes_right(&self.values[k - 1]) as usize;
+        }
+        // Undo the right turns after the last left one, and that one too
+        k >> (k.trailing_ones() + 1)
+    }
+
+    fn get(&self, k: usize) -> Option<&T> {
+        if k == 0 {
+            None
+        } else {
+            Some(&self.values[k - 1])
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.lower_bound(value).is_some_and(|found| found == value)
+    }
+
+    /// The smallest value which is not less than `value`
+    pub fn lower_bound(&self, value: &T) -> Option<&T> {
+        self.get(self.partition_point(|v| v < value))
+    }
+
+    /// The smallest value which is greater than `value`
+    pub fn upper_bound(&self, value: &T) -> Option<&T> {
+        self.get(self.partition_point(|v| v <= value))
+    }
+
+    /// Iterates over the values in `range` in order
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T> {
+        let next = match range.start_bound() {
+            Bound::Included(start) => self.partition_point(|v| v < start),
+            Bound::Excluded(start) => self.partition_point(|v| v <= start),
+            Bound::Unbounded => self.partition_point(|_| false),
+        };
+        // Searching for the end too keeps the iterator free of comparisons
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.partition_point(|v| v <= end),
+            Bound::Excluded(end) => self.partition_point(|v| v < end),
+            Bound::Unbounded => 0,
+        };
+        // A reversed range has its end in order before its start, and
+        // the iterator would never reach it
+        let empty = next == 0 || (end != 0 && self.values[end - 1] <= self.values[next - 1]);
+        Range {
+            tree: self,
+            next: if empty { 0 } else { next },
+            end,
+        }
+    }
+
+    pub fn iter(&self) -> Range<'_, T> {
+        self.range(..)
+    }
+
+    /// Converts back into a mutable tree
+    ///
+    /// Inserting in Eytzinger order makes any unbalanced tree, such as
+    /// `SimpleBst`, come out perfectly balanced.
+    pub fn thaw<Tree>(self) -> Tree
+    where
+        Tree: Bst + Default,
+        Tree::Node: BstNode<Value = T>,
+    {
+        let mut tree = Tree::default();
+        for value in self.values {
+            tree.insert(value);
+        }
+        tree
+    }
+}
+
+/// In-order iterator over a range of a `FrozenBst`
+pub struct Range<'a, T> {
+    tree: &'a FrozenBst<T>,
+    /// The next node to yield, 0 when done
+    next: usize,
+    /// The node right after the range, 0 if it goes to the end
+    end: usize,
+}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == 0 {
+            return None;
+        }
+        let value = &self.tree.values[self.next - 1];
+        self.next = next_inorder(self.next, self.tree.values.len());
+        if self.next == self.end {
+            self.next = 0;
+        }
+        Some(value)
+    }
+}
+
+/// Values in the middle of the tree are only reachable through the nodes
+/// above them, so freezing needs an in-order walk of the tree
+pub fn freeze<Tree: Bst + ?Sized>(tree: &Tree) -> FrozenBst<<Tree::Node as BstNode>::Value>
+where
+    <Tree::Node as BstNode>::Value: Clone,
+{
+    FrozenBst::from_sorted(tree.iter().cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+    fn frozen(values: Vec<u32>) -> FrozenBst<u32> {
+        let bst: SimpleBst<SimpleBstNode<u32>> = values.into_iter().collect();
+        bst.freeze()
+    }
+
+    #[test]
+    fn test_layout() {
+        //       4
+        //     /   \
+        //    2     6
+        //   / \   /
+        //  1   3 5
+        let tree = frozen(vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(tree.values, [4, 2, 6, 1, 3, 5]);
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+        assert!(frozen(vec![]).iter().next().is_none());
+        assert_eq!(frozen(vec![7]).values, [7]);
+    }
+
+    #[test]
+    fn test_queries() {
+        let tree = frozen((0..100).map(|v| 2 * v).collect());
+        for v in 0..200 {
+            assert_eq!(tree.contains(&v), v % 2 == 0);
+            assert_eq!(
+                tree.lower_bound(&v),
+                Some(&(v + v % 2)).filter(|&&b| b < 200)
+            );
+            assert_eq!(
+                tree.upper_bound(&v),
+                Some(&(v + 2 - v % 2)).filter(|&&b| b < 200)
+            );
+        }
+        let without_prefetch = tree.with_prefetch(false);
+        assert!(without_prefetch.contains(&42) && !without_prefetch.contains(&43));
+    }
+
+    #[test]
+    fn test_range() {
+        let values: Vec<u32> = (0..50).map(|v| 3 * v).collect();
+        let tree = frozen(values.clone());
+        let collect = |range: Range<u32>| range.cloned().collect::<Vec<u32>>();
+        let expected = |lo: u32, hi: u32| {
+            values
+                .iter()
+                .cloned()
+                .filter(|v| lo <= *v && *v < hi)
+                .collect::<Vec<u32>>()
+        };
+        for lo in 0..160 {
+            for hi in 0..160 {
+                assert_eq!(collect(tree.range(lo..hi)), expected(lo, hi));
+                assert_eq!(collect(tree.range(lo..=hi)), expected(lo, hi + 1));
+            }
+            assert_eq!(collect(tree.range(lo..)), expected(lo, 1_000));
+            assert_eq!(collect(tree.range(..=lo)), expected(0, lo + 1));
+            assert_eq!(
+                collect(tree.range((Bound::Excluded(lo), Bound::Unbounded))),
+                expected(lo + 1, 1_000)
+            );
+        }
+    }
+
+    #[test]
+    fn test_thaw() {
+        // A sorted insertion order makes a degenerate tree
+        let tree = frozen((0..1_000).collect());
+        let thawed: SimpleBst<SimpleBstNode<u32>> = tree.thaw();
+        assert_eq!(thawed.stats().height, 10);
+        assert!(thawed.iter().cloned().eq(0..1_000));
+    }
+}
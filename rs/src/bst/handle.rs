@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use crate::bst::base::Link;
+
+/// An opaque reference to a node of a tree, as returned by `Bst::find`
+///
+/// A handle does not own the node, so it never keeps a deleted value
+/// alive and never stands in the way of a deletion. Once its node is
+/// deleted, the handle goes stale and using it results in
+/// `HandleError::Stale`.
+pub struct Handle<Node> {
+    node: Weak<Node>,
+}
+
+impl<Node> Handle<Node> {
+    pub(crate) fn new(node: &Link<Node>) -> Self {
+        Self {
+            node: Rc::downgrade(node),
+        }
+    }
+
+    /// The node behind the handle, unless it was deleted
+    pub(crate) fn upgrade(&self) -> Option<Link<Node>> {
+        self.node.upgrade()
+    }
+
+    /// Checks whether both handles refer to the same node
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.node.ptr_eq(&other.node)
+    }
+}
+
+impl<Node> Clone for Handle<Node> {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node.clone(),
+        }
+    }
+}
+
+impl<Node> fmt::Debug for Handle<Node> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({:p})", self.node.as_ptr())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandleError {
+    /// The node is not part of the tree (anymore)
+    Stale,
+    /// The node is still referenced through a raw `Link`, obtained with
+    /// `Bst::get_root` or the `BstNode` accessors, so its value cannot be
+    /// moved out. The tree is left untouched.
+    Shared,
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandleError::Stale => write!(f, "the node is not part of the tree"),
+            HandleError::Shared => write!(f, "the node is referenced from outside of the tree"),
+        }
+    }
+}
+
+impl Error for HandleError {}
@@ -0,0 +1,571 @@
+//! A sequence stored in a treap, keyed by position rather than by value
+//!
+//! The position of a node is never stored: it is the number of nodes to
+//! its left, which every node keeps track of through its subtree size.
+//! The nodes are linked like those of the other trees and keep the size
+//! up to date in `TreeNode::pull_up`, so insertion and removal rotate
+//! with `base::rotate_up`. Nodes are not ordered by their values, so the
+//! sequence does not implement `Bst`, whose nodes are located by
+//! comparing values.
+
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+use crate::bst::base::{
+    child_direction, dismantle, link, merge_by, pull_path, rotate_up, sink_to_leaf, split_by,
+    take_child, Direction, Link, TreeNode,
+};
+
+type Tree<Value> = Option<Link<ImplicitTreapNode<Value>>>;
+
+struct ImplicitTreapNode<Value> {
+    /// In an `UnsafeCell`, because the links to a node are shared, while
+    /// `ImplicitTreap::get_mut` hands out its value mutably
+    value: UnsafeCell<Value>,
+    /// Heap key: every node has a higher priority than its children
+    priority: u32,
+    /// Number of nodes in the subtree rooted at this node
+    size: Cell<usize>,
+    left_child: RefCell<Tree<Value>>,
+    right_child: RefCell<Tree<Value>>,
+    parent: RefCell<Tree<Value>>,
+}
+
+/// A `Vec`-like sequence with O(log n) expected time insertion, removal,
+/// splitting and concatenation at any position
+pub struct ImplicitTreap<Value> {
+    root: Tree<Value>,
+    rng: ThreadRng,
+}
+
+fn size<Value>(tree: &Tree<Value>) -> usize {
+    tree.as_ref().map_or(0, |node| node.size.get())
+}
+
+impl<Value> ImplicitTreapNode<Value> {
+    fn new(value: Value, priority: u32) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            priority,
+            size: Cell::new(1),
+            left_child: RefCell::new(None),
+            right_child: RefCell::new(None),
+            parent: RefCell::new(None),
+        }
+    }
+
+    /// The direction of descent towards `index`, which is relative to the
+    /// subtree of `self`, adjusting `index` to the chosen child
+    /// Return value `None` implies that `self` is at `index`
+    fn get_direction_of_index(&self, index: &mut usize) -> Option<Direction> {
+        let left_size = size(&self.get_child(Direction::Left));
+        if *index < left_size {
+            Some(Direction::Left)
+        } else if *index > left_size {
+            *index -= left_size + 1;
+            Some(Direction::Right)
+        } else {
+            None
+        }
+    }
+}
+
+impl<Value> TreeNode for ImplicitTreapNode<Value> {
+    fn get_child(&self, direction: Direction) -> Option<Link<Self>> {
+        match direction {
+            Direction::Left => self.left_child.borrow().clone(),
+            Direction::Right => self.right_child.borrow().clone(),
+        }
+    }
+
+    fn get_parent(&self) -> Option<Link<Self>> {
+        self.parent.borrow().clone()
+    }
+
+    fn set_child(&self, direction: Direction, child: Option<Link<Self>>) {
+        match direction {
+            Direction::Left => *self.left_child.borrow_mut() = child,
+            Direction::Right => *self.right_child.borrow_mut() = child,
+        }
+    }
+
+    fn set_parent(&self, parent: Option<Link<Self>>) {
+        *self.parent.borrow_mut() = parent
+    }
+
+    /// Recomputes the size after the children have changed
+    fn pull_up(&self) {
+        let size =
+            1 + size(&self.get_child(Direction::Left)) + size(&self.get_child(Direction::Right));
+        self.size.set(size);
+    }
+}
+
+/// Splits `tree` into its first `at` values and the rest
+fn split<Value>(tree: Tree<Value>, at: usize) -> (Tree<Value>, Tree<Value>) {
+    let mut at = at;
+    split_by(tree, &mut |node| {
+        let left_size = size(&node.get_child(Direction::Left));
+        if at <= left_size {
+            false
+        } else {
+            at -= left_size + 1;
+            true
+        }
+    })
+}
+
+/// Concatenates `left` and `right`, keeping the heap order of priorities
+fn merge<Value>(left: Tree<Value>, right: Tree<Value>) -> Tree<Value> {
+    merge_by(left, right, &|node| node.priority)
+}
+
+impl<Value> ImplicitTreap<Value> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            rng: thread_rng(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Descends to the node at `index`, if it is in bounds
+    fn locate(&self, index: usize) -> Tree<Value> {
+        let mut index = index;
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            match node.get_direction_of_index(&mut index) {
+                Some(direction) => maybe_node = node.get_child(direction),
+                None => return Some(node),
+            }
+        }
+        None
+    }
+
+    /// Returns the value at `index`, or `None` if out of bounds
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        // The tree keeps the node alive for as long as it is borrowed
+        self.locate(index).map(|node| unsafe { &*node.value.get() })
+    }
+
+    /// Returns the value at `index` mutably, or `None` if out of bounds
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Value> {
+        // The tree is borrowed mutably, so there are no other references
+        // to its values
+        self.locate(index)
+            .map(|node| unsafe { &mut *node.value.get() })
+    }
+
+    /// Inserts `value` at `index`, shifting all values after it
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: Value) {
+        let len = self.len();
+        assert!(
+            index <= len,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            len
+        );
+        // Descends to the empty slot right before the value at `index`
+        let mut index = index;
+        let mut maybe_parent_and_direction = None;
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            let left_size = size(&node.get_child(Direction::Left));
+            let direction = if index <= left_size {
+                Direction::Left
+            } else {
+                index -= left_size + 1;
+                Direction::Right
+            };
+            maybe_node = node.get_child(direction);
+            maybe_parent_and_direction = Some((node, direction));
+        }
+
+        let node = Link::new(ImplicitTreapNode::new(value, self.rng.gen()));
+        if let Some((parent, direction)) = maybe_parent_and_direction {
+            link(&parent, direction, Some(node.clone()));
+        }
+        while node
+            .get_parent()
+            .is_some_and(|parent| parent.priority < node.priority)
+        {
+            rotate_up(&node);
+        }
+        if node.is_root() {
+            self.root = Some(node.clone());
+        }
+        pull_path(node.get_parent());
+    }
+
+    /// Removes and returns the value at `index`, shifting all values
+    /// after it
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> Value {
+        let len = self.len();
+        assert!(
+            index < len,
+            "removal index (is {}) should be < len (is {})",
+            index,
+            len
+        );
+        let node = self
+            .locate(index)
+            .expect("Index was checked to be in bounds");
+        if let Some(replacement) = sink_to_leaf(&node, |node| node.priority, |_, _| {}) {
+            if replacement.is_root() {
+                self.root = Some(replacement);
+            }
+        }
+        match node.get_parent() {
+            Some(parent) => {
+                parent.set_child(child_direction(&parent, &node), None);
+                node.set_parent(None);
+                pull_path(Some(parent));
+            }
+            None => self.root = None,
+        }
+        match Rc::try_unwrap(node) {
+            Ok(node) => node.value.into_inner(),
+            Err(_) => unreachable!("The sequence hands out no links to its nodes"),
+        }
+    }
+
+    /// Appends `value` to the end of the sequence
+    pub fn push(&mut self, value: Value) {
+        self.insert(self.len(), value);
+    }
+
+    /// Splits the sequence in two at `at`, returning the values from `at`
+    /// onwards and keeping the ones before it
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let len = self.len();
+        assert!(
+            at <= len,
+            "`at` split index (is {}) should be <= len (is {})",
+            at,
+            len
+        );
+        let (left, right) = split(self.root.take(), at);
+        self.root = left;
+        Self {
+            root: right,
+            rng: thread_rng(),
+        }
+    }
+
+    /// Moves all values of `other` to the end of `self`, leaving `other`
+    /// empty
+    pub fn append(&mut self, other: &mut Self) {
+        self.root = merge(self.root.take(), other.root.take());
+    }
+
+    pub fn iter(&self) -> Iter<'_, Value> {
+        let mut iter = Iter {
+            _treap: self,
+            stack: vec![],
+            remaining: self.len(),
+        };
+        iter.push_left_spine(self.root.clone());
+        iter
+    }
+}
+
+/// In-order iterator over the values of an `ImplicitTreap`
+pub struct Iter<'a, Value> {
+    _treap: &'a ImplicitTreap<Value>,
+    /// Nodes whose left subtrees have been visited, but they themselves
+    /// have not
+    stack: Vec<Link<ImplicitTreapNode<Value>>>,
+    remaining: usize,
+}
+
+impl<'a, Value> Iter<'a, Value> {
+    fn push_left_spine(&mut self, mut maybe_node: Tree<Value>) {
+        while let Some(node) = maybe_node {
+            maybe_node = node.get_child(Direction::Left);
+            self.stack.push(node);
+        }
+    }
+}
+
+impl<'a, Value> Iterator for Iter<'a, Value> {
+    type Item = &'a Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.get_child(Direction::Right));
+        self.remaining -= 1;
+        // The treap is borrowed for `'a`, which keeps the node alive
+        Some(unsafe { &*node.value.get() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, Value> ExactSizeIterator for Iter<'a, Value> {}
+
+/// Consuming in-order iterator over the values of an `ImplicitTreap`
+pub struct IntoIter<Value> {
+    /// Nodes, which have been detached from their parents and their left
+    /// subtrees, but still own their right ones
+    stack: Vec<Link<ImplicitTreapNode<Value>>>,
+    remaining: usize,
+}
+
+impl<Value> IntoIter<Value> {
+    fn push_left_spine(&mut self, mut tree: Tree<Value>) {
+        while let Some(node) = tree {
+            tree = take_child(&node, Direction::Left);
+            self.stack.push(node);
+        }
+    }
+}
+
+impl<Value> Iterator for IntoIter<Value> {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(take_child(&node, Direction::Right));
+        self.remaining -= 1;
+        match Rc::try_unwrap(node) {
+            Ok(node) => Some(node.value.into_inner()),
+            Err(_) => unreachable!("The node was detached from all others"),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<Value> ExactSizeIterator for IntoIter<Value> {}
+
+/// Dismantles the right subtrees not iterated over, which would leak
+/// otherwise
+impl<Value> Drop for IntoIter<Value> {
+    fn drop(&mut self) {
+        for node in self.stack.drain(..) {
+            dismantle(Some(node));
+        }
+    }
+}
+
+impl<Value> IntoIterator for ImplicitTreap<Value> {
+    type Item = Value;
+    type IntoIter = IntoIter<Value>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        let mut iter = IntoIter {
+            stack: vec![],
+            remaining: self.len(),
+        };
+        iter.push_left_spine(self.root.take());
+        iter
+    }
+}
+
+impl<'a, Value> IntoIterator for &'a ImplicitTreap<Value> {
+    type Item = &'a Value;
+    type IntoIter = Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<Value> Default for ImplicitTreap<Value> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Value> Drop for ImplicitTreap<Value> {
+    fn drop(&mut self) {
+        dismantle(self.root.take());
+    }
+}
+
+impl<Value> FromIterator<Value> for ImplicitTreap<Value> {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut treap = Self::new();
+        treap.extend(iter);
+        treap
+    }
+}
+
+impl<Value> Extend<Value> for ImplicitTreap<Value> {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<Value: Debug> Debug for ImplicitTreap<Value> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Checks the sizes, the parent links and the heap order of
+    /// priorities, returning the height of the tree
+    fn check_invariants<Value>(tree: &Tree<Value>) -> usize {
+        match tree {
+            None => 0,
+            Some(node) => {
+                let mut height = 0;
+                for &direction in [Direction::Left, Direction::Right].iter() {
+                    let child = node.get_child(direction);
+                    if let Some(child) = child.as_ref() {
+                        assert!(child.priority <= node.priority, "Heap order violated");
+                        assert!(Rc::ptr_eq(&child.get_parent().unwrap(), node));
+                    }
+                    height = height.max(check_invariants(&child));
+                }
+                assert_eq!(
+                    node.size.get(),
+                    1 + size(&node.get_child(Direction::Left))
+                        + size(&node.get_child(Direction::Right))
+                );
+                1 + height
+            }
+        }
+    }
+
+    fn to_vec<Value: Clone>(treap: &ImplicitTreap<Value>) -> Vec<Value> {
+        treap.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_insert_remove_get() {
+        let mut treap = ImplicitTreap::new();
+        assert!(treap.is_empty());
+        treap.insert(0, "b");
+        treap.insert(0, "a");
+        treap.insert(2, "d");
+        treap.insert(2, "c");
+        assert_eq!(to_vec(&treap), ["a", "b", "c", "d"]);
+        assert_eq!(treap.len(), 4);
+        assert_eq!(treap.get(2), Some(&"c"));
+        assert_eq!(treap.get(4), None);
+        *treap.get_mut(1).unwrap() = "B";
+        assert_eq!(treap.remove(0), "a");
+        assert_eq!(treap.remove(2), "d");
+        assert_eq!(format!("{:?}", treap), "[\"B\", \"c\"]");
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 2) should be <= len (is 1)")]
+    fn test_insert_out_of_bounds() {
+        let mut treap = ImplicitTreap::new();
+        treap.push(1);
+        treap.insert(2, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "removal index (is 1) should be < len (is 1)")]
+    fn test_remove_out_of_bounds() {
+        let mut treap: ImplicitTreap<u32> = vec![1].into_iter().collect();
+        treap.remove(1);
+    }
+
+    #[test]
+    fn test_split_off_and_append() {
+        let mut treap: ImplicitTreap<u32> = (0..10).collect();
+        let mut tail = treap.split_off(4);
+        assert_eq!(to_vec(&treap), [0, 1, 2, 3]);
+        assert_eq!(to_vec(&tail), [4, 5, 6, 7, 8, 9]);
+        check_invariants(&treap.root);
+        check_invariants(&tail.root);
+
+        // Appending in the other order swaps the halves
+        tail.append(&mut treap);
+        assert!(treap.is_empty());
+        assert_eq!(to_vec(&tail), [4, 5, 6, 7, 8, 9, 0, 1, 2, 3]);
+        check_invariants(&tail.root);
+
+        assert!(tail.split_off(10).is_empty());
+        let everything = tail.split_off(0);
+        assert!(tail.is_empty());
+        assert_eq!(everything.into_iter().collect::<Vec<u32>>().len(), 10);
+    }
+
+    #[test]
+    fn test_matches_vec() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut treap = ImplicitTreap::new();
+        let mut model = vec![];
+        for step in 0..5_000u32 {
+            let len = model.len();
+            match rng.gen_range(0..10) {
+                0..=4 => {
+                    let index = rng.gen_range(0..len + 1);
+                    treap.insert(index, step);
+                    model.insert(index, step);
+                }
+                5..=6 if len > 0 => {
+                    let index = rng.gen_range(0..len);
+                    assert_eq!(treap.remove(index), model.remove(index));
+                }
+                7 => {
+                    let at = rng.gen_range(0..len + 1);
+                    let mut tail = treap.split_off(at);
+                    let mut model_tail = model.split_off(at);
+                    assert_eq!(to_vec(&tail), model_tail);
+                    treap.append(&mut tail);
+                    model.append(&mut model_tail);
+                }
+                _ => {
+                    let index = rng.gen_range(0..len + 1);
+                    assert_eq!(treap.get(index), model.get(index));
+                }
+            }
+            assert_eq!(treap.len(), model.len());
+        }
+        assert_eq!(to_vec(&treap), model);
+        assert_eq!(treap.iter().len(), model.len());
+        // Random priorities keep the tree shallow
+        let height = check_invariants(&treap.root);
+        assert!(height < 50, "height {} for {} values", height, model.len());
+        assert_eq!(treap.into_iter().collect::<Vec<u32>>(), model);
+    }
+
+    #[test]
+    fn test_values_dropped() {
+        let witness = Rc::new(());
+        let mut treap: ImplicitTreap<Rc<()>> = (0..10).map(|_| witness.clone()).collect();
+        drop(treap.remove(3));
+        let tail = treap.split_off(5);
+        assert_eq!(Rc::strong_count(&witness), 10);
+        drop(tail);
+        assert_eq!(Rc::strong_count(&witness), 6);
+        let mut iter = treap.into_iter();
+        iter.next();
+        drop(iter);
+        assert_eq!(Rc::strong_count(&witness), 1);
+    }
+}
@@ -0,0 +1,246 @@
+//! Operation counters and an event observer for trees
+//!
+//! Trees keep an `Instruments` value and report to it as they work. With
+//! the `instrumentation` feature off, `Instruments` is zero-sized and its
+//! methods do nothing, so the reports compile away; with it on, trees
+//! expose their instruments for reading the counters and setting an
+//! observer.
+
+#[cfg(feature = "instrumentation")]
+use std::cell::{Cell, RefCell};
+#[cfg(not(feature = "instrumentation"))]
+use std::marker::PhantomData;
+
+use crate::bst::base::Direction;
+
+/// Totals of the elementary steps of the operations so far
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Counters {
+    /// Comparisons of a value with the value of a node, each of which
+    /// tells less, equal or greater
+    pub comparisons: u64,
+    /// Nodes stepped on while searching or walking the tree
+    pub visits: u64,
+    /// Rotations, in the trees that keep their balance by rotating
+    pub rotations: u64,
+    /// Nodes created
+    pub allocations: u64,
+    /// Child and parent links set or cleared
+    pub pointer_updates: u64,
+}
+
+/// A change to the tree, reported after it has happened
+#[derive(PartialEq, Debug)]
+pub enum Event<'a, Value> {
+    Inserted(&'a Value),
+    Deleted(&'a Value),
+    /// The node of `pivot` was rotated up, taking the place of its parent,
+    /// which became its `direction` child
+    Rotated {
+        pivot: &'a Value,
+        direction: Direction,
+    },
+    /// The node of `removed` was unlinked, and the node of `by` took its
+    /// place
+    Replaced {
+        removed: &'a Value,
+        by: &'a Value,
+    },
+}
+
+pub type Observer<Value> = Box<dyn FnMut(&Event<'_, Value>)>;
+
+pub struct Instruments<Value> {
+    #[cfg(feature = "instrumentation")]
+    counters: Cell<Counters>,
+    #[cfg(feature = "instrumentation")]
+    observer: RefCell<Option<Observer<Value>>>,
+    #[cfg(not(feature = "instrumentation"))]
+    _value: PhantomData<fn(&Value)>,
+}
+
+impl<Value> Instruments<Value> {
+    pub(crate) fn new() -> Self {
+        Self {
+            #[cfg(feature = "instrumentation")]
+            counters: Cell::new(Counters::default()),
+            #[cfg(feature = "instrumentation")]
+            observer: RefCell::new(None),
+            #[cfg(not(feature = "instrumentation"))]
+            _value: PhantomData,
+        }
+    }
+
+    /// Updates the counters with `update`
+    #[inline(always)]
+    pub(crate) fn count<F: FnOnce(&mut Counters)>(&self, update: F) {
+        #[cfg(feature = "instrumentation")]
+        {
+            let mut counters = self.counters.get();
+            update(&mut counters);
+            self.counters.set(counters);
+        }
+        #[cfg(not(feature = "instrumentation"))]
+        let _ = update;
+    }
+
+    /// Hands the event made by `make_event` to the observer, if any
+    #[inline(always)]
+    pub(crate) fn emit<'a, F: FnOnce() -> Event<'a, Value>>(&self, make_event: F)
+    where
+        Value: 'a,
+    {
+        #[cfg(feature = "instrumentation")]
+        {
+            if let Some(observer) = self.observer.borrow_mut().as_mut() {
+                observer(&make_event());
+            }
+        }
+        #[cfg(not(feature = "instrumentation"))]
+        let _ = make_event;
+    }
+}
+
+#[cfg(feature = "instrumentation")]
+impl<Value> Instruments<Value> {
+    pub fn counters(&self) -> Counters {
+        self.counters.get()
+    }
+
+    pub fn reset_counters(&self) {
+        self.counters.set(Counters::default());
+    }
+
+    /// Makes `observer` receive all events from now on, replacing the
+    /// previous one
+    pub fn set_observer(&self, observer: Option<Observer<Value>>) {
+        *self.observer.borrow_mut() = observer;
+    }
+}
+
+impl<Value> Default for Instruments<Value> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "instrumentation")]
+    use super::*;
+    #[cfg(feature = "instrumentation")]
+    use crate::bst::base::Bst;
+    #[cfg(feature = "instrumentation")]
+    use crate::bst::lazy::LazyTreap;
+    #[cfg(feature = "instrumentation")]
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+    #[cfg(feature = "instrumentation")]
+    use std::rc::Rc;
+
+    #[cfg(not(feature = "instrumentation"))]
+    #[test]
+    fn test_zero_sized_when_disabled() {
+        assert_eq!(std::mem::size_of::<super::Instruments<u64>>(), 0);
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn test_counters() {
+        // Sorted insertion makes a path, costing 0 + 1 + ... + (n - 1)
+        let n: u64 = 100;
+        let mut bst = SimpleBst::<SimpleBstNode<u64>>::new();
+        bst.extend(0..n);
+        let counters = bst.instruments().counters();
+        assert_eq!(counters.comparisons, n * (n - 1) / 2);
+        assert_eq!(counters.visits, n * (n - 1) / 2);
+        assert_eq!(counters.allocations, n);
+        assert_eq!(counters.pointer_updates, 2 * (n - 1));
+        assert_eq!(counters.rotations, 0);
+
+        // A balanced tree of 2^10 - 1 values needs at most 10 per search
+        let mut balanced = SimpleBst::<SimpleBstNode<u64>>::new();
+        let mut stack = vec![(0, 1 << 10)];
+        while let Some((lo, hi)) = stack.pop() {
+            if lo + 1 < hi {
+                let middle = (lo + hi) / 2;
+                balanced.insert(middle);
+                stack.push((lo, middle));
+                stack.push((middle, hi));
+            }
+        }
+        balanced.instruments().reset_counters();
+        for value in 1..1 << 10 {
+            assert!(balanced.contains(&value));
+        }
+        assert!(balanced.instruments().counters().comparisons <= 10 * ((1 << 10) - 1));
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn test_observer() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut bst = SimpleBst::<SimpleBstNode<u32>>::new();
+        let recorded = events.clone();
+        bst.instruments()
+            .set_observer(Some(Box::new(move |event: &Event<'_, u32>| {
+                recorded.borrow_mut().push(format!("{:?}", event));
+            })));
+        bst.extend(vec![2, 1, 3]);
+        bst.delete(&1).unwrap();
+        bst.delete(&2).unwrap();
+        bst.delete(&4).unwrap();
+        assert_eq!(
+            *events.borrow(),
+            [
+                "Inserted(2)",
+                "Inserted(1)",
+                "Inserted(3)",
+                "Deleted(1)",
+                "Replaced { removed: 2, by: 3 }",
+                "Deleted(2)",
+            ]
+        );
+        bst.instruments().set_observer(None);
+        bst.insert(5);
+        assert_eq!(events.borrow().len(), 6);
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn test_rotations() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut treap: LazyTreap<u32, i64> = LazyTreap::new();
+        let recorded = events.clone();
+        treap
+            .instruments()
+            .set_observer(Some(Box::new(move |event: &Event<'_, u32>| {
+                if let Event::Rotated { pivot, direction } = event {
+                    recorded.borrow_mut().push((**pivot, *direction));
+                }
+            })));
+        // Sorted keys hang to the right until rotated up, which takes
+        // their parents to the left
+        for key in 0..100 {
+            treap.insert(key, 0);
+        }
+        let counters = treap.instruments().counters();
+        assert_eq!(counters.allocations, 100);
+        assert!(counters.rotations > 0);
+        assert_eq!(counters.rotations, events.borrow().len() as u64);
+        assert!(events
+            .borrow()
+            .iter()
+            .all(|&(_, direction)| direction == Direction::Left));
+
+        treap.instruments().reset_counters();
+        events.borrow_mut().clear();
+        for key in 0..100 {
+            treap.remove(&key);
+        }
+        assert_eq!(
+            treap.instruments().counters().rotations,
+            events.borrow().len() as u64
+        );
+        assert!(treap.is_empty());
+    }
+}
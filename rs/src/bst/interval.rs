@@ -0,0 +1,483 @@
+//! An interval tree: a search tree of intervals ordered by their start,
+//! where every node also knows the largest end in its subtree
+//!
+//! The augmentation lets overlap queries skip every subtree that ends
+//! before the queried interval starts.
+//!
+//! The tree is not balanced: its height depends on the insertion order,
+//! and is O(n) in the worst case, such as intervals inserted by
+//! increasing start. Every bound below in terms of the height is O(n) in
+//! that case too.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bst::base::{
+    check_unshared, child_direction, dismantle, get_extreme, link, value_in_tree, Bst, BstNode,
+    Direction, Link, TreeNode,
+};
+use crate::bst::handle::HandleError;
+
+/// A closed interval `[start, end]`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval<Bound> {
+    pub start: Bound,
+    pub end: Bound,
+}
+
+impl<Bound: PartialOrd> Interval<Bound> {
+    pub fn new(start: Bound, end: Bound) -> Self {
+        assert!(start <= end, "Interval start has to be <= end");
+        Self { start, end }
+    }
+
+    /// Checks whether the intervals have at least one point in common
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn contains(&self, point: &Bound) -> bool {
+        &self.start <= point && point <= &self.end
+    }
+}
+
+/// An interval with a payload, as stored in an `IntervalTree`
+///
+/// Entries are ordered by interval first and by payload second, so the
+/// same interval can be stored with different payloads.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entry<Bound, Payload> {
+    pub interval: Interval<Bound>,
+    pub payload: Payload,
+}
+
+impl<Bound, Payload> Entry<Bound, Payload> {
+    pub fn new(interval: Interval<Bound>, payload: Payload) -> Self {
+        Self { interval, payload }
+    }
+}
+
+pub struct IntervalTreeNode<Bound, Payload> {
+    value: Entry<Bound, Payload>,
+    /// The largest interval end in the subtree rooted at this node
+    max_end: RefCell<Bound>,
+    left_child: RefCell<Option<Link<Self>>>,
+    right_child: RefCell<Option<Link<Self>>>,
+    parent: RefCell<Option<Link<Self>>>,
+}
+
+pub struct IntervalTree<Bound: PartialOrd + Clone, Payload: PartialOrd> {
+    root: Option<Link<IntervalTreeNode<Bound, Payload>>>,
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> PartialEq
+    for IntervalTreeNode<Bound, Payload>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> TreeNode for IntervalTreeNode<Bound, Payload> {
+    fn get_child(&self, direction: Direction) -> Option<Link<Self>> {
+        match direction {
+            Direction::Left => self.left_child.borrow().clone(),
+            Direction::Right => self.right_child.borrow().clone(),
+        }
+    }
+
+    fn get_parent(&self) -> Option<Link<Self>> {
+        self.parent.borrow().clone()
+    }
+
+    fn set_child(&self, direction: Direction, child: Option<Link<Self>>) {
+        match direction {
+            Direction::Left => *self.left_child.borrow_mut() = child,
+            Direction::Right => *self.right_child.borrow_mut() = child,
+        }
+    }
+
+    fn set_parent(&self, parent: Option<Link<Self>>) {
+        *self.parent.borrow_mut() = parent
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> BstNode for IntervalTreeNode<Bound, Payload> {
+    type Value = Entry<Bound, Payload>;
+
+    fn new(value: Self::Value) -> Self {
+        Self {
+            max_end: RefCell::new(value.interval.end.clone()),
+            value,
+            left_child: RefCell::new(None),
+            right_child: RefCell::new(None),
+            parent: RefCell::new(None),
+        }
+    }
+
+    fn as_value(&self) -> &Self::Value {
+        &self.value
+    }
+
+    fn into_value(self) -> Self::Value {
+        // Consuming a node, which has pointers to other nodes is
+        // a programming error
+        assert!(
+            self.is_leaf() && self.is_root(),
+            "Node is still linked before node consumption"
+        );
+        self.value
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> IntervalTreeNode<Bound, Payload> {
+    pub fn interval(&self) -> &Interval<Bound> {
+        &self.value.interval
+    }
+
+    /// The largest interval end in the subtree rooted at this node
+    pub fn max_end(&self) -> Bound {
+        self.max_end.borrow().clone()
+    }
+
+    /// Recomputes `max_end` after the children have changed
+    fn update_max_end(&self) {
+        let mut max_end = self.value.interval.end.clone();
+        for &direction in [Direction::Left, Direction::Right].iter() {
+            if let Some(child) = self.get_child(direction) {
+                let child_max_end = child.max_end();
+                if child_max_end > max_end {
+                    max_end = child_max_end;
+                }
+            }
+        }
+        *self.max_end.borrow_mut() = max_end;
+    }
+}
+
+/// Recomputes `max_end` of `maybe_node` and all of its ancestors
+fn update_max_ends<Bound: PartialOrd + Clone, Payload: PartialOrd>(
+    mut maybe_node: Option<Link<IntervalTreeNode<Bound, Payload>>>,
+) {
+    while let Some(node) = maybe_node {
+        node.update_max_end();
+        maybe_node = node.get_parent();
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> Bst for IntervalTree<Bound, Payload> {
+    type Node = IntervalTreeNode<Bound, Payload>;
+
+    fn get_root(&self) -> Option<Link<Self::Node>> {
+        self.root.clone()
+    }
+
+    fn insert(&mut self, value: <Self::Node as BstNode>::Value) {
+        let mut maybe_parent_and_direction: Option<(Link<Self::Node>, Direction)> = None;
+        let mut maybe_current_node = self.root.clone();
+        while let Some(current_node) = maybe_current_node {
+            match current_node.get_direction_of_value(&value) {
+                Some(direction) => {
+                    maybe_current_node = current_node.get_child(direction);
+                    maybe_parent_and_direction = Some((current_node, direction));
+                }
+                // The very same entry is already there
+                None => return,
+            }
+        }
+
+        let new_node = Link::new(<Self::Node as BstNode>::new(value));
+        match maybe_parent_and_direction {
+            Some((parent, direction)) => {
+                link(&parent, direction, Some(new_node));
+                update_max_ends(Some(parent));
+            }
+            None => self.root = Some(new_node),
+        }
+    }
+
+    fn delete_node(
+        &mut self,
+        node: Link<Self::Node>,
+    ) -> Result<<Self::Node as BstNode>::Value, HandleError> {
+        check_unshared(&node)?;
+        self.unlink(&node);
+        let deleted_node = match Rc::try_unwrap(node) {
+            Ok(deleted_node) => deleted_node,
+            Err(_) => unreachable!("References to the node were counted before the deletion"),
+        };
+        Ok(deleted_node.into_value())
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> IntervalTree<Bound, Payload> {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Takes `node` out of the tree, putting its in-order successor in
+    /// its place if it has two children
+    fn unlink(&mut self, node: &Link<IntervalTreeNode<Bound, Payload>>) {
+        let maybe_parent = node.get_parent();
+        let left_child = node.get_child(Direction::Left);
+        let right_child = node.get_child(Direction::Right);
+        node.set_parent(None);
+        node.set_child(Direction::Left, None);
+        node.set_child(Direction::Right, None);
+
+        // The replacement, and the deepest node whose subtree changed
+        let (replacement, lowest_changed) = match (left_child, right_child) {
+            (None, None) => (None, maybe_parent.clone()),
+            (Some(child), None) | (None, Some(child)) => (Some(child), maybe_parent.clone()),
+            (Some(left_child), Some(right_child)) => {
+                let successor = get_extreme(right_child.clone(), Direction::Left);
+                let lowest_changed = if Rc::ptr_eq(&successor, &right_child) {
+                    successor.clone()
+                } else {
+                    // The successor has no left child, so its right subtree
+                    // takes its place
+                    let successor_parent = successor.get_parent().unwrap();
+                    link(
+                        &successor_parent,
+                        Direction::Left,
+                        successor.get_child(Direction::Right),
+                    );
+                    link(&successor, Direction::Right, Some(right_child));
+                    successor_parent
+                };
+                link(&successor, Direction::Left, Some(left_child));
+                (Some(successor), Some(lowest_changed))
+            }
+        };
+
+        match maybe_parent {
+            Some(parent) => {
+                let direction = child_direction(&parent, node);
+                link(&parent, direction, replacement);
+            }
+            None => {
+                if let Some(replacement) = replacement.as_ref() {
+                    replacement.set_parent(None);
+                }
+                self.root = replacement;
+            }
+        }
+        update_max_ends(lowest_changed);
+    }
+
+    /// All entries overlapping `query`, ordered by interval
+    ///
+    /// Takes O(min(n, (k + 1) * height)) time for k results.
+    pub fn overlapping(&self, query: &Interval<Bound>) -> Vec<&Entry<Bound, Payload>> {
+        let mut result = vec![];
+        // In-order traversal, which skips the subtrees ending before
+        // `query` and stops at the first interval starting after it
+        let mut stack = vec![];
+        let mut maybe_node = self.root.clone();
+        loop {
+            while let Some(node) = maybe_node {
+                if node.max_end() < query.start {
+                    break;
+                }
+                maybe_node = node.get_child(Direction::Left);
+                stack.push(node);
+            }
+            let node = match stack.pop() {
+                Some(node) => node,
+                None => break,
+            };
+            if node.interval().start > query.end {
+                break;
+            }
+            if node.interval().overlaps(query) {
+                // `node` stays in the tree for as long as `self` is borrowed
+                result.push(unsafe { value_in_tree(self, &node) });
+            }
+            maybe_node = node.get_child(Direction::Right);
+        }
+        result
+    }
+
+    /// All entries containing `point`, ordered by interval
+    pub fn stab(&self, point: &Bound) -> Vec<&Entry<Bound, Payload>> {
+        self.overlapping(&Interval::new(point.clone(), point.clone()))
+    }
+
+    /// Checks whether any entry overlaps `query`, in O(height) time,
+    /// which is O(n) for a degenerate tree
+    pub fn overlaps_any(&self, query: &Interval<Bound>) -> bool {
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            if node.interval().overlaps(query) {
+                return true;
+            }
+            // If anything in the left subtree reaches `query`, but does
+            // not overlap it, the right subtree starts after `query` too
+            maybe_node = match node.get_child(Direction::Left) {
+                Some(left_child) if left_child.max_end() >= query.start => Some(left_child),
+                _ => node.get_child(Direction::Right),
+            };
+        }
+        false
+    }
+
+    /// Checks whether any two entries overlap each other
+    pub fn has_overlaps(&self) -> bool {
+        let mut max_end: Option<&Bound> = None;
+        for entry in self.iter() {
+            if max_end.is_some_and(|max_end| &entry.interval.start <= max_end) {
+                return true;
+            }
+            if max_end.is_none_or(|max_end| &entry.interval.end > max_end) {
+                max_end = Some(&entry.interval.end);
+            }
+        }
+        false
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> Default for IntervalTree<Bound, Payload> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Bound: PartialOrd + Clone, Payload: PartialOrd> Drop for IntervalTree<Bound, Payload> {
+    fn drop(&mut self) {
+        dismantle(self.root.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn entry(start: u32, end: u32, payload: &'static str) -> Entry<u32, &'static str> {
+        Entry::new(Interval::new(start, end), payload)
+    }
+
+    fn build(entries: Vec<Entry<u32, &'static str>>) -> IntervalTree<u32, &'static str> {
+        let mut tree = IntervalTree::new();
+        for e in entries {
+            tree.insert(e);
+        }
+        tree
+    }
+
+    /// Checks the search order and `max_end` of every node, returning the
+    /// largest end of the subtree
+    fn check_invariants<Bound: PartialOrd + Clone + std::fmt::Debug, Payload: PartialOrd>(
+        node: &Link<IntervalTreeNode<Bound, Payload>>,
+    ) -> Bound {
+        let mut max_end = node.interval().end.clone();
+        for &direction in [Direction::Left, Direction::Right].iter() {
+            if let Some(child) = node.get_child(direction) {
+                assert!(Rc::ptr_eq(&child.get_parent().unwrap(), node));
+                assert_eq!(
+                    node.get_direction_of_value(child.as_value()),
+                    Some(direction)
+                );
+                let child_max_end = check_invariants(&child);
+                if child_max_end > max_end {
+                    max_end = child_max_end;
+                }
+            }
+        }
+        assert_eq!(node.max_end(), max_end);
+        max_end
+    }
+
+    #[test]
+    fn test_queries() {
+        // 1 ├───┤ a
+        // 2   ├─────────┤ b
+        // 3         ├─┤ c
+        // 4               ├───┤ d
+        //   0 1 2 3 4 5 6 7 8 9
+        let tree = build(vec![
+            entry(6, 7, "c"),
+            entry(0, 2, "a"),
+            entry(8, 10, "d"),
+            entry(1, 6, "b"),
+        ]);
+        check_invariants(&tree.get_root().unwrap());
+        let payloads = |entries: Vec<&Entry<u32, &'static str>>| {
+            entries.iter().map(|e| e.payload).collect::<Vec<_>>()
+        };
+        assert_eq!(payloads(tree.stab(&2)), ["a", "b"]);
+        assert_eq!(payloads(tree.stab(&7)), ["c"]);
+        assert_eq!(payloads(tree.stab(&11)), Vec::<&str>::new());
+        assert_eq!(
+            payloads(tree.overlapping(&Interval::new(6, 8))),
+            ["b", "c", "d"]
+        );
+        assert!(tree.overlaps_any(&Interval::new(7, 7)));
+        assert!(!tree.overlaps_any(&Interval::new(11, 12)));
+        assert!(tree.has_overlaps());
+        assert!(!build(vec![entry(0, 2, "a"), entry(3, 5, "b")]).has_overlaps());
+        assert!(build(vec![entry(0, 2, "a"), entry(2, 5, "b")]).has_overlaps());
+    }
+
+    #[test]
+    fn test_payloads_and_delete() {
+        let mut tree = build(vec![entry(1, 3, "x"), entry(1, 3, "y"), entry(0, 9, "z")]);
+        assert_eq!(tree.stab(&2).len(), 3);
+        assert_eq!(tree.delete(&entry(1, 3, "x")), Ok(Some(entry(1, 3, "x"))));
+        assert_eq!(tree.delete(&entry(1, 3, "x")), Ok(None));
+        // The root goes, and the long interval has to stop counting
+        assert_eq!(tree.delete(&entry(0, 9, "z")), Ok(Some(entry(0, 9, "z"))));
+        check_invariants(&tree.get_root().unwrap());
+        assert!(!tree.overlaps_any(&Interval::new(5, 9)));
+        assert_eq!(tree.stab(&2), [&entry(1, 3, "y")]);
+    }
+
+    #[test]
+    fn test_drop_frees_nodes() {
+        let witness = Rc::new(());
+        let mut tree = IntervalTree::new();
+        for start in 0..10 {
+            tree.insert(Entry::new(Interval::new(start, start + 3), witness.clone()));
+        }
+        assert_eq!(Rc::strong_count(&witness), 11);
+        drop(tree);
+        assert_eq!(Rc::strong_count(&witness), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Interval start has to be <= end")]
+    fn test_reversed_interval() {
+        Interval::new(2, 1);
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tree = IntervalTree::new();
+        let mut model: Vec<Entry<u32, u32>> = vec![];
+        for _ in 0..3_000 {
+            let start = rng.gen_range(0..100);
+            let interval = Interval::new(start, start + rng.gen_range(0..10));
+            let e = Entry::new(interval.clone(), rng.gen_range(0..3));
+            if rng.gen_range(0..3) == 0 {
+                let expected = model.iter().position(|m| m == &e).map(|i| model.remove(i));
+                assert_eq!(tree.delete(&e), Ok(expected));
+            } else if !model.contains(&e) {
+                tree.insert(e.clone());
+                model.push(e);
+            }
+
+            model.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let expected: Vec<&Entry<u32, u32>> = model
+                .iter()
+                .filter(|m| m.interval.overlaps(&interval))
+                .collect();
+            assert_eq!(tree.overlapping(&interval), expected);
+            assert_eq!(tree.overlaps_any(&interval), !expected.is_empty());
+            if let Some(root) = tree.get_root() {
+                check_invariants(&root);
+            }
+        }
+    }
+}
@@ -0,0 +1,910 @@
+//! A treap-based ordered map with range updates and range queries
+//!
+//! Every node keeps the count, sum, min and max of the values in its
+//! subtree, and a pending tag: an update already applied to the node
+//! itself, which still has to be pushed down to its children. The nodes
+//! are ordinary `BstNode`s, which push their tags through `push_down`, so
+//! the tags move down on every descent and in every rotation made by
+//! `base::rotate_up`, and the summaries are recomputed by `pull_up`
+//! afterwards. Every range operation touches O(log n) nodes in
+//! expectation.
+
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Debug};
+use std::ops::{Add, Bound, RangeBounds, RangeInclusive};
+use std::rc::Rc;
+
+use crate::bst::base::{
+    child_direction, dismantle, link, merge_by, pull_path, rotate_up, sink_to_leaf, split_by,
+    take_child, BstNode, Direction, Link, TreeNode,
+};
+use crate::bst::instrument::{Event, Instruments};
+
+/// The arithmetic range operations need from the values
+///
+/// Sums are computed with `+` alone, so they overflow the way `+` does
+/// for the type: integers panic in debug builds and wrap around in
+/// release builds, and floats go to infinity.
+pub trait Number: Copy + PartialOrd + Add<Output = Self> {
+    fn zero() -> Self;
+
+    /// `self` added up `count` times
+    ///
+    /// Adds by doubling, in O(log count) additions, which overflow only
+    /// if the result does.
+    fn times(self, mut count: usize) -> Self {
+        let mut result = Self::zero();
+        let mut power = self;
+        while count > 0 {
+            if count & 1 == 1 {
+                result = result + power;
+            }
+            count >>= 1;
+            if count > 0 {
+                power = power + power;
+            }
+        }
+        result
+    }
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                fn zero() -> Self {
+                    0 as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+macro_rules! impl_float_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                fn zero() -> Self {
+                    0.0
+                }
+
+                fn times(self, count: usize) -> Self {
+                    self * count as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_float_number!(f32, f64);
+
+/// A pending update: an optional assignment followed by an addition
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tag<Value> {
+    assign: Option<Value>,
+    add: Value,
+}
+
+impl<Value: Number> Tag<Value> {
+    fn identity() -> Self {
+        Self {
+            assign: None,
+            add: Value::zero(),
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.assign.is_none() && self.add == Value::zero()
+    }
+
+    /// The update doing `self` first and `later` afterwards
+    fn then(self, later: Self) -> Self {
+        match later.assign {
+            Some(_) => later,
+            None => Self {
+                assign: self.assign,
+                add: self.add + later.add,
+            },
+        }
+    }
+
+    fn apply(&self, value: Value) -> Value {
+        self.assign.unwrap_or(value) + self.add
+    }
+}
+
+/// Aggregates over the values of a range, as returned by
+/// `LazyTreap::summary` and `LinkCutTree::path`
+///
+/// `sum` overflows the way `Number` describes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary<Value> {
+    pub count: usize,
+    pub sum: Value,
+    pub min: Value,
+    pub max: Value,
+}
+
+impl<Value: Number> Summary<Value> {
+    pub(crate) fn of(value: Value) -> Self {
+        Self {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        }
+    }
+
+    pub(crate) fn combine(self, other: Self) -> Self {
+        Self {
+            count: self.count + other.count,
+            sum: self.sum + other.sum,
+            min: if other.min < self.min {
+                other.min
+            } else {
+                self.min
+            },
+            max: if other.max > self.max {
+                other.max
+            } else {
+                self.max
+            },
+        }
+    }
+
+    /// The summary after `tag` is applied to every summarized value
+    fn apply(self, tag: &Tag<Value>) -> Self {
+        let (sum, min, max) = match tag.assign {
+            Some(value) => (value.times(self.count), value, value),
+            None => (self.sum, self.min, self.max),
+        };
+        Self {
+            count: self.count,
+            sum: sum + tag.add.times(self.count),
+            min: min + tag.add,
+            max: max + tag.add,
+        }
+    }
+}
+
+/// Combines the summaries of adjacent ranges, either of which may be
+/// empty
+fn join<Value: Number>(
+    left: Option<Summary<Value>>,
+    right: Option<Summary<Value>>,
+) -> Option<Summary<Value>> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(left.combine(right)),
+        (summary, None) | (None, summary) => summary,
+    }
+}
+
+struct LazyTreapNode<Key, Value> {
+    key: Key,
+    /// Heap key: every node has a higher priority than its children
+    priority: u32,
+    value: Cell<Value>,
+    /// Summary of the subtree, with `tag` already applied
+    summary: Cell<Summary<Value>>,
+    /// Update applied to this node, but not to its children yet
+    tag: Cell<Tag<Value>>,
+    left_child: RefCell<Option<Link<Self>>>,
+    right_child: RefCell<Option<Link<Self>>>,
+    parent: RefCell<Option<Link<Self>>>,
+}
+
+/// An ordered map from `Key` to numbers, which supports adding to and
+/// assigning over key ranges and summarizing them in O(log n) expected
+/// time
+pub struct LazyTreap<Key: Ord, Value: Number> {
+    root: Option<Link<LazyTreapNode<Key, Value>>>,
+    rng: ThreadRng,
+    instruments: Instruments<Key>,
+}
+
+impl<Key: Ord, Value: Number> LazyTreapNode<Key, Value> {
+    fn with_priority(key: Key, value: Value, priority: u32) -> Self {
+        Self {
+            key,
+            priority,
+            value: Cell::new(value),
+            summary: Cell::new(Summary::of(value)),
+            tag: Cell::new(Tag::identity()),
+            left_child: RefCell::new(None),
+            right_child: RefCell::new(None),
+            parent: RefCell::new(None),
+        }
+    }
+
+    /// Applies `tag` to the node, deferring it for the children
+    fn apply(&self, tag: &Tag<Value>) {
+        self.value.set(tag.apply(self.value.get()));
+        self.summary.set(self.summary.get().apply(tag));
+        self.tag.set(self.tag.get().then(*tag));
+    }
+}
+
+impl<Key: Ord, Value: Number> PartialEq for LazyTreapNode<Key, Value> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+/// Nodes are searched by key, the values only ride along
+impl<Key: Ord, Value: Number> TreeNode for LazyTreapNode<Key, Value> {
+    fn get_child(&self, direction: Direction) -> Option<Link<Self>> {
+        match direction {
+            Direction::Left => self.left_child.borrow().clone(),
+            Direction::Right => self.right_child.borrow().clone(),
+        }
+    }
+
+    fn get_parent(&self) -> Option<Link<Self>> {
+        self.parent.borrow().clone()
+    }
+
+    fn set_child(&self, direction: Direction, child: Option<Link<Self>>) {
+        match direction {
+            Direction::Left => *self.left_child.borrow_mut() = child,
+            Direction::Right => *self.right_child.borrow_mut() = child,
+        }
+    }
+
+    fn set_parent(&self, parent: Option<Link<Self>>) {
+        *self.parent.borrow_mut() = parent
+    }
+
+    /// Hands the pending tag down to the children
+    fn push_down(&self) {
+        let tag = self.tag.replace(Tag::identity());
+        if tag.is_identity() {
+            return;
+        }
+        for &direction in [Direction::Left, Direction::Right].iter() {
+            if let Some(child) = self.get_child(direction) {
+                child.apply(&tag);
+            }
+        }
+    }
+
+    /// Recomputes the summary, which requires the tag to be pushed
+    fn pull_up(&self) {
+        let mut summary = Summary::of(self.value.get());
+        if let Some(left_child) = self.get_child(Direction::Left) {
+            summary = left_child.summary.get().combine(summary);
+        }
+        if let Some(right_child) = self.get_child(Direction::Right) {
+            summary = summary.combine(right_child.summary.get());
+        }
+        self.summary.set(summary);
+    }
+}
+
+impl<Key: Ord, Value: Number> BstNode for LazyTreapNode<Key, Value> {
+    type Value = Key;
+
+    /// A node of `key` with a zero value and a random priority
+    fn new(key: Key) -> Self {
+        Self::with_priority(key, Value::zero(), rand::random())
+    }
+
+    fn as_value(&self) -> &Key {
+        &self.key
+    }
+
+    fn into_value(self) -> Key {
+        // Consuming a node, which has pointers to other nodes is
+        // a programming error
+        assert!(
+            self.is_leaf() && self.is_root(),
+            "Node is still linked before node consumption"
+        );
+        self.key
+    }
+}
+
+/// Applies `tag` to the keys in `range` under `node`, where `above_start`
+/// and `below_end` tell whether the ancestors already guarantee that all
+/// of these keys are at least the start or at most the end of the range
+///
+/// Tags whole subtrees within the range and descends, pushing, only along
+/// the two boundaries of the range.
+fn update_range<Key: Ord, Value: Number>(
+    node: &Link<LazyTreapNode<Key, Value>>,
+    range: &RangeInclusive<Key>,
+    tag: &Tag<Value>,
+    above_start: bool,
+    below_end: bool,
+) {
+    if above_start && below_end {
+        node.apply(tag);
+        return;
+    }
+    node.push_down();
+    let key = node.as_value();
+    if range.contains(key) {
+        node.value.set(tag.apply(node.value.get()));
+    }
+    if key > range.start() {
+        if let Some(left_child) = node.get_child(Direction::Left) {
+            let below_end = below_end || key <= range.end();
+            update_range(&left_child, range, tag, above_start, below_end);
+        }
+    }
+    if key < range.end() {
+        if let Some(right_child) = node.get_child(Direction::Right) {
+            let above_start = above_start || key >= range.start();
+            update_range(&right_child, range, tag, above_start, below_end);
+        }
+    }
+    node.pull_up();
+}
+
+/// Summarizes the values of the keys in `range` under `node`, descending
+/// like `update_range`
+fn query_range<Key: Ord, Value: Number>(
+    node: &Link<LazyTreapNode<Key, Value>>,
+    range: &RangeInclusive<Key>,
+    above_start: bool,
+    below_end: bool,
+) -> Option<Summary<Value>> {
+    if above_start && below_end {
+        return Some(node.summary.get());
+    }
+    node.push_down();
+    let key = node.as_value();
+    let mut summary = None;
+    if key > range.start() {
+        if let Some(left_child) = node.get_child(Direction::Left) {
+            let below_end = below_end || key <= range.end();
+            summary = query_range(&left_child, range, above_start, below_end);
+        }
+    }
+    if range.contains(key) {
+        summary = join(summary, Some(Summary::of(node.value.get())));
+    }
+    if key < range.end() {
+        if let Some(right_child) = node.get_child(Direction::Right) {
+            let above_start = above_start || key >= range.start();
+            summary = join(
+                summary,
+                query_range(&right_child, range, above_start, below_end),
+            );
+        }
+    }
+    summary
+}
+
+/// Takes `tree` apart into single nodes, appending them to `out` in
+/// order, with all tags pushed down
+fn into_nodes<Key: Ord, Value: Number>(
+    tree: Option<Link<LazyTreapNode<Key, Value>>>,
+    out: &mut Vec<Link<LazyTreapNode<Key, Value>>>,
+) {
+    if let Some(node) = tree {
+        node.push_down();
+        let right_child = take_child(&node, Direction::Right);
+        into_nodes(take_child(&node, Direction::Left), out);
+        node.pull_up();
+        out.push(node);
+        into_nodes(right_child, out);
+    }
+}
+
+impl<Key: Ord, Value: Number> LazyTreap<Key, Value> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            rng: thread_rng(),
+            instruments: Instruments::new(),
+        }
+    }
+
+    /// The operation counters and the event observer of this treap
+    #[cfg(feature = "instrumentation")]
+    pub fn instruments(&self) -> &Instruments<Key> {
+        &self.instruments
+    }
+
+    pub fn len(&self) -> usize {
+        self.root
+            .as_ref()
+            .map_or(0, |root| root.summary.get().count)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Descends to the node of `key`, pushing the tags on the way
+    fn locate(&self, key: &Key) -> Option<Link<LazyTreapNode<Key, Value>>> {
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            self.instruments.count(|counters| {
+                counters.visits += 1;
+                counters.comparisons += 1;
+            });
+            node.push_down();
+            match node.get_direction_of_value(key) {
+                Some(direction) => maybe_node = node.get_child(direction),
+                None => return Some(node),
+            }
+        }
+        None
+    }
+
+    /// Rotates `node` up while its parent has a lower priority
+    fn sift_up(&mut self, node: &Link<LazyTreapNode<Key, Value>>) {
+        while node
+            .get_parent()
+            .is_some_and(|parent| parent.priority < node.priority)
+        {
+            let direction = rotate_up(node);
+            self.count_rotation(node, direction);
+        }
+        if node.is_root() {
+            self.root = Some(node.clone());
+        }
+    }
+
+    /// Reports that `pivot` was rotated up, its parent going `direction`
+    fn count_rotation(&self, pivot: &Link<LazyTreapNode<Key, Value>>, direction: Direction) {
+        // Three links are replaced, both ways
+        self.instruments.count(|counters| {
+            counters.rotations += 1;
+            counters.pointer_updates += 6;
+        });
+        self.instruments.emit(|| Event::Rotated {
+            pivot: pivot.as_value(),
+            direction,
+        });
+    }
+
+    /// Takes `node`, the tags above which are pushed, out of the tree,
+    /// rotating it down to a leaf first
+    fn remove_node(&mut self, node: Link<LazyTreapNode<Key, Value>>) -> (Key, Value) {
+        let replacement = sink_to_leaf(
+            &node,
+            |node| node.priority,
+            |pivot, direction| self.count_rotation(pivot, direction),
+        );
+        if let Some(replacement) = replacement {
+            if replacement.is_root() {
+                self.root = Some(replacement);
+            }
+        }
+        match node.get_parent() {
+            Some(parent) => {
+                parent.set_child(child_direction(&parent, &node), None);
+                node.set_parent(None);
+                self.instruments
+                    .count(|counters| counters.pointer_updates += 2);
+                pull_path(Some(parent));
+            }
+            None => self.root = None,
+        }
+        self.take_entry(node)
+    }
+
+    /// Consumes `node`, which is already unlinked, reporting its deletion
+    fn take_entry(&self, node: Link<LazyTreapNode<Key, Value>>) -> (Key, Value) {
+        self.instruments.emit(|| Event::Deleted(node.as_value()));
+        let value = node.value.get();
+        match Rc::try_unwrap(node) {
+            Ok(node) => (node.into_value(), value),
+            Err(_) => unreachable!("The treap hands out no links to its nodes"),
+        }
+    }
+
+    /// Cuts the keys in `range` out of the treap with two splits, and
+    /// returns them as a treap of their own
+    fn cut_range<R: RangeBounds<Key>>(
+        &mut self,
+        range: &R,
+    ) -> Option<Link<LazyTreapNode<Key, Value>>> {
+        let instruments = &self.instruments;
+        let mut before_start = |node: &Link<LazyTreapNode<Key, Value>>| {
+            instruments.count(|counters| {
+                counters.visits += 1;
+                counters.comparisons += 1;
+            });
+            match range.start_bound() {
+                Bound::Included(start) => node.key < *start,
+                Bound::Excluded(start) => node.key <= *start,
+                Bound::Unbounded => false,
+            }
+        };
+        let (left, rest) = split_by(self.root.take(), &mut before_start);
+        let mut not_after_end = |node: &Link<LazyTreapNode<Key, Value>>| {
+            instruments.count(|counters| {
+                counters.visits += 1;
+                counters.comparisons += 1;
+            });
+            match range.end_bound() {
+                Bound::Included(end) => node.key <= *end,
+                Bound::Excluded(end) => node.key < *end,
+                Bound::Unbounded => true,
+            }
+        };
+        let (middle, right) = split_by(rest, &mut not_after_end);
+        self.root = merge_by(left, right, &|node| node.priority);
+        middle
+    }
+
+    /// Sets the value of `key`, returning the previous one
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        let mut maybe_parent_and_direction = None;
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            self.instruments.count(|counters| {
+                counters.visits += 1;
+                counters.comparisons += 1;
+            });
+            node.push_down();
+            match node.get_direction_of_value(&key) {
+                Some(direction) => {
+                    maybe_node = node.get_child(direction);
+                    maybe_parent_and_direction = Some((node, direction));
+                }
+                None => {
+                    let previous = node.value.replace(value);
+                    pull_path(Some(node));
+                    return Some(previous);
+                }
+            }
+        }
+
+        let node = Link::new(LazyTreapNode::with_priority(key, value, self.rng.gen()));
+        self.instruments.count(|counters| counters.allocations += 1);
+        if let Some((parent, direction)) = maybe_parent_and_direction {
+            link(&parent, direction, Some(node.clone()));
+            self.instruments
+                .count(|counters| counters.pointer_updates += 2);
+        }
+        self.instruments.emit(|| Event::Inserted(node.as_value()));
+        self.sift_up(&node);
+        pull_path(node.get_parent());
+        None
+    }
+
+    /// Removes `key`, returning its value
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let node = self.locate(key)?;
+        Some(self.remove_node(node).1)
+    }
+
+    /// Removes the keys in `range`, returning how many there were
+    ///
+    /// Cuts the range out with two splits, so this takes O(log n + k)
+    /// expected time for k removed keys.
+    pub fn remove_range<R: RangeBounds<Key>>(&mut self, range: R) -> usize {
+        self.drain_range(range).len()
+    }
+
+    /// Removes the keys in `range`, returning them with their values in
+    /// order
+    pub fn drain_range<R: RangeBounds<Key>>(&mut self, range: R) -> Vec<(Key, Value)> {
+        let mut nodes = vec![];
+        into_nodes(self.cut_range(&range), &mut nodes);
+        nodes
+            .into_iter()
+            .map(|node| self.take_entry(node))
+            .collect()
+    }
+
+    /// Removes the keys, for which `predicate` does not hold with their
+    /// values
+    ///
+    /// Takes the treap apart and merges the kept nodes back together.
+    pub fn retain<F: FnMut(&Key, &Value) -> bool>(&mut self, mut predicate: F) {
+        let mut nodes = vec![];
+        into_nodes(self.root.take(), &mut nodes);
+        for node in nodes {
+            if predicate(node.as_value(), &node.value.get()) {
+                self.root = merge_by(self.root.take(), Some(node), &|node| node.priority);
+            } else {
+                self.take_entry(node);
+            }
+        }
+    }
+
+    /// Returns the value of `key`
+    pub fn get(&self, key: &Key) -> Option<Value> {
+        self.locate(key).map(|node| node.value.get())
+    }
+
+    /// Adds `delta` to the values of all keys in `range`
+    pub fn add_range(&mut self, range: RangeInclusive<Key>, delta: Value) {
+        let tag = Tag {
+            assign: None,
+            add: delta,
+        };
+        if let Some(root) = self.root.as_ref() {
+            update_range(root, &range, &tag, false, false);
+        }
+    }
+
+    /// Sets the values of all keys in `range` to `value`
+    pub fn assign_range(&mut self, range: RangeInclusive<Key>, value: Value) {
+        let tag = Tag {
+            assign: Some(value),
+            add: Value::zero(),
+        };
+        if let Some(root) = self.root.as_ref() {
+            update_range(root, &range, &tag, false, false);
+        }
+    }
+
+    /// Summarizes the values of all keys in `range`, `None` if there are
+    /// no such keys
+    pub fn summary(&self, range: RangeInclusive<Key>) -> Option<Summary<Value>> {
+        self.root
+            .as_ref()
+            .and_then(|root| query_range(root, &range, false, false))
+    }
+
+    pub fn sum(&self, range: RangeInclusive<Key>) -> Value {
+        self.summary(range)
+            .map_or(Value::zero(), |summary| summary.sum)
+    }
+
+    pub fn min(&self, range: RangeInclusive<Key>) -> Option<Value> {
+        self.summary(range).map(|summary| summary.min)
+    }
+
+    pub fn max(&self, range: RangeInclusive<Key>) -> Option<Value> {
+        self.summary(range).map(|summary| summary.max)
+    }
+
+    /// Iterates over the keys and their values in order
+    pub fn iter(&self) -> Iter<'_, Key, Value> {
+        let mut iter = Iter {
+            _treap: self,
+            stack: vec![],
+        };
+        iter.push_left_spine(self.root.clone());
+        iter
+    }
+}
+
+/// In-order iterator over the entries of a `LazyTreap`
+pub struct Iter<'a, Key: Ord, Value: Number> {
+    _treap: &'a LazyTreap<Key, Value>,
+    /// Nodes whose left subtrees have been visited, but they themselves
+    /// have not, with the tags of all their ancestors pushed
+    stack: Vec<Link<LazyTreapNode<Key, Value>>>,
+}
+
+impl<'a, Key: Ord, Value: Number> Iter<'a, Key, Value> {
+    fn push_left_spine(&mut self, mut maybe_node: Option<Link<LazyTreapNode<Key, Value>>>) {
+        while let Some(node) = maybe_node {
+            node.push_down();
+            maybe_node = node.get_child(Direction::Left);
+            self.stack.push(node);
+        }
+    }
+}
+
+impl<'a, Key: Ord, Value: Number> Iterator for Iter<'a, Key, Value> {
+    type Item = (&'a Key, Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.get_child(Direction::Right));
+        // Pushing tags relinks nothing, so the node stays in the treap,
+        // which is borrowed for `'a`
+        let key: &'a Key = unsafe { &*(node.as_value() as *const Key) };
+        Some((key, node.value.get()))
+    }
+}
+
+impl<Key: Ord, Value: Number> Default for LazyTreap<Key, Value> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Key: Ord, Value: Number> Drop for LazyTreap<Key, Value> {
+    fn drop(&mut self) {
+        dismantle(self.root.take());
+    }
+}
+
+impl<Key: Ord + Debug, Value: Number + Debug> Debug for LazyTreap<Key, Value> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::stats;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::BTreeMap;
+
+    type Node = LazyTreapNode<i64, i64>;
+
+    /// Checks the heap order, the key order, the parent links and the
+    /// summaries, taking the pending tags into account, and returns the
+    /// true subtree summary
+    fn check_invariants(node: &Link<Node>, pending: Tag<i64>) -> Summary<i64> {
+        let below = node.tag.get().then(pending);
+        let mut summary = Summary::of(pending.apply(node.value.get()));
+        if let Some(left_child) = node.get_child(Direction::Left) {
+            assert!(left_child.priority <= node.priority && left_child.key < node.key);
+            assert!(Rc::ptr_eq(&left_child.get_parent().unwrap(), node));
+            summary = check_invariants(&left_child, below).combine(summary);
+        }
+        if let Some(right_child) = node.get_child(Direction::Right) {
+            assert!(right_child.priority <= node.priority && right_child.key > node.key);
+            assert!(Rc::ptr_eq(&right_child.get_parent().unwrap(), node));
+            summary = summary.combine(check_invariants(&right_child, below));
+        }
+        assert_eq!(node.summary.get().apply(&pending), summary);
+        summary
+    }
+
+    #[test]
+    fn test_range_updates() {
+        let mut treap = LazyTreap::new();
+        for key in 0..10 {
+            treap.insert(key, key);
+        }
+        assert_eq!(treap.sum(0..=9), 45);
+        treap.add_range(3..=5, 10);
+        assert_eq!(treap.get(&4), Some(14));
+        assert_eq!(treap.sum(2..=6), 2 + 13 + 14 + 15 + 6);
+        treap.assign_range(5..=20, -1);
+        assert_eq!(treap.max(0..=9), Some(14));
+        assert_eq!(treap.min(0..=9), Some(-1));
+        assert_eq!(
+            treap.summary(4..=6),
+            Some(Summary {
+                count: 3,
+                sum: 12,
+                min: -1,
+                max: 14,
+            })
+        );
+        assert_eq!(treap.summary(10..=20), None);
+        assert_eq!(treap.sum(10..=20), 0);
+        assert_eq!(treap.insert(4, 0), Some(14));
+        assert_eq!(treap.remove(&5), Some(-1));
+        assert_eq!(treap.remove(&5), None);
+        assert_eq!(
+            format!("{:?}", treap),
+            "{0: 0, 1: 1, 2: 2, 3: 13, 4: 0, 6: -1, 7: -1, 8: -1, 9: -1}"
+        );
+    }
+
+    #[test]
+    fn test_bulk_removal() {
+        let mut treap = LazyTreap::new();
+        for key in 0..20 {
+            treap.insert(key, key);
+        }
+        treap.add_range(0..=9, 100);
+        assert_eq!(treap.remove_range(15..), 5);
+        assert_eq!(treap.remove_range(15..=30), 0);
+        assert_eq!(treap.remove_range(..0), 0);
+        // Pending additions reach the drained values
+        assert_eq!(
+            treap.drain_range(8..=11),
+            [(8, 108), (9, 109), (10, 10), (11, 11)]
+        );
+        treap.retain(|key, value| key % 2 == 0 || *value > 100);
+        assert_eq!(
+            format!("{:?}", treap),
+            "{0: 100, 1: 101, 2: 102, 3: 103, 4: 104, 5: 105, 6: 106, 7: 107, 12: 12, 14: 14}"
+        );
+        assert_eq!(treap.len(), 10);
+        assert_eq!(treap.sum(0..=20), 100 * 8 + 28 + 26);
+        check_invariants(treap.root.as_ref().unwrap(), Tag::identity());
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn test_range_removal_cost() {
+        let mut treap = LazyTreap::new();
+        for key in 0..10_000 {
+            treap.insert(key, 1);
+        }
+        treap.add_range(0..=9_999, 1);
+        treap.instruments().reset_counters();
+        assert_eq!(treap.remove_range(1_000..9_000), 8_000);
+        // Removing the keys one by one would rotate each of them down
+        let counters = treap.instruments().counters();
+        assert_eq!(counters.rotations, 0);
+        assert!(counters.visits < 200);
+        assert_eq!(treap.len(), 2_000);
+        assert_eq!(treap.sum(0..=9_999), 4_000);
+        check_invariants(treap.root.as_ref().unwrap(), Tag::identity());
+    }
+
+    #[test]
+    fn test_sorted_insertion_stays_balanced() {
+        let mut treap = LazyTreap::new();
+        for key in 0..1_000 {
+            treap.insert(key, 1);
+        }
+        treap.add_range(0..=499, 1);
+        for key in (0..1_000).step_by(2) {
+            treap.remove(&key);
+        }
+        let root = treap.root.clone();
+        check_invariants(root.as_ref().unwrap(), Tag::identity());
+        // Without rotations, sorted keys would make a path
+        assert!(stats::compute(root).height < 50);
+        assert_eq!(treap.sum(0..=999), 250 * 2 + 250);
+    }
+
+    #[test]
+    fn test_times() {
+        assert_eq!(3i64.times(0), 0);
+        assert_eq!(3i64.times(13), 39);
+        assert_eq!((-2i8).times(64), -128);
+        assert_eq!(0u8.times(1_000), 0);
+        assert_eq!(0.5f64.times(3), 1.5);
+    }
+
+    #[test]
+    fn test_floats() {
+        let mut treap: LazyTreap<u32, f64> = LazyTreap::new();
+        treap.insert(1, 0.5);
+        treap.insert(2, 1.5);
+        treap.add_range(0..=5, 0.25);
+        assert_eq!(treap.sum(0..=5), 2.5);
+        assert_eq!(treap.max(2..=2), Some(1.75));
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut treap = LazyTreap::new();
+        let mut model: BTreeMap<i64, i64> = BTreeMap::new();
+        for _ in 0..5_000 {
+            let a = rng.gen_range(0..200);
+            let b = rng.gen_range(0..200);
+            let (lo, hi) = (a.min(b), a.max(b));
+            let value = rng.gen_range(-100..100);
+            match rng.gen_range(0..12) {
+                0..=3 => assert_eq!(treap.insert(a, value), model.insert(a, value)),
+                4 => assert_eq!(treap.remove(&a), model.remove(&a)),
+                5 | 6 => {
+                    treap.add_range(lo..=hi, value);
+                    for v in model.range_mut(lo..=hi).map(|(_, v)| v) {
+                        *v += value;
+                    }
+                }
+                7 => {
+                    treap.assign_range(lo..=hi, value);
+                    for v in model.range_mut(lo..=hi).map(|(_, v)| v) {
+                        *v = value;
+                    }
+                }
+                8 => assert_eq!(treap.get(&a), model.get(&a).cloned()),
+                _ => {
+                    let values: Vec<i64> = model.range(lo..=hi).map(|(_, v)| *v).collect();
+                    let expected = values.iter().min().map(|&min| Summary {
+                        count: values.len(),
+                        sum: values.iter().sum(),
+                        min,
+                        max: *values.iter().max().unwrap(),
+                    });
+                    assert_eq!(treap.summary(lo..=hi), expected);
+                }
+            }
+            assert_eq!(treap.len(), model.len());
+            if let Some(root) = treap.root.as_ref() {
+                check_invariants(root, Tag::identity());
+            }
+        }
+        let entries: Vec<(i64, i64)> = treap.iter().map(|(k, v)| (*k, v)).collect();
+        assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+    }
+}
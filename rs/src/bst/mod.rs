@@ -1,2 +1,18 @@
 pub mod base;
+pub mod cursor;
+pub mod frozen;
+pub mod handle;
+pub mod implicit;
+pub mod instrument;
+pub mod interval;
+pub mod lazy;
+pub mod model;
+pub mod persistent;
+pub mod pretty;
+pub mod priorityqueue;
+pub mod rope;
+pub mod serialize;
 pub mod simple;
+pub mod stats;
+pub mod trace;
+pub mod traversal;
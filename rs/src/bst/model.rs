@@ -0,0 +1,304 @@
+//! Differential testing of `Bst` implementations against `BTreeSet`
+//!
+//! Random operation sequences are applied both to a tree and to a
+//! `BTreeSet`, which serves as the model. When the two disagree, the
+//! sequence is shrunk to a minimal one that still makes them disagree.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+
+use crate::bst::base::{Bst, BstNode};
+use crate::bst::handle::HandleError;
+
+/// A single operation on a set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op<V> {
+    Insert(V),
+    Delete(V),
+    Find(V),
+    /// Collect all values in order
+    Iter,
+}
+
+/// The observable result of an `Op`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<V> {
+    Inserted,
+    Deleted(Option<V>),
+    Found(Option<V>),
+    Values(Vec<V>),
+    /// The tree refused the operation, which the model never does
+    Failed(HandleError),
+}
+
+/// The first operation of a sequence on which a tree disagreed with the model
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence<V> {
+    pub step: usize,
+    pub op: Op<V>,
+    pub expected: Outcome<V>,
+    pub actual: Outcome<V>,
+}
+
+/// Generates `len` random operations over the values `0..max_value`
+pub fn generate_ops<R: Rng>(rng: &mut R, len: usize, max_value: u32) -> Vec<Op<u32>> {
+    (0..len)
+        .map(|_| {
+            let value = rng.gen_range(0..max_value);
+            match rng.gen_range(0..20) {
+                0..=7 => Op::Insert(value),
+                8..=13 => Op::Delete(value),
+                14..=18 => Op::Find(value),
+                _ => Op::Iter,
+            }
+        })
+        .collect()
+}
+
+fn apply_to_model<V: Ord + Clone>(model: &mut BTreeSet<V>, op: &Op<V>) -> Outcome<V> {
+    match op {
+        Op::Insert(v) => {
+            model.insert(v.clone());
+            Outcome::Inserted
+        }
+        Op::Delete(v) => Outcome::Deleted(model.take(v)),
+        Op::Find(v) => Outcome::Found(model.get(v).cloned()),
+        Op::Iter => Outcome::Values(model.iter().cloned().collect()),
+    }
+}
+
+pub(crate) fn apply_to_tree<V, Tree>(tree: &mut Tree, op: &Op<V>) -> Outcome<V>
+where
+    V: Ord + Clone,
+    Tree: Bst,
+    Tree::Node: BstNode<Value = V>,
+{
+    match op {
+        Op::Insert(v) => {
+            tree.insert(v.clone());
+            Outcome::Inserted
+        }
+        Op::Delete(v) => match tree.delete(v) {
+            Ok(deleted) => Outcome::Deleted(deleted),
+            Err(error) => Outcome::Failed(error),
+        },
+        Op::Find(v) => Outcome::Found(tree.find(v).and_then(|handle| tree.get(&handle).cloned())),
+        Op::Iter => Outcome::Values(tree.iter().cloned().collect()),
+    }
+}
+
+/// Applies `ops` to a fresh tree and to the model, stopping at the first
+/// operation with differing outcomes
+pub fn run<V, Tree, F>(new_tree: F, ops: &[Op<V>]) -> Result<(), Divergence<V>>
+where
+    V: Ord + Clone,
+    Tree: Bst,
+    Tree::Node: BstNode<Value = V>,
+    F: Fn() -> Tree,
+{
+    let mut tree = new_tree();
+    let mut model = BTreeSet::new();
+    for (step, op) in ops.iter().enumerate() {
+        let expected = apply_to_model(&mut model, op);
+        let actual = apply_to_tree(&mut tree, op);
+        if expected != actual {
+            return Err(Divergence {
+                step,
+                op: op.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+    // The final state has to match even if the sequence never looked at it
+    let expected = apply_to_model(&mut model, &Op::Iter);
+    let actual = apply_to_tree(&mut tree, &Op::Iter);
+    if expected != actual {
+        return Err(Divergence {
+            step: ops.len(),
+            op: Op::Iter,
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Shrinks a diverging sequence by removing ever smaller chunks of
+/// operations for as long as the result still diverges
+///
+/// The result is minimal in the sense that removing any single operation
+/// from it makes the divergence go away.
+pub fn shrink<V, Tree, F>(new_tree: F, ops: Vec<Op<V>>) -> Vec<Op<V>>
+where
+    V: Ord + Clone,
+    Tree: Bst,
+    Tree::Node: BstNode<Value = V>,
+    F: Fn() -> Tree,
+{
+    let mut ops = match run(&new_tree, &ops) {
+        Ok(()) => return ops,
+        // Nothing after the diverging operation matters
+        Err(divergence) => ops[..(divergence.step + 1).min(ops.len())].to_vec(),
+    };
+    let mut chunk = (ops.len() / 2).max(1);
+    loop {
+        let mut removed_any = false;
+        let mut start = 0;
+        while start < ops.len() {
+            let end = (start + chunk).min(ops.len());
+            let mut candidate = ops[..start].to_vec();
+            candidate.extend_from_slice(&ops[end..]);
+            if run(&new_tree, &candidate).is_err() {
+                ops = candidate;
+                removed_any = true;
+            } else {
+                start += chunk;
+            }
+        }
+        if chunk == 1 && !removed_any {
+            return ops;
+        }
+        if !removed_any {
+            chunk = (chunk / 2).max(1);
+        }
+    }
+}
+
+/// Runs `cases` random sequences of `len` operations, derived from `seed`,
+/// against trees made by `new_tree`
+///
+/// Panics with the minimal diverging sequence on the first failure.
+pub fn check<Tree, F>(new_tree: F, seed: u64, cases: usize, len: usize)
+where
+    Tree: Bst,
+    Tree::Node: BstNode<Value = u32>,
+    F: Fn() -> Tree,
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+    for case in 0..cases {
+        // A small value range makes deletes and finds hit existing values
+        let max_value = rng.gen_range(1..(len as u32 / 2).max(2));
+        let ops = generate_ops(&mut rng, len, max_value);
+        if run(&new_tree, &ops).is_err() {
+            let minimal = shrink(&new_tree, ops);
+            let divergence = run(&new_tree, &minimal).err();
+            panic!("{}", report(seed, case, &minimal, divergence.as_ref()));
+        }
+    }
+}
+
+fn report<V: Debug>(
+    seed: u64,
+    case: usize,
+    ops: &[Op<V>],
+    divergence: Option<&Divergence<V>>,
+) -> String {
+    let mut message = format!(
+        "Tree diverged from the model (seed {}, case {}), minimal sequence:\n",
+        seed, case
+    );
+    for (step, op) in ops.iter().enumerate() {
+        message.push_str(&format!("  {:>3}: {:?}\n", step, op));
+    }
+    match divergence {
+        Some(d) => message.push_str(&format!(
+            "step {} ({:?}): expected {:?}, got {:?}",
+            d.step, d.op, d.expected, d.actual
+        )),
+        // Shrinking ran into a nondeterministic tree, which is a finding too
+        None => message.push_str("the minimal sequence no longer diverges when re-run"),
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::base::Link;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+    #[test]
+    fn test_simple_bst_matches_model() {
+        for seed in 0..8 {
+            check(SimpleBst::<SimpleBstNode<u32>>::new, seed, 50, 200);
+        }
+    }
+
+    #[test]
+    fn test_generate_ops_is_deterministic() {
+        let a = generate_ops(&mut StdRng::seed_from_u64(7), 100, 10);
+        let b = generate_ops(&mut StdRng::seed_from_u64(7), 100, 10);
+        assert_eq!(a, b);
+        assert!(a.iter().all(|op| match op {
+            Op::Insert(v) | Op::Delete(v) | Op::Find(v) => *v < 10,
+            Op::Iter => true,
+        }));
+    }
+
+    /// A tree that silently refuses to delete 7
+    struct ForgetfulBst(SimpleBst<SimpleBstNode<u32>>);
+
+    impl Bst for ForgetfulBst {
+        type Node = SimpleBstNode<u32>;
+
+        fn get_root(&self) -> Option<Link<Self::Node>> {
+            self.0.get_root()
+        }
+
+        fn insert(&mut self, value: u32) {
+            self.0.insert(value)
+        }
+
+        fn delete_node(&mut self, node: Link<Self::Node>) -> Result<u32, HandleError> {
+            self.0.delete_node(node)
+        }
+
+        fn delete(&mut self, value: &u32) -> Result<Option<u32>, HandleError> {
+            if *value == 7 {
+                Ok(None)
+            } else {
+                self.0.delete(value)
+            }
+        }
+    }
+
+    fn new_forgetful() -> ForgetfulBst {
+        ForgetfulBst(SimpleBst::new())
+    }
+
+    #[test]
+    fn test_run_reports_divergence() {
+        let ops = vec![Op::Insert(7), Op::Find(7), Op::Delete(7), Op::Find(7)];
+        assert_eq!(
+            run(new_forgetful, &ops),
+            Err(Divergence {
+                step: 2,
+                op: Op::Delete(7),
+                expected: Outcome::Deleted(Some(7)),
+                actual: Outcome::Deleted(None),
+            })
+        );
+        assert_eq!(run(new_forgetful, &ops[..2]), Ok(()));
+    }
+
+    #[test]
+    fn test_shrink_finds_minimal_sequence() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut ops = generate_ops(&mut rng, 300, 10);
+        ops.push(Op::Insert(7));
+        ops.push(Op::Delete(7));
+        assert_eq!(
+            shrink(new_forgetful, ops),
+            vec![Op::Insert(7), Op::Delete(7)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "step 1 (Delete(7)): expected Deleted(Some(7)), got Deleted(None)")]
+    fn test_check_panics_with_minimal_sequence() {
+        check(new_forgetful, 0, 10, 100);
+    }
+}
@@ -0,0 +1,407 @@
+//! An immutable ordered set, where every update returns a new version
+//!
+//! Like `persistentlist::List`, versions share their nodes through `Rc`s:
+//! an update copies only the O(log n) nodes on the path to the changed
+//! one, while all other subtrees are shared with the old version. The
+//! tree is kept AVL balanced, so paths stay short whatever the order of
+//! updates.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+use crate::bst::base::Direction;
+
+type Tree<T> = Option<Rc<PersistentNode<T>>>;
+
+struct PersistentNode<T> {
+    value: T,
+    /// Number of levels in the subtree rooted at this node
+    height: usize,
+    /// Number of nodes in the subtree rooted at this node
+    size: usize,
+    left_child: Tree<T>,
+    right_child: Tree<T>,
+}
+
+/// A persistent set, cloning which is O(1)
+///
+/// Values get cloned when the nodes holding them are copied.
+pub struct PersistentSet<T> {
+    root: Tree<T>,
+}
+
+fn height<T>(tree: &Tree<T>) -> usize {
+    tree.as_ref().map_or(0, |node| node.height)
+}
+
+fn size<T>(tree: &Tree<T>) -> usize {
+    tree.as_ref().map_or(0, |node| node.size)
+}
+
+impl<T> PersistentNode<T> {
+    fn get_child(&self, direction: Direction) -> &Tree<T> {
+        match direction {
+            Direction::Left => &self.left_child,
+            Direction::Right => &self.right_child,
+        }
+    }
+}
+
+/// A fresh node over existing subtrees, which have to be balanced
+fn node<T>(value: T, left_child: Tree<T>, right_child: Tree<T>) -> Rc<PersistentNode<T>> {
+    Rc::new(PersistentNode {
+        value,
+        height: 1 + height(&left_child).max(height(&right_child)),
+        size: 1 + size(&left_child) + size(&right_child),
+        left_child,
+        right_child,
+    })
+}
+
+/// A fresh node over subtrees, which may differ in height by 2 at most,
+/// rotating as needed to get the heights within 1
+fn balance<T: Clone>(value: T, left_child: Tree<T>, right_child: Tree<T>) -> Rc<PersistentNode<T>> {
+    let (left_height, right_height) = (height(&left_child), height(&right_child));
+    if left_height > right_height + 1 {
+        let left = left_child.expect("The higher subtree is not empty");
+        if height(&left.left_child) >= height(&left.right_child) {
+            // Single right rotation
+            node(
+                left.value.clone(),
+                left.left_child.clone(),
+                Some(node(value, left.right_child.clone(), right_child)),
+            )
+        } else {
+            // Left-right double rotation
+            let inner = left
+                .right_child
+                .as_ref()
+                .expect("The higher subtree is not empty");
+            node(
+                inner.value.clone(),
+                Some(node(
+                    left.value.clone(),
+                    left.left_child.clone(),
+                    inner.left_child.clone(),
+                )),
+                Some(node(value, inner.right_child.clone(), right_child)),
+            )
+        }
+    } else if right_height > left_height + 1 {
+        let right = right_child.expect("The higher subtree is not empty");
+        if height(&right.right_child) >= height(&right.left_child) {
+            // Single left rotation
+            node(
+                right.value.clone(),
+                Some(node(value, left_child, right.left_child.clone())),
+                right.right_child.clone(),
+            )
+        } else {
+            // Right-left double rotation
+            let inner = right
+                .left_child
+                .as_ref()
+                .expect("The higher subtree is not empty");
+            node(
+                inner.value.clone(),
+                Some(node(value, left_child, inner.left_child.clone())),
+                Some(node(
+                    right.value.clone(),
+                    inner.right_child.clone(),
+                    right.right_child.clone(),
+                )),
+            )
+        }
+    } else {
+        node(value, left_child, right_child)
+    }
+}
+
+/// `tree` with `value` added, or `None` if it is there already
+fn insert<T: Ord + Clone>(tree: &Tree<T>, value: T) -> Option<Rc<PersistentNode<T>>> {
+    let node = match tree {
+        None => return Some(node(value, None, None)),
+        Some(node) => node,
+    };
+    match value.cmp(&node.value) {
+        Ordering::Equal => None,
+        Ordering::Less => insert(&node.left_child, value).map(|left_child| {
+            balance(
+                node.value.clone(),
+                Some(left_child),
+                node.right_child.clone(),
+            )
+        }),
+        Ordering::Greater => insert(&node.right_child, value).map(|right_child| {
+            balance(
+                node.value.clone(),
+                node.left_child.clone(),
+                Some(right_child),
+            )
+        }),
+    }
+}
+
+/// The subtree of `node` without its smallest value, and that value
+fn remove_min<T: Clone>(node: &PersistentNode<T>) -> (Tree<T>, T) {
+    match node.left_child.as_ref() {
+        None => (node.right_child.clone(), node.value.clone()),
+        Some(left_child) => {
+            let (left_child, min) = remove_min(left_child);
+            (
+                Some(balance(
+                    node.value.clone(),
+                    left_child,
+                    node.right_child.clone(),
+                )),
+                min,
+            )
+        }
+    }
+}
+
+/// `tree` without `value`, or `None` if it is not there
+fn remove<T: Ord + Clone>(tree: &Tree<T>, value: &T) -> Option<Tree<T>> {
+    let node = tree.as_ref()?;
+    match value.cmp(&node.value) {
+        Ordering::Less => remove(&node.left_child, value).map(|left_child| {
+            Some(balance(
+                node.value.clone(),
+                left_child,
+                node.right_child.clone(),
+            ))
+        }),
+        Ordering::Greater => remove(&node.right_child, value).map(|right_child| {
+            Some(balance(
+                node.value.clone(),
+                node.left_child.clone(),
+                right_child,
+            ))
+        }),
+        Ordering::Equal => Some(match (&node.left_child, &node.right_child) {
+            (None, child) | (child, None) => child.clone(),
+            (Some(_), Some(right_child)) => {
+                // The successor takes the place of the removed value
+                let (right_child, successor) = remove_min(right_child);
+                Some(balance(successor, node.left_child.clone(), right_child))
+            }
+        }),
+    }
+}
+
+impl<T: Ord + Clone> PersistentSet<T> {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// A version with `value` added, sharing all untouched subtrees
+    /// with `self`
+    pub fn insert(&self, value: T) -> Self {
+        match insert(&self.root, value) {
+            Some(root) => Self { root: Some(root) },
+            None => self.clone(),
+        }
+    }
+
+    /// A version without `value`, sharing all untouched subtrees with
+    /// `self`
+    pub fn remove(&self, value: &T) -> Self {
+        match remove(&self.root, value) {
+            Some(root) => Self { root },
+            None => self.clone(),
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        let mut maybe_node = self.root.as_ref();
+        while let Some(node) = maybe_node {
+            let direction = match value.cmp(&node.value) {
+                Ordering::Less => Direction::Left,
+                Ordering::Greater => Direction::Right,
+                Ordering::Equal => return true,
+            };
+            maybe_node = node.get_child(direction).as_ref();
+        }
+        false
+    }
+
+    /// Checks whether both versions are the very same one, in O(1)
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: vec![] };
+        iter.push_left_spine(self.root.as_deref());
+        iter
+    }
+}
+
+/// In-order iterator over the values of a `PersistentSet`
+pub struct Iter<'a, T> {
+    /// Nodes whose left subtrees have been visited, but they themselves
+    /// have not
+    stack: Vec<&'a PersistentNode<T>>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn push_left_spine(&mut self, mut maybe_node: Option<&'a PersistentNode<T>>) {
+        while let Some(node) = maybe_node {
+            self.stack.push(node);
+            maybe_node = node.left_child.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.right_child.as_deref());
+        Some(&node.value)
+    }
+}
+
+impl<'a, T: Ord + Clone> IntoIterator for &'a PersistentSet<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> Clone for PersistentSet<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for PersistentSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for PersistentSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |set, value| set.insert(value))
+    }
+}
+
+impl<T: Ord + Clone + Debug> Debug for PersistentSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeSet, HashSet};
+
+    /// Checks the AVL balance and the cached heights and sizes
+    fn check_invariants<T: Ord>(tree: &Tree<T>) {
+        if let Some(node) = tree {
+            check_invariants(&node.left_child);
+            check_invariants(&node.right_child);
+            let (left_height, right_height) = (height(&node.left_child), height(&node.right_child));
+            assert!(left_height <= right_height + 1 && right_height <= left_height + 1);
+            assert_eq!(node.height, 1 + left_height.max(right_height));
+            assert_eq!(
+                node.size,
+                1 + size(&node.left_child) + size(&node.right_child)
+            );
+        }
+    }
+
+    /// Addresses of all nodes of a version
+    fn nodes<T>(tree: &Tree<T>, out: &mut HashSet<*const PersistentNode<T>>) {
+        if let Some(node) = tree {
+            out.insert(&**node as *const _);
+            nodes(&node.left_child, out);
+            nodes(&node.right_child, out);
+        }
+    }
+
+    #[test]
+    fn test_versions() {
+        let empty = PersistentSet::new();
+        let v1 = empty.insert(2).insert(1).insert(3);
+        let v2 = v1.remove(&2);
+        let v3 = v2.insert(4);
+        assert!(empty.is_empty());
+        assert_eq!(v1.iter().cloned().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(v2.iter().cloned().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(v3.iter().cloned().collect::<Vec<_>>(), [1, 3, 4]);
+        assert!(v1.contains(&2) && !v2.contains(&2));
+        assert_eq!(v3.len(), 3);
+        // No-op updates return the same version
+        assert!(v3.insert(4).ptr_eq(&v3));
+        assert!(v3.remove(&2).ptr_eq(&v3));
+        assert!(!v3.insert(5).ptr_eq(&v3));
+        assert_eq!(format!("{:?}", v2), "{1, 3}");
+    }
+
+    #[test]
+    fn test_structural_sharing() {
+        let old: PersistentSet<u32> = (0..1_000).collect();
+        check_invariants(&old.root);
+        let new = old.insert(500).remove(&10).insert(5_000);
+        let mut old_nodes = HashSet::new();
+        let mut new_nodes = HashSet::new();
+        nodes(&old.root, &mut old_nodes);
+        nodes(&new.root, &mut new_nodes);
+        // Sorted insertion would make an unbalanced tree 1000 levels deep
+        assert!(old.root.as_ref().unwrap().height <= 15);
+        let copied = new_nodes.difference(&old_nodes).count();
+        assert!(copied <= 2 * 15, "{} nodes copied", copied);
+        assert_eq!(old.len(), 1_000);
+        assert_eq!(new.len(), 1_000);
+        assert!(old.contains(&10) && !new.contains(&10));
+    }
+
+    #[test]
+    fn test_matches_btreeset() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut versions = vec![(PersistentSet::new(), BTreeSet::new())];
+        for _ in 0..2_000 {
+            // Update a random earlier version, which all have to stay intact
+            let (set, mut model) = versions[rng.gen_range(0..versions.len())].clone();
+            let value = rng.gen_range(0..100);
+            let set = if rng.gen_range(0..3) == 0 {
+                model.remove(&value);
+                set.remove(&value)
+            } else {
+                model.insert(value);
+                set.insert(value)
+            };
+            check_invariants(&set.root);
+            versions.push((set, model));
+        }
+        for (set, model) in versions.iter() {
+            assert!(set.iter().eq(model.iter()));
+            assert_eq!(set.len(), model.len());
+        }
+    }
+}
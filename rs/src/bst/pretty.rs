@@ -0,0 +1,305 @@
+use std::fmt::{self, Display};
+
+use crate::bst::base::{Bst, BstNode, Direction, Link};
+
+/// The way `Pretty` lays a tree out
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layout {
+    /// Root on the left, right subtree above and left subtree below it,
+    /// one node per line. Lines longer than the width are cut.
+    Sideways,
+    /// Root on top, every level on its own line. Level `d` splits the
+    /// width into `2^d` slots, so levels whose slots would be narrower
+    /// than a single character are omitted.
+    TopDown,
+}
+
+/// A `Display` wrapper rendering the whole shape of a `Bst` with
+/// box-drawing characters
+pub struct Pretty<'a, Tree: Bst + ?Sized> {
+    tree: &'a Tree,
+    layout: Layout,
+    width: usize,
+}
+
+impl<'a, Tree: Bst + ?Sized> Pretty<'a, Tree> {
+    /// Sideways rendering of `tree`, 80 characters wide
+    pub fn new(tree: &'a Tree) -> Self {
+        Self {
+            tree,
+            layout: Layout::Sideways,
+            width: 80,
+        }
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+}
+
+/// Cuts `s` to at most `width` characters, marking the cut with an ellipsis
+fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        s.to_string()
+    } else if width == 0 {
+        String::new()
+    } else {
+        let mut cut: String = s.chars().take(width - 1).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+/// Position of a node relative to its parent
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Root,
+    Child(Direction),
+}
+
+enum SidewaysTask<Node> {
+    Visit(Link<Node>, String, Position),
+    Emit(String),
+}
+
+fn sideways<Node: BstNode>(root: Link<Node>, width: usize) -> Vec<String>
+where
+    Node::Value: Display,
+{
+    let mut lines = vec![];
+    let mut tasks = vec![SidewaysTask::Visit(root, String::new(), Position::Root)];
+    while let Some(task) = tasks.pop() {
+        match task {
+            SidewaysTask::Emit(line) => lines.push(truncate(&line, width)),
+            SidewaysTask::Visit(node, prefix, position) => {
+                let connector = match position {
+                    Position::Root => "",
+                    Position::Child(Direction::Right) => "┌── ",
+                    Position::Child(Direction::Left) => "└── ",
+                };
+                // A child on the far side of its parent needs a vertical bar
+                // in its prefix, linking the parent to the grandparent
+                let child_prefix = |direction: Direction| match position {
+                    Position::Child(d) if d != direction => format!("{}│   ", prefix),
+                    Position::Child(_) => format!("{}    ", prefix),
+                    Position::Root => prefix.clone(),
+                };
+                // Tasks are popped in reverse: right subtree, node, left subtree
+                if let Some(left) = node.get_child(Direction::Left) {
+                    tasks.push(SidewaysTask::Visit(
+                        left,
+                        child_prefix(Direction::Left),
+                        Position::Child(Direction::Left),
+                    ));
+                }
+                tasks.push(SidewaysTask::Emit(format!(
+                    "{}{}{}",
+                    prefix,
+                    connector,
+                    node.as_value()
+                )));
+                if let Some(right) = node.get_child(Direction::Right) {
+                    tasks.push(SidewaysTask::Visit(
+                        right,
+                        child_prefix(Direction::Right),
+                        Position::Child(Direction::Right),
+                    ));
+                }
+            }
+        }
+    }
+    lines
+}
+
+fn top_down<Node: BstNode>(root: Link<Node>, width: usize) -> Vec<String>
+where
+    Node::Value: Display,
+{
+    // Slot `i` of level `d` spans the columns `[i * width / 2^d, (i + 1) * width / 2^d)`
+    let slot = |depth: u32, i: usize| ((i * width) >> depth, ((i + 1) * width) >> depth);
+    let center = |depth: u32, i: usize| {
+        let (start, end) = slot(depth, i);
+        (start + end) / 2
+    };
+
+    let mut rows: Vec<Vec<char>> = vec![];
+    let mut level: Vec<(usize, Link<Node>)> = vec![(0, root)];
+    let mut depth: u32 = 0;
+    while !level.is_empty() {
+        if depth >= usize::BITS - 1 || width >> depth == 0 {
+            rows.push("(deeper levels omitted)".chars().collect());
+            break;
+        }
+        let mut labels = vec![' '; width];
+        let mut connectors = vec![' '; width];
+        let mut next_level = vec![];
+        for (i, node) in level {
+            let (start, end) = slot(depth, i);
+            let label: Vec<char> = truncate(&node.as_value().to_string(), end - start)
+                .chars()
+                .collect();
+            let label_start = (center(depth, i).saturating_sub(label.len() / 2)).max(start);
+            labels[label_start..label_start + label.len()].copy_from_slice(&label);
+
+            let left = node.get_child(Direction::Left);
+            let right = node.get_child(Direction::Right);
+            let c = center(depth, i);
+            let from = if left.is_some() {
+                center(depth + 1, 2 * i)
+            } else {
+                c
+            };
+            let to = if right.is_some() {
+                center(depth + 1, 2 * i + 1)
+            } else {
+                c
+            };
+            if from == to {
+                // Either a leaf, or the child slots are too narrow to branch
+                if left.is_some() || right.is_some() {
+                    connectors[c] = '│';
+                }
+            } else {
+                for cell in connectors[from..=to].iter_mut() {
+                    *cell = '─';
+                }
+                connectors[c] = match (left.is_some(), right.is_some()) {
+                    (true, true) => '┴',
+                    (true, false) => '┘',
+                    _ => '└',
+                };
+                if left.is_some() {
+                    connectors[from] = '┌';
+                }
+                if right.is_some() {
+                    connectors[to] = '┐';
+                }
+            }
+            next_level.extend(left.map(|left| (2 * i, left)));
+            next_level.extend(right.map(|right| (2 * i + 1, right)));
+        }
+        rows.push(labels);
+        if !next_level.is_empty() {
+            rows.push(connectors);
+        }
+        level = next_level;
+        depth += 1;
+    }
+    rows.into_iter()
+        .map(|row| row.into_iter().collect::<String>().trim_end().to_string())
+        .collect()
+}
+
+impl<'a, Tree: Bst + ?Sized> Display for Pretty<'a, Tree>
+where
+    <Tree::Node as BstNode>::Value: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let root = match self.tree.get_root() {
+            Some(root) => root,
+            None => return writeln!(f, "(empty)"),
+        };
+        let lines = match self.layout {
+            Layout::Sideways => sideways(root, self.width),
+            Layout::TopDown => top_down(root, self.width),
+        };
+        for line in lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+    fn build<V: PartialEq + PartialOrd>(values: Vec<V>) -> SimpleBst<SimpleBstNode<V>> {
+        let mut bst = SimpleBst::<SimpleBstNode<V>>::new();
+        for v in values {
+            bst.insert(v);
+        }
+        bst
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let bst = build::<u32>(vec![]);
+        assert_eq!(Pretty::new(&bst).to_string(), "(empty)\n");
+        assert_eq!(
+            Pretty::new(&bst).layout(Layout::TopDown).to_string(),
+            "(empty)\n"
+        );
+    }
+
+    #[test]
+    fn test_sideways() {
+        let bst = build(vec![3, 5, 1, 4, 6, 2]);
+        let expected = "    ┌── 6
+┌── 5
+│   └── 4
+3
+│   ┌── 2
+└── 1
+";
+        assert_eq!(Pretty::new(&bst).to_string(), expected);
+    }
+
+    #[test]
+    fn test_sideways_width() {
+        let bst = build(vec!["b", "a", "clementine"]);
+        let expected = "┌── clem…
+b
+└── a
+";
+        assert_eq!(Pretty::new(&bst).width(9).to_string(), expected);
+    }
+
+    #[test]
+    fn test_top_down() {
+        //   2
+        //  / \
+        // 1   4
+        //    /
+        //   3
+        let bst = build(vec![2, 4, 1, 3]);
+        let expected = "        2
+    ┌───┴───┐
+    1       4
+          ┌─┘
+          3
+";
+        assert_eq!(
+            Pretty::new(&bst)
+                .layout(Layout::TopDown)
+                .width(16)
+                .to_string(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_top_down_omits_levels_narrower_than_a_character() {
+        let bst = build(vec![1, 2, 3, 4]);
+        let expected = " 1
+ └┐
+  2
+  │
+(deeper levels omitted)
+";
+        assert_eq!(
+            Pretty::new(&bst)
+                .layout(Layout::TopDown)
+                .width(3)
+                .to_string(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! A double-ended priority queue on top of any `Bst`, `SimpleBst` by
+//! default
+//!
+//! A search tree keeps each value once, so the queue tags every pushed
+//! value with a sequence number, which tells equal values apart. Equal
+//! values come out of the minimum end in the order they were pushed, and
+//! out of the maximum end in the reverse order.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+
+use crate::bst::base::{Bst, BstNode};
+use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+/// A value with the sequence number of its push, as stored in the tree
+/// of a `DoublePriorityQueue`
+pub struct Entry<Value> {
+    value: Value,
+    seq: u64,
+}
+
+impl<Value: PartialEq> PartialEq for Entry<Value> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value && self.seq == other.seq
+    }
+}
+
+impl<Value: PartialOrd> PartialOrd for Entry<Value> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.value.partial_cmp(&other.value) {
+            Some(Ordering::Equal) => self.seq.partial_cmp(&other.seq),
+            ordering => ordering,
+        }
+    }
+}
+
+/// The tree is private, so no one can hold a link into it
+const UNSHARED: &str = "The queue hands out no links into its tree";
+
+pub struct DoublePriorityQueue<Value, Tree = SimpleBst<SimpleBstNode<Entry<Value>>>>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    tree: Tree,
+    len: usize,
+    next_seq: u64,
+}
+
+impl<Value: PartialOrd> DoublePriorityQueue<Value> {
+    /// An empty queue on a `SimpleBst`, see `default` for other trees
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<Value, Tree> DoublePriorityQueue<Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, value: Value) {
+        self.tree.insert(Entry {
+            value,
+            seq: self.next_seq,
+        });
+        self.next_seq += 1;
+        self.len += 1;
+    }
+
+    pub fn peek_min(&self) -> Option<&Value> {
+        self.tree.peek_min().map(|entry| &entry.value)
+    }
+
+    pub fn peek_max(&self) -> Option<&Value> {
+        self.tree.peek_max().map(|entry| &entry.value)
+    }
+
+    pub fn pop_min(&mut self) -> Option<Value> {
+        let entry = self.tree.pop_min().expect(UNSHARED)?;
+        self.len -= 1;
+        Some(entry.value)
+    }
+
+    pub fn pop_max(&mut self) -> Option<Value> {
+        let entry = self.tree.pop_max().expect(UNSHARED)?;
+        self.len -= 1;
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.tree = Tree::default();
+        self.len = 0;
+    }
+
+    /// Removes the values in order, from either end
+    ///
+    /// The values left when the iterator is dropped are removed as well.
+    pub fn drain(&mut self) -> Drain<'_, Value, Tree> {
+        Drain { queue: self }
+    }
+}
+
+pub struct Drain<'a, Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    queue: &'a mut DoublePriorityQueue<Value, Tree>,
+}
+
+impl<'a, Value, Tree> Iterator for Drain<'a, Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop_min()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.queue.len, Some(self.queue.len))
+    }
+}
+
+impl<'a, Value, Tree> DoubleEndedIterator for Drain<'a, Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.queue.pop_max()
+    }
+}
+
+impl<'a, Value, Tree> ExactSizeIterator for Drain<'a, Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+}
+
+impl<'a, Value, Tree> Drop for Drain<'a, Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    fn drop(&mut self) {
+        self.queue.clear();
+    }
+}
+
+impl<Value, Tree> Default for DoublePriorityQueue<Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    fn default() -> Self {
+        Self {
+            tree: Tree::default(),
+            len: 0,
+            next_seq: 0,
+        }
+    }
+}
+
+impl<Value, Tree> FromIterator<Value> for DoublePriorityQueue<Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut queue = Self::default();
+        queue.extend(iter);
+        queue
+    }
+}
+
+impl<Value, Tree> Extend<Value> for DoublePriorityQueue<Value, Tree>
+where
+    Value: PartialOrd,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<Value, Tree> Debug for DoublePriorityQueue<Value, Tree>
+where
+    Value: PartialOrd + Debug,
+    Tree: Bst + Default,
+    Tree::Node: BstNode<Value = Entry<Value>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.tree.iter().map(|entry| &entry.value))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::base::Link;
+    use crate::bst::handle::HandleError;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut queue = DoublePriorityQueue::new();
+        assert_eq!(queue.peek_min(), None);
+        assert_eq!(queue.pop_max(), None);
+        queue.extend(vec![5, 1, 9, 5, 3, 9, 7]);
+        assert_eq!(queue.len(), 7);
+        assert_eq!((queue.peek_min(), queue.peek_max()), (Some(&1), Some(&9)));
+        assert_eq!(format!("{:?}", queue), "[1, 3, 5, 5, 7, 9, 9]");
+        assert_eq!(queue.pop_max(), Some(9));
+        assert_eq!(queue.pop_min(), Some(1));
+        assert_eq!(queue.pop_max(), Some(9));
+        assert_eq!(queue.len(), 4);
+        queue.push(0);
+        assert_eq!(queue.pop_min(), Some(0));
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [3, 5, 5, 7]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop_min(), None);
+    }
+
+    #[test]
+    fn test_equal_values() {
+        // Ordered by the number only, so the letters show the push order
+        #[derive(Debug, PartialEq)]
+        struct Task(u32, char);
+        impl PartialOrd for Task {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                self.0.partial_cmp(&other.0)
+            }
+        }
+
+        let mut queue: DoublePriorityQueue<Task> =
+            vec![Task(1, 'a'), Task(2, 'b'), Task(1, 'c'), Task(1, 'd')]
+                .into_iter()
+                .collect();
+        assert_eq!(queue.pop_min(), Some(Task(1, 'a')));
+        assert_eq!(queue.pop_max(), Some(Task(2, 'b')));
+        assert_eq!(queue.pop_max(), Some(Task(1, 'd')));
+        assert_eq!(queue.pop_min(), Some(Task(1, 'c')));
+    }
+
+    /// A tree, which counts the nodes deleted from it
+    #[derive(Default)]
+    struct CountingBst {
+        tree: SimpleBst<SimpleBstNode<Entry<u32>>>,
+        deleted: usize,
+    }
+
+    impl Bst for CountingBst {
+        type Node = SimpleBstNode<Entry<u32>>;
+
+        fn get_root(&self) -> Option<Link<Self::Node>> {
+            self.tree.get_root()
+        }
+
+        fn insert(&mut self, entry: Entry<u32>) {
+            self.tree.insert(entry)
+        }
+
+        fn delete_node(&mut self, node: Link<Self::Node>) -> Result<Entry<u32>, HandleError> {
+            self.deleted += 1;
+            self.tree.delete_node(node)
+        }
+    }
+
+    #[test]
+    fn test_other_tree() {
+        let mut queue: DoublePriorityQueue<u32, CountingBst> =
+            vec![3, 1, 2, 1].into_iter().collect();
+        assert_eq!(format!("{:?}", queue), "[1, 1, 2, 3]");
+        assert_eq!(queue.pop_max(), Some(3));
+        assert_eq!(queue.pop_min(), Some(1));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.tree.deleted, 2);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut queue: DoublePriorityQueue<u32> = (0..10).rev().collect();
+        let mut drain = queue.drain();
+        assert_eq!(drain.len(), 10);
+        assert_eq!(drain.next(), Some(0));
+        assert_eq!(drain.next_back(), Some(9));
+        assert_eq!(drain.len(), 8);
+        assert_eq!(drain.by_ref().take(3).collect::<Vec<_>>(), [1, 2, 3]);
+        drop(drain);
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek_max(), None);
+
+        queue.extend(vec![2, 1]);
+        assert_eq!(queue.drain().rev().collect::<Vec<_>>(), [2, 1]);
+    }
+
+    #[test]
+    fn test_values_dropped() {
+        struct Counted {
+            value: u32,
+            drops: Rc<Cell<usize>>,
+        }
+        impl PartialEq for Counted {
+            fn eq(&self, other: &Self) -> bool {
+                self.value == other.value
+            }
+        }
+        impl PartialOrd for Counted {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                self.value.partial_cmp(&other.value)
+            }
+        }
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let counted = |value| Counted {
+            value,
+            drops: drops.clone(),
+        };
+        let mut queue: DoublePriorityQueue<Counted> = (0..10).map(counted).collect();
+        queue.clear();
+        assert_eq!(drops.get(), 10);
+
+        queue.extend((0..10).map(counted));
+        let mut drain = queue.drain();
+        assert_eq!(drain.next().map(|counted| counted.value), Some(0));
+        assert_eq!(drops.get(), 11);
+        drop(drain);
+        assert_eq!(drops.get(), 20);
+
+        queue.extend((0..10).map(counted));
+        drop(queue);
+        assert_eq!(drops.get(), 30);
+        assert_eq!(Rc::strong_count(&drops), 1);
+    }
+}
@@ -0,0 +1,673 @@
+//! A text buffer stored as a treap of UTF-8 chunks
+//!
+//! Every node keeps the byte, char and newline counts of its subtree, so
+//! char offsets and line numbers are located in O(log n) expected time,
+//! and edits never copy more than a chunk of text. The nodes are linked
+//! like those of `ImplicitTreap`, with chunks instead of single values,
+//! and keep the counts up to date in `TreeNode::pull_up`, so the rope is
+//! split and merged by `base::split_by` and `base::merge_by`.
+
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::fmt::{self, Debug, Display};
+use std::ops::{Add, Range};
+
+use crate::bst::base::{
+    dismantle, get_extreme, merge_by, pull_path, split_by, Direction, Link, TreeNode,
+};
+
+/// Chunks are never made longer than this many bytes, but shorter ones
+/// are fine, e.g. after a deletion
+const MAX_CHUNK_LEN: usize = 1024;
+
+type Tree = Option<Link<RopeNode>>;
+
+/// Sizes of a piece of text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Metrics {
+    bytes: usize,
+    chars: usize,
+    newlines: usize,
+}
+
+impl Metrics {
+    fn of(text: &str) -> Self {
+        Self {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            newlines: text.bytes().filter(|&b| b == b'\n').count(),
+        }
+    }
+}
+
+impl Add for Metrics {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            chars: self.chars + other.chars,
+            newlines: self.newlines + other.newlines,
+        }
+    }
+}
+
+struct RopeNode {
+    /// In an `UnsafeCell`, because the links to a node are shared, while
+    /// the rope edits its chunks in place
+    chunk: UnsafeCell<String>,
+    /// Metrics of `chunk` alone
+    own: Cell<Metrics>,
+    /// Metrics of the whole subtree rooted at this node
+    total: Cell<Metrics>,
+    /// Heap key: every node has a higher priority than its children
+    priority: u32,
+    left_child: RefCell<Tree>,
+    right_child: RefCell<Tree>,
+    parent: RefCell<Tree>,
+}
+
+/// A string, which stays cheap to edit at any char offset however long
+/// it grows
+pub struct Rope {
+    root: Tree,
+    rng: ThreadRng,
+}
+
+fn metrics(tree: &Tree) -> Metrics {
+    tree.as_ref()
+        .map_or(Metrics::default(), |node| node.total.get())
+}
+
+/// The byte offset of the char at `char_idx` in `text`, or the length of
+/// `text` for the index right past its end
+fn char_to_byte(text: &str, char_idx: usize) -> usize {
+    text.char_indices()
+        .nth(char_idx)
+        .map_or(text.len(), |(byte_idx, _)| byte_idx)
+}
+
+impl RopeNode {
+    fn new(chunk: String, priority: u32) -> Self {
+        let own = Metrics::of(&chunk);
+        Self {
+            chunk: UnsafeCell::new(chunk),
+            own: Cell::new(own),
+            total: Cell::new(own),
+            priority,
+            left_child: RefCell::new(None),
+            right_child: RefCell::new(None),
+            parent: RefCell::new(None),
+        }
+    }
+
+    fn chunk(&self) -> &str {
+        // Chunks are only edited through `&mut Rope`, so no borrowed
+        // chunk is ever changed
+        unsafe { &*self.chunk.get() }
+    }
+
+    /// Appends `text` to the chunk, leaving the ancestors to be pulled up
+    fn push_str(&self, text: &str) {
+        // See `chunk`
+        unsafe { &mut *self.chunk.get() }.push_str(text);
+        self.own.set(self.own.get() + Metrics::of(text));
+        self.pull_up();
+    }
+
+    /// Cuts the chunk in two at the char `char_idx`, returning the tail
+    /// and leaving the ancestors to be pulled up
+    fn split_off(&self, char_idx: usize) -> String {
+        let byte_idx = char_to_byte(self.chunk(), char_idx);
+        // See `chunk`
+        let tail = unsafe { &mut *self.chunk.get() }.split_off(byte_idx);
+        self.own.set(Metrics::of(self.chunk()));
+        self.pull_up();
+        tail
+    }
+}
+
+impl TreeNode for RopeNode {
+    fn get_child(&self, direction: Direction) -> Option<Link<Self>> {
+        match direction {
+            Direction::Left => self.left_child.borrow().clone(),
+            Direction::Right => self.right_child.borrow().clone(),
+        }
+    }
+
+    fn get_parent(&self) -> Option<Link<Self>> {
+        self.parent.borrow().clone()
+    }
+
+    fn set_child(&self, direction: Direction, child: Option<Link<Self>>) {
+        match direction {
+            Direction::Left => *self.left_child.borrow_mut() = child,
+            Direction::Right => *self.right_child.borrow_mut() = child,
+        }
+    }
+
+    fn set_parent(&self, parent: Option<Link<Self>>) {
+        *self.parent.borrow_mut() = parent
+    }
+
+    /// Recomputes the subtree metrics after the chunk or the children
+    /// have changed
+    fn pull_up(&self) {
+        let total = metrics(&self.get_child(Direction::Left))
+            + self.own.get()
+            + metrics(&self.get_child(Direction::Right));
+        self.total.set(total);
+    }
+}
+
+/// Splits `tree` into its first `at` chars and the rest, cutting a chunk
+/// in two if `at` falls inside of it
+fn split<R: Rng>(tree: Tree, at: usize, rng: &mut R) -> (Tree, Tree) {
+    let mut at = at;
+    // The node with the chunk to cut, and the char offset into it
+    let mut cut = None;
+    let (left, right) = split_by(tree, &mut |node| {
+        let left_chars = metrics(&node.get_child(Direction::Left)).chars;
+        let own_chars = node.own.get().chars;
+        if at <= left_chars {
+            return false;
+        }
+        if at < left_chars + own_chars {
+            cut = Some((node.clone(), at - left_chars));
+        }
+        at = at.saturating_sub(left_chars + own_chars);
+        true
+    });
+    match cut {
+        None => (left, right),
+        Some((node, char_idx)) => {
+            // The node is the last one of `left`, so the tail goes first
+            // in `right`
+            let tail = RopeNode::new(node.split_off(char_idx), rng.gen());
+            pull_path(node.get_parent());
+            (left, merge(Some(Link::new(tail)), right))
+        }
+    }
+}
+
+/// Concatenates `left` and `right`, keeping the heap order of priorities
+fn merge(left: Tree, right: Tree) -> Tree {
+    merge_by(left, right, &|node| node.priority)
+}
+
+/// Appends `text` to the last chunk of `tree`, if it has room for it
+fn append_to_last_chunk(tree: &Tree, text: &str) -> bool {
+    let last = match tree {
+        Some(root) => get_extreme(root.clone(), Direction::Right),
+        None => return false,
+    };
+    if last.chunk().len() + text.len() > MAX_CHUNK_LEN {
+        return false;
+    }
+    last.push_str(text);
+    pull_path(last.get_parent());
+    true
+}
+
+/// Builds a tree out of `text`, cut into chunks on char boundaries
+fn build<R: Rng>(mut text: &str, rng: &mut R) -> Tree {
+    let mut tree = None;
+    while !text.is_empty() {
+        let mut len = text.len().min(MAX_CHUNK_LEN);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        let (chunk, rest) = text.split_at(len);
+        let node = RopeNode::new(chunk.to_string(), rng.gen());
+        tree = merge(tree, Some(Link::new(node)));
+        text = rest;
+    }
+    tree
+}
+
+/// Appends the chars `range` of `tree`, which is relative to the subtree,
+/// to `out`
+fn collect_slice(tree: &Tree, range: Range<usize>, out: &mut String) {
+    let node = match tree {
+        Some(node) if range.start < range.end => node,
+        _ => return,
+    };
+    let left_child = node.get_child(Direction::Left);
+    let left_chars = metrics(&left_child).chars;
+    let own_end = left_chars + node.own.get().chars;
+    collect_slice(
+        &left_child,
+        range.start.min(left_chars)..range.end.min(left_chars),
+        out,
+    );
+    let own_start = range.start.max(left_chars).min(own_end) - left_chars;
+    let own_stop = range.end.max(left_chars).min(own_end) - left_chars;
+    if own_start < own_stop {
+        let chunk = node.chunk();
+        let from = char_to_byte(chunk, own_start);
+        let to = char_to_byte(chunk, own_stop);
+        out.push_str(&chunk[from..to]);
+    }
+    collect_slice(
+        &node.get_child(Direction::Right),
+        range.start.max(own_end) - own_end..range.end.max(own_end) - own_end,
+        out,
+    );
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            rng: thread_rng(),
+        }
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        metrics(&self.root).bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        metrics(&self.root).chars
+    }
+
+    /// The number of lines, which is one more than the number of newlines,
+    /// so an empty rope has a single empty line
+    pub fn len_lines(&self) -> usize {
+        metrics(&self.root).newlines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Inserts `text` at the char offset `char_idx`
+    ///
+    /// Panics if `char_idx > len_chars`.
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        let len = self.len_chars();
+        assert!(
+            char_idx <= len,
+            "insertion index (is {}) should be <= len (is {})",
+            char_idx,
+            len
+        );
+        let (mut left, right) = split(self.root.take(), char_idx, &mut self.rng);
+        // Typing char by char should not make a node out of every char
+        if !append_to_last_chunk(&left, text) {
+            left = merge(left, build(text, &mut self.rng));
+        }
+        self.root = merge(left, right);
+    }
+
+    /// Removes the chars in `char_range`
+    ///
+    /// Panics if the range is decreasing or goes past `len_chars`.
+    pub fn remove(&mut self, char_range: Range<usize>) {
+        self.check_range(&char_range);
+        let (rest, right) = split(self.root.take(), char_range.end, &mut self.rng);
+        let (left, _removed) = split(rest, char_range.start, &mut self.rng);
+        self.root = merge(left, right);
+    }
+
+    /// Copies the chars in `char_range` out into a `String`
+    ///
+    /// Panics if the range is decreasing or goes past `len_chars`.
+    pub fn slice(&self, char_range: Range<usize>) -> String {
+        self.check_range(&char_range);
+        let mut out = String::new();
+        collect_slice(&self.root, char_range, &mut out);
+        out
+    }
+
+    fn check_range(&self, char_range: &Range<usize>) {
+        let len = self.len_chars();
+        assert!(
+            char_range.start <= char_range.end && char_range.end <= len,
+            "char range {:?} out of bounds for len {}",
+            char_range,
+            len
+        );
+    }
+
+    /// The line `char_idx` is on, counting from 0
+    ///
+    /// Panics if `char_idx > len_chars`.
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        let len = self.len_chars();
+        assert!(
+            char_idx <= len,
+            "char index (is {}) should be <= len (is {})",
+            char_idx,
+            len
+        );
+        let mut char_idx = char_idx;
+        let mut line = 0;
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            let left = metrics(&node.get_child(Direction::Left));
+            if char_idx < left.chars {
+                maybe_node = node.get_child(Direction::Left);
+                continue;
+            }
+            char_idx -= left.chars;
+            line += left.newlines;
+            let own = node.own.get();
+            if char_idx < own.chars {
+                return line
+                    + node
+                        .chunk()
+                        .chars()
+                        .take(char_idx)
+                        .filter(|&c| c == '\n')
+                        .count();
+            }
+            char_idx -= own.chars;
+            line += own.newlines;
+            maybe_node = node.get_child(Direction::Right);
+        }
+        line
+    }
+
+    /// The char offset at which `line` starts
+    ///
+    /// Panics if `line >= len_lines`.
+    pub fn line_to_char(&self, line: usize) -> usize {
+        let len = self.len_lines();
+        assert!(
+            line < len,
+            "line index (is {}) should be < len (is {})",
+            line,
+            len
+        );
+        // The line starts right after the `line`-th newline
+        let mut newlines = line;
+        let mut char_idx = 0;
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            if newlines == 0 {
+                break;
+            }
+            let left = metrics(&node.get_child(Direction::Left));
+            if newlines <= left.newlines {
+                maybe_node = node.get_child(Direction::Left);
+                continue;
+            }
+            newlines -= left.newlines;
+            char_idx += left.chars;
+            let own = node.own.get();
+            if newlines <= own.newlines {
+                let newline_idx = node
+                    .chunk()
+                    .chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '\n')
+                    .nth(newlines - 1)
+                    .map(|(i, _)| i)
+                    .expect("The chunk was counted to have enough newlines");
+                return char_idx + newline_idx + 1;
+            }
+            newlines -= own.newlines;
+            char_idx += own.chars;
+            maybe_node = node.get_child(Direction::Right);
+        }
+        char_idx
+    }
+
+    /// The text of `line`, including its trailing newline, if any
+    ///
+    /// Panics if `line >= len_lines`.
+    pub fn line(&self, line: usize) -> String {
+        let start = self.line_to_char(line);
+        let end = if line + 1 < self.len_lines() {
+            self.line_to_char(line + 1)
+        } else {
+            self.len_chars()
+        };
+        self.slice(start..end)
+    }
+
+    /// Iterates over the chunks the text is stored in, in order
+    pub fn chunks(&self) -> Chunks<'_> {
+        let mut chunks = Chunks {
+            _rope: self,
+            stack: vec![],
+        };
+        chunks.push_left_spine(self.root.clone());
+        chunks
+    }
+}
+
+/// In-order iterator over the chunks of a `Rope`
+pub struct Chunks<'a> {
+    _rope: &'a Rope,
+    /// Nodes whose left subtrees have been visited, but they themselves
+    /// have not
+    stack: Vec<Link<RopeNode>>,
+}
+
+impl<'a> Chunks<'a> {
+    fn push_left_spine(&mut self, mut maybe_node: Tree) {
+        while let Some(node) = maybe_node {
+            maybe_node = node.get_child(Direction::Left);
+            self.stack.push(node);
+        }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.get_child(Direction::Right));
+        // The rope is borrowed for `'a`, so it keeps the node alive and
+        // leaves the chunk as it is
+        let chunk: &'a str = unsafe { &*(node.chunk() as *const str) };
+        Some(chunk)
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Rope {
+    fn drop(&mut self) {
+        dismantle(self.root.take());
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        let mut rope = Self::new();
+        rope.root = build(text, &mut rope.rng);
+        rope
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.to_string(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Checks the metrics, the heap order of priorities, the parent
+    /// links and the chunk lengths
+    fn check_invariants(tree: &Tree) {
+        if let Some(node) = tree {
+            for &direction in [Direction::Left, Direction::Right].iter() {
+                let child = node.get_child(direction);
+                if let Some(child) = child.as_ref() {
+                    assert!(child.priority <= node.priority, "Heap order violated");
+                    assert!(Link::ptr_eq(&child.get_parent().unwrap(), node));
+                }
+                check_invariants(&child);
+            }
+            let chunk = node.chunk();
+            assert!(!chunk.is_empty() && chunk.len() <= MAX_CHUNK_LEN);
+            assert_eq!(node.own.get(), Metrics::of(chunk));
+            assert_eq!(
+                node.total.get(),
+                metrics(&node.get_child(Direction::Left))
+                    + node.own.get()
+                    + metrics(&node.get_child(Direction::Right))
+            );
+        }
+    }
+
+    #[test]
+    fn test_edit_and_slice() {
+        let mut rope = Rope::from("hello world");
+        rope.insert(5, ",");
+        rope.insert(rope.len_chars(), "!");
+        rope.insert(0, "¡");
+        assert_eq!(rope.to_string(), "¡hello, world!");
+        assert_eq!(rope.len_chars(), 14);
+        assert_eq!(rope.len_bytes(), 15);
+        rope.remove(6..8);
+        assert_eq!(rope.to_string(), "¡helloworld!");
+        assert_eq!(rope.slice(1..6), "hello");
+        assert_eq!(rope.slice(3..3), "");
+        rope.remove(0..rope.len_chars());
+        assert!(rope.is_empty());
+        assert_eq!(format!("{:?}", rope), "\"\"");
+    }
+
+    #[test]
+    fn test_drop_frees_nodes() {
+        let mut rope = Rope::from("x".repeat(5 * MAX_CHUNK_LEN).as_str());
+        rope.insert(10, "split");
+        let nodes: Vec<_> = {
+            let mut stack: Vec<Link<RopeNode>> = rope.root.clone().into_iter().collect();
+            let mut nodes = vec![];
+            while let Some(node) = stack.pop() {
+                stack.extend(node.get_child(Direction::Left));
+                stack.extend(node.get_child(Direction::Right));
+                nodes.push(Link::downgrade(&node));
+            }
+            nodes
+        };
+        assert!(nodes.len() > 5);
+        drop(rope);
+        assert!(nodes.iter().all(|node| node.upgrade().is_none()));
+    }
+
+    #[test]
+    fn test_lines() {
+        let rope = Rope::from("first\nsecond\n\nlast");
+        assert_eq!(rope.len_lines(), 4);
+        assert_eq!(rope.line(0), "first\n");
+        assert_eq!(rope.line(1), "second\n");
+        assert_eq!(rope.line(2), "\n");
+        assert_eq!(rope.line(3), "last");
+        assert_eq!(rope.line_to_char(1), 6);
+        assert_eq!(rope.char_to_line(5), 0);
+        assert_eq!(rope.char_to_line(6), 1);
+        assert_eq!(rope.char_to_line(rope.len_chars()), 3);
+
+        let empty = Rope::new();
+        assert_eq!(empty.len_lines(), 1);
+        assert_eq!(empty.line(0), "");
+        assert_eq!(empty.char_to_line(0), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "char range 2..5 out of bounds for len 3")]
+    fn test_remove_out_of_bounds() {
+        let mut rope = Rope::from("abc");
+        rope.remove(2..5);
+    }
+
+    #[test]
+    fn test_large_text() {
+        let line = "Добрий вечір, we are from Ukraine\n";
+        let text = line.repeat(2_000);
+        let mut rope = Rope::from(text.as_str());
+        check_invariants(&rope.root);
+        assert!(rope.chunks().count() > 1);
+        assert_eq!(rope.chunks().collect::<String>(), text);
+        assert_eq!(rope.len_lines(), 2_001);
+        let line_chars = line.chars().count();
+        assert_eq!(rope.line_to_char(1_000), 1_000 * line_chars);
+        assert_eq!(rope.line(1_234), line);
+
+        // Typing in the middle reuses the chunk it ends up in
+        let chunks = rope.chunks().count();
+        let middle = rope.line_to_char(1_000);
+        for (i, c) in "typed ".chars().enumerate() {
+            rope.insert(middle + i, &c.to_string());
+        }
+        assert!(rope.chunks().count() <= chunks + 2);
+        assert_eq!(rope.line(1_000), format!("typed {}", line));
+        check_invariants(&rope.root);
+    }
+
+    #[test]
+    fn test_matches_string() {
+        let pieces = ["a", "bc", "é", "日本", "\n", "x\ny", "🦀"];
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut rope = Rope::new();
+        let mut model = String::new();
+        let byte_idx = |model: &String, char_idx| char_to_byte(model, char_idx);
+        for _ in 0..3_000 {
+            let len = model.chars().count();
+            let a = rng.gen_range(0..len + 1);
+            let b = rng.gen_range(0..len + 1);
+            let (start, end) = (a.min(b), a.max(b));
+            match rng.gen_range(0..10) {
+                0..=5 => {
+                    let piece = pieces[rng.gen_range(0..pieces.len())].repeat(rng.gen_range(1..50));
+                    rope.insert(a, &piece);
+                    model.insert_str(byte_idx(&model, a), &piece);
+                }
+                6 => {
+                    rope.remove(start..end);
+                    model.replace_range(byte_idx(&model, start)..byte_idx(&model, end), "");
+                }
+                7 => {
+                    assert_eq!(
+                        rope.slice(start..end),
+                        model[byte_idx(&model, start)..byte_idx(&model, end)]
+                    );
+                }
+                _ => {
+                    let line = model.chars().take(a).filter(|&c| c == '\n').count();
+                    assert_eq!(rope.char_to_line(a), line);
+                    let line_start = model
+                        .chars()
+                        .take(a)
+                        .collect::<String>()
+                        .rfind('\n')
+                        .map_or(0, |i| model[..i].chars().count() + 1);
+                    assert_eq!(rope.line_to_char(line), line_start);
+                }
+            }
+            assert_eq!(rope.len_chars(), model.chars().count());
+            assert_eq!(rope.len_bytes(), model.len());
+        }
+        check_invariants(&rope.root);
+        assert_eq!(rope.to_string(), model);
+        assert_eq!(
+            rope.len_lines(),
+            model.chars().filter(|&c| c == '\n').count() + 1
+        );
+    }
+}
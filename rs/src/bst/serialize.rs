@@ -0,0 +1,464 @@
+//! Binary serialization of `SimpleBst`, preserving the exact tree shape
+//!
+//! Format, version 1 (all integers little-endian):
+//!
+//! ```text
+//! header: b"DBST" | version: u8 | node count: u64
+//! body:   one record per node, in pre-order
+//! record: flags: u8 | value
+//! ```
+//!
+//! Bit 0 of `flags` is set when the node has a left child, bit 1 when it
+//! has a right child; all other bits must be zero. Values are encoded by
+//! their `BinaryValue` implementation.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::bst::base::{Bst, BstNode, Direction, Link, TreeNode};
+use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+const MAGIC: [u8; 4] = *b"DBST";
+const VERSION: u8 = 1;
+const HAS_LEFT: u8 = 0b01;
+const HAS_RIGHT: u8 = 0b10;
+
+#[derive(Debug)]
+pub enum SerializationError {
+    /// The underlying reader or writer failed
+    Io(io::Error),
+    /// The input ended in the middle of the header or a node record
+    Truncated,
+    /// The input does not start with the expected magic bytes
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u8),
+    /// A node record has flag bits that are not defined by the format
+    InvalidFlags(u8),
+    /// A value could not be decoded
+    InvalidValue(String),
+    /// The number of node records differs from the one in the header
+    NodeCountMismatch {
+        declared: u64,
+        actual: u64,
+    },
+    /// The decoded values are not in strictly increasing in-order
+    NotSearchTree,
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Io(e) => write!(f, "I/O error: {}", e),
+            SerializationError::Truncated => write!(f, "unexpected end of input"),
+            SerializationError::BadMagic(magic) => write!(f, "bad magic bytes {:?}", magic),
+            SerializationError::UnsupportedVersion(v) => {
+                write!(f, "unsupported format version {}", v)
+            }
+            SerializationError::InvalidFlags(flags) => {
+                write!(f, "invalid node flags {:#04x}", flags)
+            }
+            SerializationError::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+            SerializationError::NodeCountMismatch { declared, actual } => write!(
+                f,
+                "header declares {} nodes, but the body holds {}",
+                declared, actual
+            ),
+            SerializationError::NotSearchTree => {
+                write!(f, "values are not ordered as in a binary search tree")
+            }
+        }
+    }
+}
+
+impl Error for SerializationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SerializationError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SerializationError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            SerializationError::Truncated
+        } else {
+            SerializationError::Io(e)
+        }
+    }
+}
+
+/// A value with a binary encoding
+pub trait BinaryValue: Sized {
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()>;
+
+    fn read_value<R: Read>(r: &mut R) -> Result<Self, SerializationError>;
+}
+
+macro_rules! impl_binary_value_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl BinaryValue for $t {
+                fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                    w.write_all(&self.to_le_bytes())
+                }
+
+                fn read_value<R: Read>(r: &mut R) -> Result<Self, SerializationError> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    r.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_value_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl BinaryValue for bool {
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).write_value(w)
+    }
+
+    fn read_value<R: Read>(r: &mut R) -> Result<Self, SerializationError> {
+        match u8::read_value(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(SerializationError::InvalidValue(format!(
+                "{} is not a boolean",
+                b
+            ))),
+        }
+    }
+}
+
+/// Strings are encoded as a `u64` byte length followed by UTF-8 bytes
+impl BinaryValue for String {
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.len() as u64).write_value(w)?;
+        w.write_all(self.as_bytes())
+    }
+
+    fn read_value<R: Read>(r: &mut R) -> Result<Self, SerializationError> {
+        let len = u64::read_value(r)?;
+        // Do not trust `len` with an allocation up front: a corrupt length
+        // should end in `Truncated`, not in an out-of-memory abort
+        let mut bytes = vec![];
+        let read = r.take(len).read_to_end(&mut bytes)?;
+        if (read as u64) < len {
+            return Err(SerializationError::Truncated);
+        }
+        String::from_utf8(bytes).map_err(|e| SerializationError::InvalidValue(e.to_string()))
+    }
+}
+
+impl<Value: BinaryValue + PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
+    /// Writes the tree in the binary format described in the module docs
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<(), SerializationError> {
+        w.write_all(&MAGIC)?;
+        VERSION.write_value(&mut w)?;
+        (self.iter().count() as u64).write_value(&mut w)?;
+
+        let mut stack: Vec<Link<SimpleBstNode<Value>>> = self.get_root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let left = node.get_child(Direction::Left);
+            let right = node.get_child(Direction::Right);
+            let mut flags = 0;
+            if left.is_some() {
+                flags |= HAS_LEFT;
+            }
+            if right.is_some() {
+                flags |= HAS_RIGHT;
+            }
+            flags.write_value(&mut w)?;
+            node.as_value().write_value(&mut w)?;
+            stack.extend(right);
+            stack.extend(left);
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Reads a tree written by `write_to`, restoring its exact shape
+    pub fn read_from<R: Read>(mut r: R) -> Result<Self, SerializationError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SerializationError::BadMagic(magic));
+        }
+        let version = u8::read_value(&mut r)?;
+        if version != VERSION {
+            return Err(SerializationError::UnsupportedVersion(version));
+        }
+        let declared = u64::read_value(&mut r)?;
+        if declared == 0 {
+            return Ok(Self::from_root(None));
+        }
+
+        let mut actual: u64 = 0;
+        let mut read_node =
+            |r: &mut R| -> Result<(u8, Link<SimpleBstNode<Value>>), SerializationError> {
+                actual += 1;
+                if actual > declared {
+                    // Reading on would only tell how far off the header is, so stop here
+                    return Err(SerializationError::NodeCountMismatch { declared, actual });
+                }
+                let flags = u8::read_value(r)?;
+                if flags & !(HAS_LEFT | HAS_RIGHT) != 0 {
+                    return Err(SerializationError::InvalidFlags(flags));
+                }
+                let value = Value::read_value(r)?;
+                Ok((flags, Link::new(SimpleBstNode::new(value))))
+            };
+
+        // Slots still waiting for a child, the next one to fill on top
+        let mut pending: Vec<(Link<SimpleBstNode<Value>>, Direction)> = vec![];
+        let (flags, root) = read_node(&mut r)?;
+        // Owns the nodes read so far, so that they are freed if the rest
+        // of the input turns out to be invalid
+        let tree = Self::from_root(Some(root.clone()));
+        let push_slots = |pending: &mut Vec<(Link<SimpleBstNode<Value>>, Direction)>,
+                          flags: u8,
+                          node: &Link<_>| {
+            if flags & HAS_RIGHT != 0 {
+                pending.push((Link::clone(node), Direction::Right));
+            }
+            if flags & HAS_LEFT != 0 {
+                pending.push((Link::clone(node), Direction::Left));
+            }
+        };
+        push_slots(&mut pending, flags, &root);
+        while let Some((parent, direction)) = pending.pop() {
+            let (flags, node) = read_node(&mut r)?;
+            parent.set_child(direction, Some(node.clone()));
+            node.set_parent(Some(parent));
+            push_slots(&mut pending, flags, &node);
+        }
+        if actual != declared {
+            return Err(SerializationError::NodeCountMismatch { declared, actual });
+        }
+
+        let ordered = {
+            let values: Vec<&Value> = tree.iter().collect();
+            values.windows(2).all(|pair| pair[0] < pair[1])
+        };
+        if !ordered {
+            return Err(SerializationError::NotSearchTree);
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::fmt::Debug;
+
+    fn assert_same_shape<V: Debug + PartialEq + PartialOrd>(
+        a: Option<Link<SimpleBstNode<V>>>,
+        b: Option<Link<SimpleBstNode<V>>>,
+    ) {
+        let mut stack = vec![(a, b)];
+        while let Some((a, b)) = stack.pop() {
+            match (a, b) {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.as_value(), b.as_value());
+                    let (a_parent, b_parent) = (a.get_parent(), b.get_parent());
+                    assert_eq!(
+                        a_parent.as_ref().map(|p| p.as_value()),
+                        b_parent.as_ref().map(|p| p.as_value())
+                    );
+                    for &direction in [Direction::Left, Direction::Right].iter() {
+                        if let Some(child) = b.get_child(direction) {
+                            assert_eq!(child.get_parent().unwrap(), b);
+                        }
+                        stack.push((a.get_child(direction), b.get_child(direction)));
+                    }
+                }
+                (a, b) => panic!("Shapes differ: {:?} vs {:?}", a, b),
+            }
+        }
+    }
+
+    fn test_round_trip_gen<V: BinaryValue + Clone + Debug + PartialEq + PartialOrd>(
+        values: Vec<V>,
+    ) {
+        let mut bst = SimpleBst::<SimpleBstNode<V>>::new();
+        for v in values {
+            bst.insert(v);
+        }
+        let mut bytes = vec![];
+        bst.write_to(&mut bytes).unwrap();
+        let restored = SimpleBst::<SimpleBstNode<V>>::read_from(&bytes[..]).unwrap();
+        assert_same_shape(bst.get_root(), restored.get_root());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        test_round_trip_gen::<u32>(vec![]);
+        test_round_trip_gen::<u32>(vec![5]);
+        test_round_trip_gen::<u32>(vec![5, 3, 8, 1, 4, 7, 9, 2, 6]);
+        test_round_trip_gen::<i64>((0..100).map(|i| (i * 37) % 101 - 50).collect());
+        test_round_trip_gen::<u8>((0..=255).collect());
+        test_round_trip_gen::<bool>(vec![true, false]);
+        test_round_trip_gen::<String>(
+            vec!["m", "dibrova", "z", "", "ß"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let mut bst = SimpleBst::<SimpleBstNode<u8>>::new();
+        bst.insert(2);
+        bst.insert(1);
+        let mut bytes = vec![];
+        bst.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes,
+            vec![b'D', b'B', b'S', b'T', 1, 2, 0, 0, 0, 0, 0, 0, 0, 0b01, 2, 0b00, 1]
+        );
+    }
+
+    fn read(bytes: &[u8]) -> Result<SimpleBst<SimpleBstNode<u8>>, SerializationError> {
+        SimpleBst::<SimpleBstNode<u8>>::read_from(bytes)
+    }
+
+    thread_local! {
+        static LIVE: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// A value, which keeps count of its live instances
+    #[derive(Debug, PartialEq, PartialOrd)]
+    struct Counted(u8);
+
+    impl BinaryValue for Counted {
+        fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+            self.0.write_value(w)
+        }
+
+        fn read_value<R: Read>(r: &mut R) -> Result<Self, SerializationError> {
+            let value = u8::read_value(r)?;
+            LIVE.with(|live| live.set(live.get() + 1));
+            Ok(Counted(value))
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            LIVE.with(|live| live.set(live.get() - 1));
+        }
+    }
+
+    #[test]
+    fn test_corrupt_input_frees_nodes() {
+        let read = |bytes: &[u8]| SimpleBst::<SimpleBstNode<Counted>>::read_from(bytes);
+        let mut bytes = vec![b'D', b'B', b'S', b'T', 1, 4, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend_from_slice(&[0b11, 2, 0, 1, 0b10, 4, 0, 5]);
+        // Truncated in the middle of the last record
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 1]),
+            Err(SerializationError::Truncated)
+        ));
+        assert_eq!(LIVE.with(Cell::get), 0);
+        // One node fewer than declared
+        bytes[5] = 5;
+        assert!(matches!(
+            read(&bytes),
+            Err(SerializationError::NodeCountMismatch { .. })
+        ));
+        assert_eq!(LIVE.with(Cell::get), 0);
+        // Out of order
+        bytes[5] = 4;
+        bytes[16] = 3;
+        assert!(matches!(
+            read(&bytes),
+            Err(SerializationError::NotSearchTree)
+        ));
+        assert_eq!(LIVE.with(Cell::get), 0);
+        bytes[16] = 1;
+        let tree = read(&bytes).unwrap();
+        assert_eq!(LIVE.with(Cell::get), 4);
+        drop(tree);
+        assert_eq!(LIVE.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn test_corrupt_input() {
+        let header = |count: u8| vec![b'D', b'B', b'S', b'T', 1, count, 0, 0, 0, 0, 0, 0, 0];
+        let with_body = |count: u8, body: &[u8]| {
+            let mut bytes = header(count);
+            bytes.extend_from_slice(body);
+            bytes
+        };
+
+        assert!(matches!(read(&[]), Err(SerializationError::Truncated)));
+        assert!(matches!(
+            read(b"XBST"),
+            Err(SerializationError::BadMagic(m)) if &m == b"XBST"
+        ));
+        assert!(matches!(
+            read(&[b'D', b'B', b'S', b'T', 7]),
+            Err(SerializationError::UnsupportedVersion(7))
+        ));
+        assert!(matches!(
+            read(&header(2)[..10]),
+            Err(SerializationError::Truncated)
+        ));
+        // Root promises a left child that never comes
+        assert!(matches!(
+            read(&with_body(2, &[0b01, 2])),
+            Err(SerializationError::Truncated)
+        ));
+        assert!(matches!(
+            read(&with_body(1, &[0b100, 2])),
+            Err(SerializationError::InvalidFlags(0b100))
+        ));
+        assert!(matches!(
+            read(&with_body(1, &[0b01, 2, 0, 1])),
+            Err(SerializationError::NodeCountMismatch {
+                declared: 1,
+                actual: 2
+            })
+        ));
+        assert!(matches!(
+            read(&with_body(3, &[0b01, 2, 0, 1])),
+            Err(SerializationError::NodeCountMismatch {
+                declared: 3,
+                actual: 2
+            })
+        ));
+        // 1 as the left child of 2 is fine, 3 is not
+        assert!(read(&with_body(2, &[0b01, 2, 0, 1])).is_ok());
+        assert!(matches!(
+            read(&with_body(2, &[0b01, 2, 0, 3])),
+            Err(SerializationError::NotSearchTree)
+        ));
+        assert!(matches!(
+            read(&with_body(2, &[0b10, 2, 0, 2])),
+            Err(SerializationError::NotSearchTree)
+        ));
+
+        let mut bytes = header(1);
+        bytes.push(0);
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            SimpleBst::<SimpleBstNode<String>>::read_from(&bytes[..]),
+            Err(SerializationError::Truncated)
+        ));
+        let mut bytes = header(1);
+        bytes.push(0);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.push(0xff);
+        assert!(matches!(
+            SimpleBst::<SimpleBstNode<String>>::read_from(&bytes[..]),
+            Err(SerializationError::InvalidValue(_))
+        ));
+    }
+}
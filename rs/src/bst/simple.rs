@@ -1,10 +1,19 @@
 use rand::{rngs::ThreadRng, thread_rng, Rng};
 use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::VecDeque;
 use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::mem;
 use std::rc::Rc;
 
-use crate::bst::base::{get_extreme, Bst, BstNode, Direction, Link};
+use crate::bst::base::{
+    check_unshared, dismantle, get_extreme, Bst, BstIterator, BstNode, Direction, Link, TreeNode,
+};
+use crate::bst::handle::{Handle, HandleError};
+use crate::bst::instrument::{Event, Instruments};
+use crate::bst::trace::RecordsChoices;
 
 pub struct SimpleBstNode<Value: PartialEq + PartialOrd> {
     value: Value,
@@ -16,6 +25,15 @@ pub struct SimpleBstNode<Value: PartialEq + PartialOrd> {
 pub struct SimpleBst<Node: BstNode> {
     root: Option<Link<Node>>,
     rng: RefCell<ThreadRng>,
+    choices: RefCell<Choices>,
+    instruments: Instruments<Node::Value>,
+}
+
+/// The deletion sides chosen at random, kept and forced for traces
+#[derive(Default)]
+struct Choices {
+    recorded: Option<Vec<Direction>>,
+    forced: VecDeque<Direction>,
 }
 
 impl<Value: fmt::Debug + PartialEq + PartialOrd> Debug for SimpleBstNode<Value> {
@@ -58,6 +76,30 @@ impl<Value: PartialEq + PartialOrd> PartialOrd for SimpleBstNode<Value> {
     }
 }
 
+impl<Value: PartialEq + PartialOrd> TreeNode for SimpleBstNode<Value> {
+    fn get_child(&self, direction: Direction) -> Option<Link<Self>> {
+        match direction {
+            Direction::Left => self.left_child.borrow().clone(),
+            Direction::Right => self.right_child.borrow().clone(),
+        }
+    }
+
+    fn get_parent(&self) -> Option<Link<Self>> {
+        self.parent.borrow().clone()
+    }
+
+    fn set_child(&self, direction: Direction, child: Option<Link<Self>>) {
+        match direction {
+            Direction::Left => *self.left_child.borrow_mut() = child,
+            Direction::Right => *self.right_child.borrow_mut() = child,
+        }
+    }
+
+    fn set_parent(&self, parent: Option<Link<Self>>) {
+        *self.parent.borrow_mut() = parent
+    }
+}
+
 impl<Value: PartialEq + PartialOrd> BstNode for SimpleBstNode<Value> {
     type Value = Value;
 
@@ -91,28 +133,6 @@ impl<Value: PartialEq + PartialOrd> BstNode for SimpleBstNode<Value> {
         );
         self.value
     }
-
-    fn get_child(&self, direction: Direction) -> Option<Link<Self>> {
-        match direction {
-            Direction::Left => self.left_child.borrow().clone(),
-            Direction::Right => self.right_child.borrow().clone(),
-        }
-    }
-
-    fn get_parent(&self) -> Option<Link<Self>> {
-        self.parent.borrow().clone()
-    }
-
-    fn set_child(&self, direction: Direction, child: Option<Link<Self>>) {
-        match direction {
-            Direction::Left => *self.left_child.borrow_mut() = child,
-            Direction::Right => *self.right_child.borrow_mut() = child,
-        }
-    }
-
-    fn set_parent(&self, parent: Option<Link<Self>>) {
-        *self.parent.borrow_mut() = parent
-    }
 }
 
 impl<Value: PartialEq + PartialOrd> Bst for SimpleBst<SimpleBstNode<Value>> {
@@ -128,6 +148,10 @@ impl<Value: PartialEq + PartialOrd> Bst for SimpleBst<SimpleBstNode<Value>> {
         loop {
             match maybe_current_node.clone() {
                 Some(current_node) => {
+                    self.instruments.count(|counters| {
+                        counters.visits += 1;
+                        counters.comparisons += 1;
+                    });
                     match current_node.get_direction_of_value(&value) {
                         Some(direction) => {
                             maybe_parent_and_direction = Some((current_node.clone(), direction));
@@ -147,38 +171,54 @@ impl<Value: PartialEq + PartialOrd> Bst for SimpleBst<SimpleBstNode<Value>> {
         }
 
         let new_node = Link::new(<Self::Node as BstNode>::new(value));
+        self.instruments.count(|counters| counters.allocations += 1);
         match maybe_parent_and_direction {
-            Some((parent, direction)) => {
-                parent.set_child(direction, Some(new_node.clone()));
-                new_node.set_parent(Some(parent));
-            }
+            Some((parent, direction)) => self.link(&parent, direction, &new_node),
             None => {
                 // First node of the tree
-                self.root.replace(new_node);
+                self.root.replace(new_node.clone());
             }
         };
+        self.instruments
+            .emit(|| Event::Inserted(new_node.as_value()));
+    }
+
+    fn delete_node(
+        &mut self,
+        node: Link<Self::Node>,
+    ) -> Result<<Self::Node as BstNode>::Value, HandleError> {
+        check_unshared(&node)?;
+        if let Some(replacement) = self.delete_subtree_root(node.clone()) {
+            self.instruments.emit(|| Event::Replaced {
+                removed: node.as_value(),
+                by: replacement.as_value(),
+            });
+        }
+        self.instruments.emit(|| Event::Deleted(node.as_value()));
+        let deleted_node = match Rc::try_unwrap(node) {
+            Ok(deleted_node) => deleted_node,
+            Err(_) => unreachable!("References to the node were counted before the deletion"),
+        };
+        Ok(deleted_node.into_value())
     }
 
+    // The searches are the provided ones, only counted
+
     fn delete(
         &mut self,
         value: &<Self::Node as BstNode>::Value,
-    ) -> Option<<Self::Node as BstNode>::Value> {
-        match self.find(value) {
-            None => None,
-            Some(node) => {
-                let _ = self.delete_subtree_root(node.clone());
-                // Here it is explicitly expected that this is the last pointer to the node
-                // Therefore it is very important that the concept of the `Link` is never exposed
-                // in the BST API
-                let deleted_node = match Rc::try_unwrap(node) {
-                    Ok(deleted_node) => deleted_node,
-                    Err(_) => {
-                        panic!("Freshly deleted node link expected to only have one reference left")
-                    }
-                };
-                Some(deleted_node.into_value())
-            }
-        }
+    ) -> Result<Option<<Self::Node as BstNode>::Value>, HandleError> {
+        self.locate(value)
+            .map(|node| self.delete_node(node))
+            .transpose()
+    }
+
+    fn find(&self, value: &<Self::Node as BstNode>::Value) -> Option<Handle<Self::Node>> {
+        self.locate(value).map(|node| Handle::new(&node))
+    }
+
+    fn contains(&self, value: &<Self::Node as BstNode>::Value) -> bool {
+        self.locate(value).is_some()
     }
 }
 
@@ -187,15 +227,111 @@ impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
         Self {
             root: None,
             rng: RefCell::new(thread_rng()),
+            choices: RefCell::default(),
+            instruments: Instruments::new(),
+        }
+    }
+
+    /// Wraps an already linked node structure into a tree
+    ///
+    /// The caller is responsible for `root` being a valid search tree
+    /// with consistent parent links.
+    pub(crate) fn from_root(root: Option<Link<SimpleBstNode<Value>>>) -> Self {
+        Self {
+            root,
+            rng: RefCell::new(thread_rng()),
+            choices: RefCell::default(),
+            instruments: Instruments::new(),
         }
     }
 
+    /// The operation counters and the event observer of this tree
+    #[cfg(feature = "instrumentation")]
+    pub fn instruments(&self) -> &Instruments<Value> {
+        &self.instruments
+    }
+
     fn get_random_direction(&self) -> Direction {
-        let rn: u8 = self.rng.borrow_mut().gen();
-        if rn % 2 == 0 {
-            Direction::Left
-        } else {
-            Direction::Right
+        let mut choices = self.choices.borrow_mut();
+        let direction = choices.forced.pop_front().unwrap_or_else(|| {
+            let rn: u8 = self.rng.borrow_mut().gen();
+            if rn % 2 == 0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            }
+        });
+        if let Some(recorded) = choices.recorded.as_mut() {
+            recorded.push(direction);
+        }
+        direction
+    }
+
+    /// The node with `value`, found from the root like `find_node` does
+    fn locate(&self, value: &Value) -> Option<Link<SimpleBstNode<Value>>> {
+        let mut maybe_node = self.root.clone();
+        while let Some(node) = maybe_node {
+            self.instruments.count(|counters| {
+                counters.visits += 1;
+                counters.comparisons += 1;
+            });
+            match node.get_direction_of_value(value) {
+                None => return Some(node),
+                Some(direction) => maybe_node = node.get_child(direction),
+            }
+        }
+        None
+    }
+
+    /// The last node going in `direction` from `node`, like `get_extreme`
+    fn extreme(
+        &self,
+        mut node: Link<SimpleBstNode<Value>>,
+        direction: Direction,
+    ) -> Link<SimpleBstNode<Value>> {
+        while let Some(child) = node.get_child(direction) {
+            self.instruments.count(|counters| counters.visits += 1);
+            node = child;
+        }
+        node
+    }
+
+    /// Makes `child` the `direction` child of `parent`
+    fn link(
+        &self,
+        parent: &Link<SimpleBstNode<Value>>,
+        direction: Direction,
+        child: &Link<SimpleBstNode<Value>>,
+    ) {
+        parent.set_child(direction, Some(child.clone()));
+        child.set_parent(Some(parent.clone()));
+        self.instruments
+            .count(|counters| counters.pointer_updates += 2);
+    }
+
+    /// Counts the links that `node.extract()` is about to remove
+    fn count_extraction(&self, node: &SimpleBstNode<Value>) {
+        self.instruments.count(|counters| {
+            let neighbours = [
+                node.get_parent(),
+                node.get_child(Direction::Left),
+                node.get_child(Direction::Right),
+            ];
+            counters.pointer_updates += 2 * neighbours.iter().flatten().count() as u64;
+        });
+    }
+
+    /// Links `maybe_orphan` into the place of a freshly extracted node,
+    /// whose former parent and direction are given
+    fn reattach(
+        &self,
+        maybe_direction_and_parent: Option<(Direction, Link<SimpleBstNode<Value>>)>,
+        maybe_orphan: Option<Link<SimpleBstNode<Value>>>,
+    ) {
+        if let (Some((direction, parent)), Some(orphan)) =
+            (maybe_direction_and_parent, maybe_orphan)
+        {
+            self.link(&parent, direction, &orphan);
         }
     }
 
@@ -205,6 +341,7 @@ impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
         &mut self,
         subtree_root: Link<SimpleBstNode<Value>>,
     ) -> Option<Link<SimpleBstNode<Value>>> {
+        self.count_extraction(&subtree_root);
         let (maybe_direction_and_parent, maybe_left_child, maybe_right_child) =
             subtree_root.extract();
         let replacement = match (
@@ -217,8 +354,7 @@ impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
             (Some((_direction, _parent)), None, None) => None,
             (Some((direction, parent)), Some(child), None)
             | (Some((direction, parent)), None, Some(child)) => {
-                parent.set_child(direction, Some(child.clone()));
-                child.set_parent(Some(parent));
+                self.link(&parent, direction, &child);
                 Some(child)
             }
             (maybe_direction_and_parent, Some(left_child), Some(right_child)) => {
@@ -227,19 +363,17 @@ impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
                         if left_child.get_child(Direction::Right).is_some() {
                             // `left_child` has a right subtree, so rightmost is not equal to left_child
                             let rightmost_in_left_subtree =
-                                get_extreme(left_child.clone(), Direction::Right);
-                            let _ = rightmost_in_left_subtree.extract();
-                            rightmost_in_left_subtree
-                                .set_child(Direction::Left, Some(left_child.clone()));
-                            left_child.set_parent(Some(rightmost_in_left_subtree.clone()));
-                            rightmost_in_left_subtree
-                                .set_child(Direction::Right, Some(right_child.clone()));
-                            right_child.set_parent(Some(rightmost_in_left_subtree.clone()));
+                                self.extreme(left_child.clone(), Direction::Right);
+                            self.count_extraction(&rightmost_in_left_subtree);
+                            let (maybe_direction_and_parent, maybe_orphan, _) =
+                                rightmost_in_left_subtree.extract();
+                            self.reattach(maybe_direction_and_parent, maybe_orphan);
+                            self.link(&rightmost_in_left_subtree, Direction::Left, &left_child);
+                            self.link(&rightmost_in_left_subtree, Direction::Right, &right_child);
                             rightmost_in_left_subtree
                         } else {
                             // `left_child` has no right subtree, so we can just lift it 1 level
-                            left_child.set_child(Direction::Right, Some(right_child.clone()));
-                            right_child.set_parent(Some(left_child.clone()));
+                            self.link(&left_child, Direction::Right, &right_child);
                             left_child
                         }
                     }
@@ -247,27 +381,24 @@ impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
                         if right_child.get_child(Direction::Left).is_some() {
                             // `right_child` has a left subtree, so leftmost is not equal to right_child
                             let leftmost_in_right_subtree =
-                                get_extreme(right_child.clone(), Direction::Left);
-                            let _ = leftmost_in_right_subtree.extract();
-                            leftmost_in_right_subtree
-                                .set_child(Direction::Right, Some(right_child.clone()));
-                            right_child.set_parent(Some(leftmost_in_right_subtree.clone()));
-                            leftmost_in_right_subtree
-                                .set_child(Direction::Left, Some(left_child.clone()));
-                            left_child.set_parent(Some(leftmost_in_right_subtree.clone()));
+                                self.extreme(right_child.clone(), Direction::Left);
+                            self.count_extraction(&leftmost_in_right_subtree);
+                            let (maybe_direction_and_parent, _, maybe_orphan) =
+                                leftmost_in_right_subtree.extract();
+                            self.reattach(maybe_direction_and_parent, maybe_orphan);
+                            self.link(&leftmost_in_right_subtree, Direction::Right, &right_child);
+                            self.link(&leftmost_in_right_subtree, Direction::Left, &left_child);
                             leftmost_in_right_subtree
                         } else {
                             // `right_child` has no left subtree, so we can just lift it 1 level
-                            right_child.set_child(Direction::Left, Some(left_child.clone()));
-                            left_child.set_parent(Some(right_child.clone()));
+                            self.link(&right_child, Direction::Left, &left_child);
                             right_child
                         }
                     }
                 };
 
                 if let Some((direction, parent)) = maybe_direction_and_parent {
-                    replacement.set_parent(Some(parent.clone()));
-                    parent.set_child(direction, Some(replacement.clone()));
+                    self.link(&parent, direction, &replacement);
                 }
                 Some(replacement)
             }
@@ -280,11 +411,187 @@ impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
     }
 }
 
+impl<Value: PartialEq + PartialOrd> Default for SimpleBst<SimpleBstNode<Value>> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Value: PartialEq + PartialOrd> FromIterator<Value> for SimpleBst<SimpleBstNode<Value>> {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut bst = Self::new();
+        bst.extend(iter);
+        bst
+    }
+}
+
+impl<Value: PartialEq + PartialOrd> Extend<Value> for SimpleBst<SimpleBstNode<Value>> {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<'a, Value: PartialEq + PartialOrd> IntoIterator for &'a SimpleBst<SimpleBstNode<Value>> {
+    type Item = &'a Value;
+    type IntoIter = BstIterator<'a, SimpleBst<SimpleBstNode<Value>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<Value: PartialEq + PartialOrd> RecordsChoices for SimpleBst<SimpleBstNode<Value>> {
+    fn record_choices(&self, on: bool) {
+        self.choices.borrow_mut().recorded = if on { Some(vec![]) } else { None };
+    }
+
+    fn take_choices(&self) -> Vec<Direction> {
+        let mut choices = self.choices.borrow_mut();
+        choices.recorded.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    fn force_choices(&self, choices: Vec<Direction>) {
+        self.choices.borrow_mut().forced = choices.into();
+    }
+}
+
+impl<Node: BstNode> Drop for SimpleBst<Node> {
+    fn drop(&mut self) {
+        dismantle(self.root.take());
+    }
+}
+
+/// A by-value in-order iterator, dismantling the tree as it goes
+///
+/// A node still referenced through an outstanding `Link` is unlinked like
+/// the others, but its value stays with that link, so it is left out and
+/// counted by `skipped`.
+pub struct IntoIter<Value: PartialEq + PartialOrd> {
+    // The smallest node left, which never has a left child
+    next: Option<Link<SimpleBstNode<Value>>>,
+    skipped: usize,
+}
+
+impl<Value: PartialEq + PartialOrd> IntoIter<Value> {
+    /// The number of values left out so far, because their nodes were
+    /// shared with an outstanding `Link`
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+impl<Value: PartialEq + PartialOrd> Iterator for IntoIter<Value> {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.next.take()?;
+            let (maybe_direction_and_parent, _, maybe_right_child) = node.extract();
+            self.next = match (maybe_direction_and_parent, maybe_right_child) {
+                (Some((direction, parent)), Some(child)) => {
+                    parent.set_child(direction, Some(child.clone()));
+                    child.set_parent(Some(parent));
+                    Some(get_extreme(child, Direction::Left))
+                }
+                (Some((_, parent)), None) => Some(parent),
+                (None, Some(child)) => Some(get_extreme(child, Direction::Left)),
+                (None, None) => None,
+            };
+            // `node` is fully unlinked now, so the only other owner it can
+            // have is a `Link` obtained through `get_root` and friends
+            match Rc::try_unwrap(node) {
+                Ok(node) => return Some(node.into_value()),
+                Err(_) => self.skipped += 1,
+            }
+        }
+    }
+}
+
+/// Dismantles the nodes not iterated over, which would leak otherwise
+impl<Value: PartialEq + PartialOrd> Drop for IntoIter<Value> {
+    fn drop(&mut self) {
+        while self.next().is_some() {}
+    }
+}
+
+/// Moves the values out in order
+///
+/// The values of nodes still referenced through a `Link` obtained from
+/// the tree, e.g. by `get_root`, cannot be moved out: they are dropped
+/// from the output, and `IntoIter::skipped` tells how many there were.
+impl<Value: PartialEq + PartialOrd> IntoIterator for SimpleBst<SimpleBstNode<Value>> {
+    type Item = Value;
+    type IntoIter = IntoIter<Value>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        IntoIter {
+            next: self
+                .root
+                .take()
+                .map(|root| get_extreme(root, Direction::Left)),
+            skipped: 0,
+        }
+    }
+}
+
+/// A deep copy, with the same shape as the original
+impl<Value: PartialEq + PartialOrd + Clone> Clone for SimpleBst<SimpleBstNode<Value>> {
+    fn clone(&self) -> Self {
+        let root = self.root.as_ref().map(|original_root| {
+            let root = Link::new(SimpleBstNode::new(original_root.as_value().clone()));
+            let mut stack = vec![(original_root.clone(), root.clone())];
+            while let Some((original, copy)) = stack.pop() {
+                for &direction in [Direction::Left, Direction::Right].iter() {
+                    if let Some(original_child) = original.get_child(direction) {
+                        let child =
+                            Link::new(SimpleBstNode::new(original_child.as_value().clone()));
+                        copy.set_child(direction, Some(child.clone()));
+                        child.set_parent(Some(copy.clone()));
+                        stack.push((original_child, child));
+                    }
+                }
+            }
+            root
+        });
+        Self::from_root(root)
+    }
+}
+
+/// Trees are equal when they hold equal values, regardless of their shapes
+impl<Value: PartialEq + PartialOrd> PartialEq for SimpleBst<SimpleBstNode<Value>> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<Value: Eq + PartialOrd> Eq for SimpleBst<SimpleBstNode<Value>> {}
+
+impl<Value: Hash + PartialEq + PartialOrd> Hash for SimpleBst<SimpleBstNode<Value>> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len: usize = 0;
+        for value in self.iter() {
+            value.hash(state);
+            len += 1;
+        }
+        // Mixing in the length keeps the hash prefix-free
+        len.hash(state);
+    }
+}
+
+impl<Value: Debug + PartialEq + PartialOrd> Debug for SimpleBst<SimpleBstNode<Value>> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::bst::base::next_inorder;
     use std::fmt::Debug;
+    use std::ops::Bound;
 
     fn get_three_nodes<Node: BstNode>(
         small: Node::Value,
@@ -605,10 +912,10 @@ mod tests {
         bst.insert(e5.clone());
         bst.insert(e1.clone());
         bst.insert(e4.clone());
-        assert_eq!(bst.find(&e3).unwrap().as_value(), &e3);
-        assert_eq!(bst.find(&e5).unwrap().as_value(), &e5);
-        assert_eq!(bst.find(&e1).unwrap().as_value(), &e1);
-        assert_eq!(bst.find(&e4).unwrap().as_value(), &e4);
+        assert_eq!(bst.get(&bst.find(&e3).unwrap()).unwrap(), &e3);
+        assert_eq!(bst.get(&bst.find(&e5).unwrap()).unwrap(), &e5);
+        assert_eq!(bst.get(&bst.find(&e1).unwrap()).unwrap(), &e1);
+        assert_eq!(bst.get(&bst.find(&e4).unwrap()).unwrap(), &e4);
         assert!(bst.find(&e2).is_none());
         assert!(bst.find(&e6).is_none());
     }
@@ -740,42 +1047,296 @@ mod tests {
         bst.insert(e4.clone());
         bst.insert(e6.clone());
 
-        assert_eq!(bst.delete(&e5), Some(e5));
+        assert_eq!(bst.delete(&e5), Ok(Some(e5)));
         {
             let v: Vec<&V> = bst.iter().collect();
             assert_eq!(v, [&e1, &e2, &e3, &e4, &e6]);
         }
 
-        assert_eq!(bst.delete(&e3), Some(e3));
+        assert_eq!(bst.delete(&e3), Ok(Some(e3)));
         {
             let v: Vec<&V> = bst.iter().collect();
             assert_eq!(v, [&e1, &e2, &e4, &e6]);
         }
-        assert_eq!(bst.delete(&e4), Some(e4));
+        assert_eq!(bst.delete(&e4), Ok(Some(e4)));
         {
             let v: Vec<&V> = bst.iter().collect();
             assert_eq!(v, [&e1, &e2, &e6]);
         }
-        assert_eq!(bst.delete(&e2), Some(e2));
+        assert_eq!(bst.delete(&e2), Ok(Some(e2)));
         {
             let v: Vec<&V> = bst.iter().collect();
             assert_eq!(v, [&e1, &e6]);
         }
-        assert_eq!(bst.delete(&e6), Some(e6));
+        assert_eq!(bst.delete(&e6), Ok(Some(e6)));
         {
             let v: Vec<&V> = bst.iter().collect();
             assert_eq!(v, [&e1]);
         }
-        assert_eq!(bst.delete(&e1), Some(e1.clone()));
+        assert_eq!(bst.delete(&e1), Ok(Some(e1.clone())));
         {
             let v: Vec<&V> = bst.iter().collect();
             assert!(v.is_empty());
         }
-        assert_eq!(bst.delete(&e1), None);
+        assert_eq!(bst.delete(&e1), Ok(None));
     }
 
     #[test]
     fn test_delete() {
         test_delete_gen::<u32>(1, 2, 3, 4, 5, 6);
     }
+
+    #[test]
+    fn test_delete_keeps_replacement_subtree() {
+        // Whichever side the replacement for 5 comes from (4 or 6),
+        // it has a child (3 or 7), which has to survive the deletion
+        //       5
+        //     /   \
+        //    2     8
+        //     \   /
+        //      4 6
+        //     /   \
+        //    3     7
+        // The side is chosen randomly, so try a few times
+        for _ in 0..20 {
+            let mut bst = empty_simple_bst::<u32>();
+            for v in [5, 2, 8, 4, 6, 3, 7].iter() {
+                bst.insert(*v);
+            }
+            assert_eq!(bst.delete(&5), Ok(Some(5)));
+            let v: Vec<&u32> = bst.iter().collect();
+            assert_eq!(v, [&2, &3, &4, &6, &7, &8]);
+        }
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut bst: SimpleBst<SimpleBstNode<u32>> = vec![2, 1, 3].into_iter().collect();
+        let h1 = bst.find(&1).unwrap();
+        let h2 = bst.find(&2).unwrap();
+        assert!(h2.ptr_eq(&bst.find(&2).unwrap()));
+        assert!(!h1.ptr_eq(&h2));
+        // Outstanding handles do not stand in the way of a deletion
+        assert_eq!(bst.delete(&2), Ok(Some(2)));
+        assert_eq!(bst.get(&h2), None);
+        assert_eq!(bst.delete_by_handle(&h2), Err(HandleError::Stale));
+        assert_eq!(bst.get(&h1), Some(&1));
+        assert_eq!(bst.delete_by_handle(&h1), Ok(1));
+        assert_eq!(bst.delete_by_handle(&h1), Err(HandleError::Stale));
+        assert!(!bst.contains(&1) && bst.contains(&3));
+
+        // A handle into another tree is stale for this one
+        let other: SimpleBst<SimpleBstNode<u32>> = vec![3].into_iter().collect();
+        let h3 = other.find(&3).unwrap();
+        assert_eq!(bst.get(&h3), None);
+        assert_eq!(bst.delete_by_handle(&h3), Err(HandleError::Stale));
+        assert_eq!(other.get(&h3), Some(&3));
+    }
+
+    #[test]
+    fn test_lca_and_paths() {
+        //       4
+        //     /   \
+        //    2     6
+        //   / \   / \
+        //  1   3 5   7
+        let balanced: SimpleBst<SimpleBstNode<u32>> =
+            vec![4, 2, 6, 1, 3, 5, 7].into_iter().collect();
+        assert_eq!(balanced.lca(&1, &3), Some(&2));
+        assert_eq!(balanced.lca(&3, &5), Some(&4));
+        assert_eq!(balanced.lca(&6, &7), Some(&6));
+        assert_eq!(balanced.lca(&5, &5), Some(&5));
+        assert_eq!(balanced.lca(&1, &8), None);
+        assert_eq!(balanced.path_to(&5), Some(vec![&4, &6, &5]));
+        assert_eq!(balanced.path_to(&4), Some(vec![&4]));
+        assert_eq!(balanced.path_to(&8), None);
+        assert_eq!(balanced.distance(&1, &7), Some(4));
+        assert_eq!(balanced.distance(&2, &3), Some(1));
+        assert_eq!(balanced.distance(&3, &3), Some(0));
+        assert_eq!(balanced.distance(&0, &3), None);
+
+        // 0 - 1 - ... - 9, all right children
+        let skewed: SimpleBst<SimpleBstNode<u32>> = (0..10).collect();
+        assert_eq!(skewed.lca(&3, &8), Some(&3));
+        assert_eq!(skewed.lca(&9, &0), Some(&0));
+        assert_eq!(skewed.path_to(&9).map(|path| path.len()), Some(10));
+        assert_eq!(skewed.distance(&2, &9), Some(7));
+
+        let empty: SimpleBst<SimpleBstNode<u32>> = SimpleBst::new();
+        assert_eq!(empty.lca(&1, &1), None);
+        assert_eq!(empty.path_to(&1), None);
+    }
+
+    #[test]
+    fn test_bulk_removal() {
+        let mut bst: SimpleBst<SimpleBstNode<u32>> =
+            vec![8, 4, 12, 2, 6, 10, 14, 1, 3, 5, 7, 9, 11, 13, 15]
+                .into_iter()
+                .collect();
+        assert_eq!(bst.drain_range(5..9), Ok(vec![5, 6, 7, 8]));
+        assert_eq!(bst.drain_range(5..9), Ok(vec![]));
+        assert_eq!(bst.remove_range(..=2), Ok(2));
+        assert_eq!(
+            bst.remove_range((Bound::Excluded(12), Bound::Unbounded)),
+            Ok(3)
+        );
+        assert_eq!(bst.retain(|v| v % 3 != 0), Ok(()));
+        let v: Vec<&u32> = bst.iter().collect();
+        assert_eq!(v, [&4, &10, &11]);
+
+        // A node referenced through a raw link fails the whole removal
+        let root = bst.get_root().unwrap();
+        assert_eq!(bst.drain_range(..), Err(HandleError::Shared));
+        assert_eq!(bst.retain(|_| false), Err(HandleError::Shared));
+        let v: Vec<&u32> = bst.iter().collect();
+        assert_eq!(v, [&4, &10, &11]);
+        drop(root);
+        assert_eq!(bst.drain_range(..), Ok(vec![4, 10, 11]));
+        assert!(bst.get_root().is_none());
+    }
+
+    #[test]
+    fn test_extremes() {
+        let mut bst: SimpleBst<SimpleBstNode<u32>> = SimpleBst::new();
+        assert_eq!(bst.peek_min(), None);
+        assert_eq!(bst.pop_max(), Ok(None));
+        bst.extend(vec![5, 3, 8, 1, 4, 7, 9]);
+        assert_eq!((bst.peek_min(), bst.peek_max()), (Some(&1), Some(&9)));
+        assert_eq!(bst.pop_min(), Ok(Some(1)));
+        assert_eq!(bst.pop_max(), Ok(Some(9)));
+        assert_eq!(bst.pop_max(), Ok(Some(8)));
+
+        // A node referenced through a raw link is not popped
+        let min = get_extreme(bst.get_root().unwrap(), Direction::Left);
+        assert_eq!(bst.pop_min(), Err(HandleError::Shared));
+        assert_eq!(bst.peek_min(), Some(&3));
+        drop(min);
+        let rest: Vec<u32> = std::iter::from_fn(|| bst.pop_min().unwrap()).collect();
+        assert_eq!(rest, [3, 4, 5, 7]);
+        assert!(bst.get_root().is_none());
+    }
+
+    #[test]
+    fn test_delete_with_outstanding_link() {
+        let mut bst: SimpleBst<SimpleBstNode<u32>> = vec![2, 1, 3].into_iter().collect();
+        let root = bst.get_root().unwrap();
+        let handle = bst.find(&2).unwrap();
+        assert_eq!(bst.delete(&2), Err(HandleError::Shared));
+        assert_eq!(bst.delete_by_handle(&handle), Err(HandleError::Shared));
+        // The tree is left untouched
+        let v: Vec<&u32> = bst.iter().collect();
+        assert_eq!(v, [&1, &2, &3]);
+        drop(root);
+        assert_eq!(bst.delete_by_handle(&handle), Ok(2));
+        let v: Vec<&u32> = bst.iter().collect();
+        assert_eq!(v, [&1, &3]);
+    }
+
+    #[test]
+    fn test_collect_and_extend() {
+        let mut bst: SimpleBst<SimpleBstNode<u32>> = vec![3, 1, 2].into_iter().collect();
+        bst.extend(vec![5, 4, 3]);
+        let v: Vec<&u32> = (&bst).into_iter().collect();
+        assert_eq!(v, [&1, &2, &3, &4, &5]);
+        let mut total = 0;
+        for value in &bst {
+            total += value;
+        }
+        assert_eq!(total, 15);
+        assert!(SimpleBst::<SimpleBstNode<u32>>::default()
+            .get_root()
+            .is_none());
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let bst: SimpleBst<SimpleBstNode<String>> = vec!["d", "b", "f", "a", "c", "e", "g"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let v: Vec<String> = bst.into_iter().collect();
+        assert_eq!(v, ["a", "b", "c", "d", "e", "f", "g"]);
+
+        let bst: SimpleBst<SimpleBstNode<u32>> = (0..100).rev().collect();
+        assert_eq!(
+            bst.into_iter().collect::<Vec<u32>>(),
+            (0..100).collect::<Vec<u32>>()
+        );
+    }
+
+    #[test]
+    fn test_into_iter_with_outstanding_link() {
+        let bst: SimpleBst<SimpleBstNode<u32>> = vec![2, 1, 3].into_iter().collect();
+        let root = bst.get_root().unwrap();
+        let mut iter = bst.into_iter();
+        assert_eq!(iter.by_ref().collect::<Vec<u32>>(), [1, 3]);
+        // The root is shared, so its value cannot be moved out
+        assert_eq!(iter.skipped(), 1);
+        // The outstanding node is left detached, but keeps its value
+        assert_eq!(root.as_value(), &2);
+        assert!(root.is_root() && root.is_leaf());
+    }
+
+    #[test]
+    fn test_into_iter_without_clone() {
+        // Neither `Clone` nor `Copy`, and dropping it leaves a trace
+        #[derive(PartialEq, PartialOrd, Debug)]
+        struct Unique(u32, Rc<()>);
+
+        let witness = Rc::new(());
+        let bst: SimpleBst<SimpleBstNode<Unique>> =
+            (0..10).map(|v| Unique(v, witness.clone())).collect();
+        let mut iter = bst.into_iter();
+        assert_eq!(iter.next().map(|unique| unique.0), Some(0));
+        assert_eq!(iter.next().map(|unique| unique.0), Some(1));
+        assert_eq!(Rc::strong_count(&witness), 9);
+        // Dropping the iterator halfway frees the rest of the tree
+        drop(iter);
+        assert_eq!(Rc::strong_count(&witness), 1);
+    }
+
+    #[test]
+    fn test_clone() {
+        let bst: SimpleBst<SimpleBstNode<u32>> = vec![4, 2, 6, 1, 3, 5, 7].into_iter().collect();
+        let mut copy = bst.clone();
+        assert_eq!(copy, bst);
+        // Same shape, with parent links pointing into the copy
+        {
+            let root = copy.get_root().unwrap();
+            assert_eq!(root.as_value(), &4);
+            for &direction in [Direction::Left, Direction::Right].iter() {
+                let child = root.get_child(direction).unwrap();
+                assert!(Rc::ptr_eq(&child.get_parent().unwrap(), &root));
+                let grandchild = child.get_child(direction).unwrap();
+                assert!(Rc::ptr_eq(&grandchild.get_parent().unwrap(), &child));
+            }
+            assert!(!Rc::ptr_eq(&root, &bst.get_root().unwrap()));
+        }
+        // Copies are independent of each other
+        assert_eq!(copy.delete(&4), Ok(Some(4)));
+        assert_ne!(copy, bst);
+        assert_eq!(bst.iter().count(), 7);
+    }
+
+    #[test]
+    fn test_eq_hash_and_debug() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |bst: &SimpleBst<SimpleBstNode<u32>>| {
+            let mut hasher = DefaultHasher::new();
+            bst.hash(&mut hasher);
+            hasher.finish()
+        };
+        // Different shapes, same values
+        let a: SimpleBst<SimpleBstNode<u32>> = vec![1, 2, 3].into_iter().collect();
+        let b: SimpleBst<SimpleBstNode<u32>> = vec![2, 3, 1].into_iter().collect();
+        let c: SimpleBst<SimpleBstNode<u32>> = vec![1, 2].into_iter().collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(hash(&a), hash(&b));
+        assert_ne!(hash(&a), hash(&c));
+
+        let bst: SimpleBst<SimpleBstNode<&str>> = vec!["b", "a"].into_iter().collect();
+        assert_eq!(format!("{:?}", bst), "{\"a\", \"b\"}");
+    }
 }
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::bst::base::{BstNode, Direction, Link};
+
+/// Shape statistics of a tree, as returned by `Bst::stats`
+///
+/// Depths are counted in edges, so the root has depth 0, while heights
+/// are counted in levels, so a single node tree has height 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeStats {
+    pub size: usize,
+    pub height: usize,
+    pub leaf_count: usize,
+    pub min_leaf_depth: usize,
+    pub avg_leaf_depth: f64,
+    pub max_leaf_depth: usize,
+    /// Sum of the depths of all nodes
+    pub internal_path_length: usize,
+    /// Number of nodes for every observed balance factor, which is
+    /// the height of the right subtree minus the height of the left one
+    pub balance_factors: BTreeMap<isize, usize>,
+}
+
+impl TreeStats {
+    /// The smallest height a tree of `self.size` nodes can have
+    pub fn optimal_height(&self) -> usize {
+        (usize::BITS - self.size.leading_zeros()) as usize
+    }
+
+    /// How many times the tree is higher than it could be,
+    /// `1.0` for a perfectly balanced (or empty) tree
+    pub fn height_ratio(&self) -> f64 {
+        if self.size == 0 {
+            1.0
+        } else {
+            self.height as f64 / self.optimal_height() as f64
+        }
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size:                 {}", self.size)?;
+        writeln!(
+            f,
+            "height:               {} (optimal {}, ratio {:.2})",
+            self.height,
+            self.optimal_height(),
+            self.height_ratio()
+        )?;
+        writeln!(
+            f,
+            "leaf depth:           min {}, avg {:.2}, max {} over {} leaves",
+            self.min_leaf_depth, self.avg_leaf_depth, self.max_leaf_depth, self.leaf_count
+        )?;
+        writeln!(f, "internal path length: {}", self.internal_path_length)?;
+        write!(f, "balance factors:     ")?;
+        for (factor, count) in self.balance_factors.iter() {
+            write!(f, " {:+}: {}", factor, count)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// A node with its depth, the index of its parent and the direction
+/// to reach the node from the parent
+type Visited<Node> = (Link<Node>, usize, Option<(usize, Direction)>);
+
+/// Computes the statistics of a tree rooted at `root`
+///
+/// Runs in O(n) time without recursion, so degenerate trees are fine.
+pub fn compute<Node: BstNode>(root: Option<Link<Node>>) -> TreeStats {
+    // Pre-order, so that every node comes before its descendants
+    let mut nodes: Vec<Visited<Node>> = vec![];
+    let mut stack: Vec<Visited<Node>> = root.into_iter().map(|root| (root, 0, None)).collect();
+    while let Some((node, depth, parent)) = stack.pop() {
+        let index = nodes.len();
+        for &direction in [Direction::Right, Direction::Left].iter() {
+            if let Some(child) = node.get_child(direction) {
+                stack.push((child, depth + 1, Some((index, direction))));
+            }
+        }
+        nodes.push((node, depth, parent));
+    }
+
+    let mut stats = TreeStats {
+        size: nodes.len(),
+        height: 0,
+        leaf_count: 0,
+        min_leaf_depth: 0,
+        avg_leaf_depth: 0.0,
+        max_leaf_depth: 0,
+        internal_path_length: 0,
+        balance_factors: BTreeMap::new(),
+    };
+    let mut leaf_depth_sum = 0;
+    // Heights of the left and right subtrees of every node
+    let mut child_heights = vec![(0, 0); nodes.len()];
+    for (index, (node, depth, parent)) in nodes.iter().enumerate().rev() {
+        stats.internal_path_length += depth;
+        if node.is_leaf() {
+            stats.min_leaf_depth = if stats.leaf_count == 0 {
+                *depth
+            } else {
+                stats.min_leaf_depth.min(*depth)
+            };
+            stats.max_leaf_depth = stats.max_leaf_depth.max(*depth);
+            stats.leaf_count += 1;
+            leaf_depth_sum += depth;
+        }
+
+        let (left, right) = child_heights[index];
+        *stats
+            .balance_factors
+            .entry(right as isize - left as isize)
+            .or_insert(0) += 1;
+        let height = 1 + left.max(right);
+        match parent {
+            Some((parent, Direction::Left)) => child_heights[*parent].0 = height,
+            Some((parent, Direction::Right)) => child_heights[*parent].1 = height,
+            None => stats.height = height,
+        }
+    }
+    if stats.leaf_count > 0 {
+        stats.avg_leaf_depth = leaf_depth_sum as f64 / stats.leaf_count as f64;
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::base::Bst;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+    fn stats_of(values: Vec<u32>) -> TreeStats {
+        let mut bst = SimpleBst::<SimpleBstNode<u32>>::new();
+        for v in values {
+            bst.insert(v);
+        }
+        bst.stats()
+    }
+
+    #[test]
+    fn test_empty_tree_stats() {
+        let stats = stats_of(vec![]);
+        assert_eq!(stats.size, 0);
+        assert_eq!(stats.height, 0);
+        assert_eq!(stats.leaf_count, 0);
+        assert_eq!(stats.optimal_height(), 0);
+        assert_eq!(stats.height_ratio(), 1.0);
+        assert!(stats.balance_factors.is_empty());
+    }
+
+    #[test]
+    fn test_balanced_tree_stats() {
+        //       4
+        //     /   \
+        //    2     6
+        //   / \   / \
+        //  1   3 5   7
+        let stats = stats_of(vec![4, 2, 6, 1, 3, 5, 7]);
+        assert_eq!(stats.size, 7);
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.optimal_height(), 3);
+        assert_eq!(stats.height_ratio(), 1.0);
+        assert_eq!(stats.leaf_count, 4);
+        assert_eq!(stats.min_leaf_depth, 2);
+        assert_eq!(stats.avg_leaf_depth, 2.0);
+        assert_eq!(stats.max_leaf_depth, 2);
+        assert_eq!(stats.internal_path_length, 10);
+        assert_eq!(stats.balance_factors, vec![(0, 7)].into_iter().collect());
+    }
+
+    #[test]
+    fn test_skewed_tree_stats() {
+        //   2
+        //  / \
+        // 1   3
+        //      \
+        //       4
+        //        \
+        //         5
+        let stats = stats_of(vec![2, 1, 3, 4, 5]);
+        assert_eq!(stats.size, 5);
+        assert_eq!(stats.height, 4);
+        assert_eq!(stats.optimal_height(), 3);
+        assert_eq!(stats.leaf_count, 2);
+        assert_eq!(stats.min_leaf_depth, 1);
+        assert_eq!(stats.avg_leaf_depth, 2.0);
+        assert_eq!(stats.max_leaf_depth, 3);
+        assert_eq!(stats.internal_path_length, 7);
+        assert_eq!(
+            stats.balance_factors,
+            vec![(0, 2), (1, 1), (2, 2)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn test_degenerate_tree_stats() {
+        let stats = stats_of((0..2_000).collect());
+        assert_eq!(stats.height, 2_000);
+        assert_eq!(stats.optimal_height(), 11);
+        assert_eq!(stats.leaf_count, 1);
+        assert_eq!(stats.internal_path_length, 2_000 * 1_999 / 2);
+        assert_eq!(stats.balance_factors.len(), 2_000);
+    }
+}
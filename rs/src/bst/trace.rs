@@ -0,0 +1,490 @@
+//! Recording operation sequences on trees and replaying them exactly
+//!
+//! A `Recorder` wraps a tree and keeps every operation applied through it,
+//! with its outcome and the random choices the tree made for it, such as
+//! the deletion side of `SimpleBst`. Replaying the trace forces the same
+//! choices, so the tree goes through the same shapes, and checks that
+//! every operation has the recorded outcome again.
+//!
+//! Text format, one step per line:
+//!
+//! ```text
+//! insert <value> [choices]
+//! delete <value> deleted|absent|shared [choices]
+//! find <value> found|missing [choices]
+//! iter <value>*
+//! ```
+//!
+//! Choices are written as one letter per choice, `L` or `R`. Empty lines
+//! and lines starting with `#` are ignored. Values are written with their
+//! `Display` implementation and read back with `FromStr`, so they must
+//! not contain whitespace.
+
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::bst::base::{Bst, BstNode, Direction};
+use crate::bst::handle::HandleError;
+use crate::bst::model::{apply_to_tree, Op, Outcome};
+
+/// An operation together with what came out of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step<V> {
+    pub op: Op<V>,
+    pub outcome: Outcome<V>,
+    /// The random choices the tree made while applying `op`
+    pub choices: Vec<Direction>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace<V> {
+    pub steps: Vec<Step<V>>,
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    /// Line `line`, counted from 1, is not a valid step
+    Syntax {
+        line: usize,
+        reason: String,
+    },
+    /// Step `step`, counted from 0, had another outcome or made other
+    /// choices on replay than recorded
+    Diverged {
+        step: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Io(e) => write!(f, "I/O error: {}", e),
+            TraceError::Syntax { line, reason } => write!(f, "line {}: {}", line, reason),
+            TraceError::Diverged { step, reason } => {
+                write!(f, "replay diverged at step {}: {}", step, reason)
+            }
+        }
+    }
+}
+
+impl Error for TraceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TraceError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TraceError {
+    fn from(e: io::Error) -> Self {
+        TraceError::Io(e)
+    }
+}
+
+fn write_choices(f: &mut fmt::Formatter<'_>, choices: &[Direction]) -> fmt::Result {
+    if !choices.is_empty() {
+        write!(f, " ")?;
+    }
+    for direction in choices {
+        match direction {
+            Direction::Left => write!(f, "L")?,
+            Direction::Right => write!(f, "R")?,
+        }
+    }
+    Ok(())
+}
+
+impl<V: Display> fmt::Display for Trace<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# bst trace, {} steps", self.steps.len())?;
+        for step in &self.steps {
+            match (&step.op, &step.outcome) {
+                (Op::Insert(v), _) => write!(f, "insert {}", v)?,
+                (Op::Delete(v), outcome) => {
+                    let result = match outcome {
+                        Outcome::Deleted(Some(_)) => "deleted",
+                        Outcome::Failed(_) => "shared",
+                        _ => "absent",
+                    };
+                    write!(f, "delete {} {}", v, result)?;
+                }
+                (Op::Find(v), outcome) => {
+                    let result = if matches!(outcome, Outcome::Found(Some(_))) {
+                        "found"
+                    } else {
+                        "missing"
+                    };
+                    write!(f, "find {} {}", v, result)?;
+                }
+                (Op::Iter, outcome) => {
+                    write!(f, "iter")?;
+                    if let Outcome::Values(values) = outcome {
+                        for v in values {
+                            write!(f, " {}", v)?;
+                        }
+                    }
+                }
+            }
+            // Iteration makes no choices, and its values take the rest of
+            // the line
+            if !matches!(step.op, Op::Iter) {
+                write_choices(f, &step.choices)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_value<V: FromStr>(token: &str) -> Result<V, String>
+where
+    V::Err: Display,
+{
+    token
+        .parse()
+        .map_err(|e| format!("invalid value {:?}: {}", token, e))
+}
+
+fn parse_choices(tokens: &[&str]) -> Result<Vec<Direction>, String> {
+    match tokens {
+        [] => Ok(vec![]),
+        [letters] => letters
+            .chars()
+            .map(|letter| match letter {
+                'L' => Ok(Direction::Left),
+                'R' => Ok(Direction::Right),
+                _ => Err(format!("invalid choice {:?}", letter)),
+            })
+            .collect(),
+        _ => Err("too many fields".to_string()),
+    }
+}
+
+fn parse_step<V: FromStr + Clone>(line: &str) -> Result<Step<V>, String>
+where
+    V::Err: Display,
+{
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let (op, outcome, choices) = match tokens.as_slice() {
+        ["insert", value, choices @ ..] => {
+            (Op::Insert(parse_value(value)?), Outcome::Inserted, choices)
+        }
+        ["delete", value, result, choices @ ..] => {
+            let value: V = parse_value(value)?;
+            let outcome = match *result {
+                "deleted" => Outcome::Deleted(Some(value.clone())),
+                "absent" => Outcome::Deleted(None),
+                "shared" => Outcome::Failed(HandleError::Shared),
+                _ => return Err(format!("invalid delete result {:?}", result)),
+            };
+            (Op::Delete(value), outcome, choices)
+        }
+        ["find", value, result, choices @ ..] => {
+            let value: V = parse_value(value)?;
+            let found = match *result {
+                "found" => Some(value.clone()),
+                "missing" => None,
+                _ => return Err(format!("invalid find result {:?}", result)),
+            };
+            (Op::Find(value), Outcome::Found(found), choices)
+        }
+        ["iter", values @ ..] => {
+            let values = values
+                .iter()
+                .map(|value| parse_value(value))
+                .collect::<Result<_, _>>()?;
+            (Op::Iter, Outcome::Values(values), &[][..])
+        }
+        _ => return Err(format!("invalid step {:?}", line)),
+    };
+    Ok(Step {
+        op,
+        outcome,
+        choices: parse_choices(choices)?,
+    })
+}
+
+impl<V: FromStr + Clone> FromStr for Trace<V>
+where
+    V::Err: Display,
+{
+    type Err = TraceError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut steps = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = parse_step(line).map_err(|reason| TraceError::Syntax {
+                line: index + 1,
+                reason,
+            })?;
+            steps.push(step);
+        }
+        Ok(Trace { steps })
+    }
+}
+
+impl<V: Display> Trace<V> {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl<V: FromStr + Clone> Trace<V>
+where
+    V::Err: Display,
+{
+    pub fn load(path: &Path) -> Result<Self, TraceError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+/// A tree whose operations make random choices, which traces record and
+/// force to reproduce them
+pub trait RecordsChoices {
+    /// Starts or stops keeping the choices the operations make
+    fn record_choices(&self, on: bool);
+
+    /// The choices kept since recording started or since the last call
+    fn take_choices(&self) -> Vec<Direction>;
+
+    /// Makes the next choices be `choices`, in order, replacing any forced
+    /// before
+    fn force_choices(&self, choices: Vec<Direction>);
+}
+
+/// A tree that records the operations applied through it
+pub struct Recorder<Tree: Bst + RecordsChoices> {
+    tree: Tree,
+    trace: Trace<<Tree::Node as BstNode>::Value>,
+}
+
+impl<V, Tree> Recorder<Tree>
+where
+    V: Ord + Clone,
+    Tree: Bst + RecordsChoices,
+    Tree::Node: BstNode<Value = V>,
+{
+    pub fn new(tree: Tree) -> Self {
+        tree.record_choices(true);
+        Self {
+            tree,
+            trace: Trace { steps: vec![] },
+        }
+    }
+
+    pub fn apply(&mut self, op: Op<V>) -> Outcome<V> {
+        let outcome = apply_to_tree(&mut self.tree, &op);
+        self.trace.steps.push(Step {
+            op,
+            outcome: outcome.clone(),
+            choices: self.tree.take_choices(),
+        });
+        outcome
+    }
+
+    pub fn insert(&mut self, value: V) {
+        self.apply(Op::Insert(value));
+    }
+
+    pub fn delete(&mut self, value: &V) -> Result<Option<V>, HandleError> {
+        match self.apply(Op::Delete(value.clone())) {
+            Outcome::Deleted(deleted) => Ok(deleted),
+            Outcome::Failed(error) => Err(error),
+            _ => unreachable!("Deletion has a deletion outcome"),
+        }
+    }
+
+    pub fn contains(&mut self, value: &V) -> bool {
+        self.apply(Op::Find(value.clone())) != Outcome::Found(None)
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    pub fn trace(&self) -> &Trace<V> {
+        &self.trace
+    }
+
+    pub fn into_parts(self) -> (Tree, Trace<V>) {
+        self.tree.record_choices(false);
+        (self.tree, self.trace)
+    }
+}
+
+fn replay_step<V, Tree>(tree: &mut Tree, recorded: &Step<V>) -> Result<(), String>
+where
+    V: Ord + Clone + Debug,
+    Tree: Bst + RecordsChoices,
+    Tree::Node: BstNode<Value = V>,
+{
+    tree.force_choices(recorded.choices.clone());
+    let outcome = apply_to_tree(tree, &recorded.op);
+    let choices = tree.take_choices();
+    if outcome != recorded.outcome {
+        Err(format!(
+            "{:?} gave {:?} instead of {:?}",
+            recorded.op, outcome, recorded.outcome
+        ))
+    } else if choices != recorded.choices {
+        Err(format!(
+            "{:?} made choices {:?} instead of {:?}",
+            recorded.op, choices, recorded.choices
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Applies the steps of `trace` to `tree` with the recorded choices,
+/// stopping at the first step that turns out differently
+pub fn replay<V, Tree>(tree: &mut Tree, trace: &Trace<V>) -> Result<(), TraceError>
+where
+    V: Ord + Clone + Debug,
+    Tree: Bst + RecordsChoices,
+    Tree::Node: BstNode<Value = V>,
+{
+    tree.record_choices(true);
+    let mut result = Ok(());
+    for (step, recorded) in trace.steps.iter().enumerate() {
+        if let Err(reason) = replay_step(tree, recorded) {
+            result = Err(TraceError::Diverged { step, reason });
+            break;
+        }
+    }
+    tree.force_choices(vec![]);
+    tree.record_choices(false);
+    result
+}
+
+/// Replays the trace saved at `path` on a fresh tree, returning the tree
+pub fn replay_file<V, Tree>(path: &Path) -> Result<Tree, TraceError>
+where
+    V: Ord + Clone + Debug + FromStr,
+    V::Err: Display,
+    Tree: Bst + RecordsChoices + Default,
+    Tree::Node: BstNode<Value = V>,
+{
+    let trace = Trace::load(path)?;
+    let mut tree = Tree::default();
+    replay(&mut tree, &trace)?;
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::model::generate_ops;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+    use crate::testutil::temp_path;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    type Tree = SimpleBst<SimpleBstNode<u32>>;
+
+    fn record(seed: u64) -> (Tree, Trace<u32>) {
+        let mut recorder = Recorder::new(Tree::new());
+        for op in generate_ops(&mut StdRng::seed_from_u64(seed), 300, 40) {
+            recorder.apply(op);
+        }
+        recorder.into_parts()
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut recorder = Recorder::new(Tree::new());
+        recorder.insert(2);
+        recorder.insert(1);
+        recorder.insert(3);
+        assert!(recorder.contains(&3));
+        assert_eq!(recorder.delete(&2), Ok(Some(2)));
+        assert_eq!(recorder.delete(&2), Ok(None));
+        recorder.apply(Op::Iter);
+        let (_, trace) = recorder.into_parts();
+        let text = trace.to_string();
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(
+            lines[..4],
+            ["insert 2", "insert 1", "insert 3", "find 3 found"]
+        );
+        assert!(lines[4] == "delete 2 deleted L" || lines[4] == "delete 2 deleted R");
+        assert_eq!(lines[5..], ["delete 2 absent", "iter 1 3"]);
+        assert_eq!(text.parse::<Trace<u32>>().unwrap(), trace);
+
+        for seed in 0..4 {
+            let (_, trace) = record(seed);
+            assert_eq!(trace.to_string().parse::<Trace<u32>>().unwrap(), trace);
+        }
+    }
+
+    #[test]
+    fn test_replay_reproduces_shape() {
+        for seed in 0..8 {
+            let (recorded, trace) = record(seed);
+            assert!(trace.steps.iter().any(|step| !step.choices.is_empty()));
+            let mut replayed = Tree::new();
+            replay(&mut replayed, &trace).unwrap();
+            assert_eq!(replayed.preorder(), recorded.preorder());
+        }
+    }
+
+    #[test]
+    fn test_replay_detects_divergence() {
+        let trace: Trace<u32> = "insert 2\ninsert 1\n\n# comment\ndelete 2 deleted\n"
+            .parse()
+            .unwrap();
+        // The deletion of a node with one child makes no choice
+        let mut tree = Tree::new();
+        replay(&mut tree, &trace).unwrap();
+
+        let trace: Trace<u32> = "insert 2\ninsert 1\ninsert 3\ndelete 2 deleted\n"
+            .parse()
+            .unwrap();
+        match replay(&mut Tree::new(), &trace) {
+            Err(TraceError::Diverged { step: 3, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let trace: Trace<u32> = "insert 2\nfind 3 found\n".parse().unwrap();
+        match replay(&mut Tree::new(), &trace) {
+            Err(TraceError::Diverged { step: 1, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        for (text, line) in [
+            ("insert", 1),
+            ("insert 1\nupdate 1", 2),
+            ("insert x", 1),
+            ("delete 1 gone", 1),
+            ("delete 1 absent LX", 1),
+            ("find 1 found L R", 1),
+        ]
+        .iter()
+        {
+            match text.parse::<Trace<u32>>() {
+                Err(TraceError::Syntax { line: l, .. }) => assert_eq!(l, *line, "{}", text),
+                other => panic!("unexpected result {:?} for {:?}", other, text),
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_file() {
+        let path = temp_path("trace");
+        let (recorded, trace) = record(42);
+        trace.save(&path).unwrap();
+        let replayed: Tree = replay_file(&path).unwrap();
+        assert_eq!(replayed.preorder(), recorded.preorder());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
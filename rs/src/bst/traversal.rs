@@ -0,0 +1,371 @@
+//! Traversal sequences of `SimpleBst`, and rebuilding the exact tree shape
+//! from them
+//!
+//! The values of a search tree are distinct, so a pre-order, post-order
+//! or level-order sequence alone determines the tree: the in-order one
+//! needed for general binary trees is the sorted sequence.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+use crate::bst::base::{
+    dismantle, find_node, flip_direction, value_in_tree, Bst, BstNode, Direction, Link, TreeNode,
+};
+use crate::bst::simple::{SimpleBst, SimpleBstNode};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraversalOrder {
+    PreOrder,
+    PostOrder,
+    LevelOrder,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraversalError {
+    /// The value at `index` occurs elsewhere in the sequence too
+    Duplicate { index: usize },
+    /// The value at `index` cannot be where it is in a traversal of any
+    /// search tree holding the values around it
+    OutOfPlace { order: TraversalOrder, index: usize },
+}
+
+impl fmt::Display for TraversalOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraversalOrder::PreOrder => write!(f, "pre-order"),
+            TraversalOrder::PostOrder => write!(f, "post-order"),
+            TraversalOrder::LevelOrder => write!(f, "level-order"),
+        }
+    }
+}
+
+impl fmt::Display for TraversalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraversalError::Duplicate { index } => {
+                write!(f, "value at index {} occurs more than once", index)
+            }
+            TraversalError::OutOfPlace { order, index } => write!(
+                f,
+                "value at index {} is out of place in a {} traversal of a search tree",
+                index, order
+            ),
+        }
+    }
+}
+
+impl Error for TraversalError {}
+
+type NodeLink<Value> = Link<SimpleBstNode<Value>>;
+
+/// A place for a child in a tree under construction, which takes the
+/// values strictly between the bounds
+struct Slot<Value: PartialEq + PartialOrd> {
+    parent: NodeLink<Value>,
+    direction: Direction,
+    lower: Option<NodeLink<Value>>,
+    upper: Option<NodeLink<Value>>,
+}
+
+fn attach<Value: PartialEq + PartialOrd>(
+    parent: &NodeLink<Value>,
+    direction: Direction,
+    child: &NodeLink<Value>,
+) {
+    parent.set_child(direction, Some(child.clone()));
+    child.set_parent(Some(parent.clone()));
+}
+
+/// Tells a duplicate from any other reason for `value` to be rejected
+fn rejection<Value: PartialEq + PartialOrd>(
+    root: &Option<NodeLink<Value>>,
+    value: &Value,
+    order: TraversalOrder,
+    index: usize,
+) -> TraversalError {
+    if find_node(root.clone(), value).is_some() {
+        TraversalError::Duplicate { index }
+    } else {
+        TraversalError::OutOfPlace { order, index }
+    }
+}
+
+/// Builds a tree from a sequence listing every node before its `first`
+/// subtree, and that before the other one
+///
+/// That is the pre-order with `first` on the left, and the reversed
+/// post-order with `first` on the right.
+fn from_root_first<Value, I>(
+    values: I,
+    first: Direction,
+    order: TraversalOrder,
+) -> Result<Option<NodeLink<Value>>, TraversalError>
+where
+    Value: PartialEq + PartialOrd,
+    I: Iterator<Item = (usize, Value)>,
+{
+    // Whether `a` is on the `first` side of `b`
+    let before = |a: &Value, b: &Value| match first {
+        Direction::Left => a < b,
+        Direction::Right => a > b,
+    };
+    let mut root = None;
+    // The path to the last node, without the nodes the path leaves on
+    // their `first` side, whose subtrees are complete
+    let mut stack: Vec<NodeLink<Value>> = vec![];
+    // The latest node left on its `first` side: all values to come have
+    // to be on its other side
+    let mut bound: Option<NodeLink<Value>> = None;
+    for (index, value) in values {
+        let out_of_bound = bound
+            .as_ref()
+            .is_some_and(|bound| !before(bound.as_value(), &value));
+        let mut last_popped = None;
+        while stack
+            .last()
+            .is_some_and(|top| before(top.as_value(), &value))
+        {
+            last_popped = stack.pop();
+        }
+        let beside_top = stack
+            .last()
+            .is_some_and(|top| !before(&value, top.as_value()));
+        if out_of_bound || beside_top {
+            let error = rejection(&root, &value, order, index);
+            dismantle(root);
+            return Err(error);
+        }
+
+        let node = Link::new(SimpleBstNode::new(value));
+        match (last_popped, stack.last()) {
+            (Some(popped), _) => {
+                attach(&popped, flip_direction(first), &node);
+                bound = Some(popped);
+            }
+            (None, Some(top)) => attach(top, first, &node),
+            (None, None) => root = Some(node.clone()),
+        }
+        stack.push(node);
+    }
+    Ok(root)
+}
+
+impl<Value: PartialEq + PartialOrd> SimpleBst<SimpleBstNode<Value>> {
+    /// Rebuilds the tree with the pre-order traversal `values`
+    pub fn from_preorder<I: IntoIterator<Item = Value>>(values: I) -> Result<Self, TraversalError> {
+        let root = from_root_first(
+            values.into_iter().enumerate(),
+            Direction::Left,
+            TraversalOrder::PreOrder,
+        )?;
+        Ok(Self::from_root(root))
+    }
+
+    /// Rebuilds the tree with the post-order traversal `values`
+    pub fn from_postorder<I: IntoIterator<Item = Value>>(
+        values: I,
+    ) -> Result<Self, TraversalError> {
+        // Read backwards, a post-order lists the root, the right subtree
+        // and then the left one
+        let values: Vec<Value> = values.into_iter().collect();
+        let root = from_root_first(
+            values.into_iter().enumerate().rev(),
+            Direction::Right,
+            TraversalOrder::PostOrder,
+        )?;
+        Ok(Self::from_root(root))
+    }
+
+    /// Rebuilds the tree with the level-order traversal `values`
+    pub fn from_level_order<I: IntoIterator<Item = Value>>(
+        values: I,
+    ) -> Result<Self, TraversalError> {
+        let mut root = None;
+        // The places where children can still go, in level order, with
+        // the bounds of the values that fit there
+        let mut slots: VecDeque<Slot<Value>> = VecDeque::new();
+        for (index, value) in values.into_iter().enumerate() {
+            let (parent, lower, upper) = if root.is_none() {
+                (None, None, None)
+            } else {
+                // Skipped slots stay empty: their level has moved on
+                loop {
+                    let slot = match slots.pop_front() {
+                        Some(slot) => slot,
+                        None => {
+                            let error = rejection(&root, &value, TraversalOrder::LevelOrder, index);
+                            dismantle(root);
+                            return Err(error);
+                        }
+                    };
+                    let fits = slot
+                        .lower
+                        .as_ref()
+                        .is_none_or(|lower| lower.as_value() < &value)
+                        && slot
+                            .upper
+                            .as_ref()
+                            .is_none_or(|upper| &value < upper.as_value());
+                    if fits {
+                        break (Some((slot.parent, slot.direction)), slot.lower, slot.upper);
+                    }
+                }
+            };
+            let node = Link::new(SimpleBstNode::new(value));
+            match parent {
+                Some((parent, direction)) => attach(&parent, direction, &node),
+                None => root = Some(node.clone()),
+            }
+            slots.push_back(Slot {
+                parent: node.clone(),
+                direction: Direction::Left,
+                lower,
+                upper: Some(node.clone()),
+            });
+            slots.push_back(Slot {
+                parent: node.clone(),
+                direction: Direction::Right,
+                lower: Some(node),
+                upper,
+            });
+        }
+        Ok(Self::from_root(root))
+    }
+
+    pub fn preorder(&self) -> Vec<&Value> {
+        let mut values = vec![];
+        let mut stack: Vec<NodeLink<Value>> = self.get_root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            values.push(unsafe { value_in_tree(self, &node) });
+            stack.extend(node.get_child(Direction::Right));
+            stack.extend(node.get_child(Direction::Left));
+        }
+        values
+    }
+
+    pub fn postorder(&self) -> Vec<&Value> {
+        // The reversed pre-order of the mirrored tree
+        let mut values = vec![];
+        let mut stack: Vec<NodeLink<Value>> = self.get_root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            values.push(unsafe { value_in_tree(self, &node) });
+            stack.extend(node.get_child(Direction::Left));
+            stack.extend(node.get_child(Direction::Right));
+        }
+        values.reverse();
+        values
+    }
+
+    pub fn level_order(&self) -> Vec<&Value> {
+        let mut values = vec![];
+        let mut queue: VecDeque<NodeLink<Value>> = self.get_root().into_iter().collect();
+        while let Some(node) = queue.pop_front() {
+            values.push(unsafe { value_in_tree(self, &node) });
+            queue.extend(node.get_child(Direction::Left));
+            queue.extend(node.get_child(Direction::Right));
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use std::rc::Rc;
+
+    type Tree = SimpleBst<SimpleBstNode<u32>>;
+
+    fn cloned(values: Vec<&u32>) -> Vec<u32> {
+        values.into_iter().cloned().collect()
+    }
+
+    /// Checks that `tree` has the shape of `expected`, parent links included
+    fn assert_same_shape(tree: &Tree, expected: &Tree) {
+        assert_eq!(tree.preorder(), expected.preorder());
+        assert_eq!(tree.level_order(), expected.level_order());
+        for value in expected.iter() {
+            assert_eq!(tree.path_to(value), expected.path_to(value));
+        }
+    }
+
+    #[test]
+    fn test_traversals() {
+        //       4
+        //     /   \
+        //    2     6
+        //     \   /
+        //      3 5
+        let tree: Tree = vec![4, 2, 6, 3, 5].into_iter().collect();
+        assert_eq!(cloned(tree.preorder()), [4, 2, 3, 6, 5]);
+        assert_eq!(cloned(tree.postorder()), [3, 2, 5, 6, 4]);
+        assert_eq!(cloned(tree.level_order()), [4, 2, 6, 3, 5]);
+        assert_same_shape(&Tree::from_preorder(vec![4, 2, 3, 6, 5]).unwrap(), &tree);
+        assert_same_shape(&Tree::from_postorder(vec![3, 2, 5, 6, 4]).unwrap(), &tree);
+        assert_same_shape(&Tree::from_level_order(vec![4, 2, 6, 3, 5]).unwrap(), &tree);
+        assert!(Tree::from_preorder(vec![]).unwrap().get_root().is_none());
+        assert!(Tree::from_level_order(vec![]).unwrap().get_root().is_none());
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for size in 0..60 {
+            let mut values: Vec<u32> = (0..size).collect();
+            values.shuffle(&mut rng);
+            let tree: Tree = values.into_iter().collect();
+            let preorder = Tree::from_preorder(cloned(tree.preorder())).unwrap();
+            let postorder = Tree::from_postorder(cloned(tree.postorder())).unwrap();
+            let level_order = Tree::from_level_order(cloned(tree.level_order())).unwrap();
+            for rebuilt in [preorder, postorder, level_order].iter() {
+                assert_same_shape(rebuilt, &tree);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_sequences() {
+        let out_of_place = |order, index| TraversalError::OutOfPlace { order, index };
+        let duplicate = |index| TraversalError::Duplicate { index };
+        // 0 comes after the right subtree of 2 was started, so it cannot
+        // be smaller than 2
+        assert_eq!(
+            Tree::from_preorder(vec![2, 1, 4, 3, 0]).unwrap_err(),
+            out_of_place(TraversalOrder::PreOrder, 4)
+        );
+        assert_eq!(
+            Tree::from_preorder(vec![5, 3, 7, 3]).unwrap_err(),
+            duplicate(3)
+        );
+        assert_eq!(
+            Tree::from_postorder(vec![3, 1, 2]).unwrap_err(),
+            out_of_place(TraversalOrder::PostOrder, 0)
+        );
+        assert_eq!(Tree::from_postorder(vec![1, 1]).unwrap_err(), duplicate(0));
+        // 3 belongs under 2, whose children had to come before 5's
+        assert_eq!(
+            Tree::from_level_order(vec![4, 2, 6, 1, 5, 3]).unwrap_err(),
+            out_of_place(TraversalOrder::LevelOrder, 5)
+        );
+        assert_eq!(
+            Tree::from_level_order(vec![4, 2, 6, 2]).unwrap_err(),
+            duplicate(3)
+        );
+        // The nodes built before the rejected value are freed
+        let witness = Rc::new(());
+        let counted = |values: Vec<u32>| -> Vec<(u32, Rc<()>)> {
+            values.into_iter().map(|v| (v, witness.clone())).collect()
+        };
+        assert!(SimpleBst::from_preorder(counted(vec![2, 1, 4, 3, 0])).is_err());
+        assert!(SimpleBst::from_postorder(counted(vec![3, 1, 2])).is_err());
+        assert!(SimpleBst::from_level_order(counted(vec![4, 2, 6, 1, 5, 3])).is_err());
+        assert_eq!(Rc::strong_count(&witness), 1);
+        assert_eq!(
+            out_of_place(TraversalOrder::LevelOrder, 5).to_string(),
+            "value at index 5 is out of place in a level-order traversal of a search tree"
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! An in-memory B-tree ordered set
+//!
+//! Every node keeps up to `FANOUT - 1` sorted values in one `Vec` and,
+//! unless it is a leaf, `FANOUT` children in another, so a search looks
+//! at a few contiguous values per level instead of following a `Link` per
+//! comparison. All leaves are at the same depth, which is O(log n) with
+//! base `FANOUT / 2`.
+
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+struct BTreeNode<Value> {
+    values: Vec<Value>,
+    /// Empty for leaves, one more than `values` otherwise
+    children: Vec<BTreeNode<Value>>,
+}
+
+/// Outcome of inserting into a subtree
+enum Insertion<Value> {
+    Duplicate,
+    Inserted,
+    /// The subtree root overflowed and got split around the value
+    Split(Value, BTreeNode<Value>),
+}
+
+/// An ordered set stored in a B-tree with `FANOUT` children per node
+///
+/// `FANOUT` has to be at least 3.
+pub struct BTree<Value, const FANOUT: usize> {
+    root: BTreeNode<Value>,
+    len: usize,
+}
+
+impl<Value> BTreeNode<Value> {
+    fn new(fanout: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(fanout),
+            children: vec![],
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn height(&self) -> usize {
+        match self.children.first() {
+            Some(child) => 1 + child.height(),
+            None => 1,
+        }
+    }
+}
+
+impl<Value: Ord> BTreeNode<Value> {
+    fn insert(&mut self, value: Value, fanout: usize) -> Insertion<Value> {
+        let index = match self.values.binary_search(&value) {
+            Ok(_) => return Insertion::Duplicate,
+            Err(index) => index,
+        };
+        if self.is_leaf() {
+            self.values.insert(index, value);
+        } else {
+            match self.children[index].insert(value, fanout) {
+                Insertion::Split(middle, right) => {
+                    self.values.insert(index, middle);
+                    self.children.insert(index + 1, right);
+                }
+                other => return other,
+            }
+        }
+        if self.values.len() < fanout {
+            return Insertion::Inserted;
+        }
+        let middle_index = self.values.len() / 2;
+        let mut right = Self::new(fanout);
+        right.values.extend(self.values.drain(middle_index + 1..));
+        if !self.is_leaf() {
+            right.children = self.children.split_off(middle_index + 1);
+        }
+        let middle = self.values.pop().unwrap();
+        Insertion::Split(middle, right)
+    }
+
+    fn delete(&mut self, value: &Value, min_len: usize) -> Option<Value> {
+        match (self.values.binary_search(value), self.is_leaf()) {
+            (Ok(index), true) => Some(self.values.remove(index)),
+            (Ok(index), false) => {
+                // Swap in the predecessor, which is always in a leaf
+                let predecessor = self.children[index].pop_last(min_len);
+                let deleted = mem::replace(&mut self.values[index], predecessor);
+                self.fix_child(index, min_len);
+                Some(deleted)
+            }
+            (Err(_), true) => None,
+            (Err(index), false) => {
+                let deleted = self.children[index].delete(value, min_len);
+                if deleted.is_some() {
+                    self.fix_child(index, min_len);
+                }
+                deleted
+            }
+        }
+    }
+
+    fn pop_last(&mut self, min_len: usize) -> Value {
+        if self.is_leaf() {
+            return self.values.pop().unwrap();
+        }
+        let last = self.children.len() - 1;
+        let value = self.children[last].pop_last(min_len);
+        self.fix_child(last, min_len);
+        value
+    }
+
+    /// Brings the child at `index` back to at least `min_len` values by
+    /// borrowing one from a sibling or, if they have none to spare,
+    /// merging it with one
+    fn fix_child(&mut self, index: usize, min_len: usize) {
+        if self.children[index].values.len() >= min_len {
+            return;
+        }
+        if index > 0 && self.children[index - 1].values.len() > min_len {
+            let (left, right) = self.children.split_at_mut(index);
+            let (left, child) = (&mut left[index - 1], &mut right[0]);
+            let separator = mem::replace(&mut self.values[index - 1], left.values.pop().unwrap());
+            child.values.insert(0, separator);
+            if let Some(grandchild) = left.children.pop() {
+                child.children.insert(0, grandchild);
+            }
+        } else if index + 1 < self.children.len() && self.children[index + 1].values.len() > min_len
+        {
+            let (left, right) = self.children.split_at_mut(index + 1);
+            let (child, right) = (&mut left[index], &mut right[0]);
+            let separator = mem::replace(&mut self.values[index], right.values.remove(0));
+            child.values.push(separator);
+            if !right.is_leaf() {
+                child.children.push(right.children.remove(0));
+            }
+        } else {
+            let left_index = if index > 0 { index - 1 } else { index };
+            let right = self.children.remove(left_index + 1);
+            let separator = self.values.remove(left_index);
+            let left = &mut self.children[left_index];
+            left.values.push(separator);
+            left.values.extend(right.values);
+            left.children.extend(right.children);
+        }
+    }
+}
+
+impl<Value: Ord, const FANOUT: usize> BTree<Value, FANOUT> {
+    pub fn new() -> Self {
+        assert!(FANOUT >= 3, "B-tree fanout (is {}) should be >= 3", FANOUT);
+        Self {
+            root: BTreeNode::new(FANOUT),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of levels, 1 for an empty tree
+    pub fn height(&self) -> usize {
+        self.root.height()
+    }
+
+    /// Fewest values a node other than the root may hold
+    fn min_len() -> usize {
+        (FANOUT - 1) / 2
+    }
+
+    /// Inserts `value`, ignoring duplicates like `Bst::insert` does
+    pub fn insert(&mut self, value: Value) {
+        match self.root.insert(value, FANOUT) {
+            Insertion::Duplicate => return,
+            Insertion::Inserted => {}
+            Insertion::Split(middle, right) => {
+                let mut root = BTreeNode::new(FANOUT);
+                root.values.push(middle);
+                let left = mem::replace(&mut self.root, root);
+                self.root.children = vec![left, right];
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Removes `value` from the tree, returning it if it was present
+    pub fn delete(&mut self, value: &Value) -> Option<Value> {
+        let deleted = self.root.delete(value, Self::min_len())?;
+        if self.root.values.is_empty() {
+            if let Some(child) = self.root.children.pop() {
+                self.root = child;
+            }
+        }
+        self.len -= 1;
+        Some(deleted)
+    }
+
+    /// Returns the stored value equal to `value`, if any
+    pub fn find(&self, value: &Value) -> Option<&Value> {
+        let mut node = &self.root;
+        loop {
+            match node.values.binary_search(value) {
+                Ok(index) => return Some(&node.values[index]),
+                Err(_) if node.is_leaf() => return None,
+                Err(index) => node = &node.children[index],
+            }
+        }
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        self.find(value).is_some()
+    }
+
+    /// The first value after all those `goes_right` holds for, which has
+    /// to be a prefix of the values
+    fn partition_point<F: Fn(&Value) -> bool>(&self, goes_right: F) -> Option<&Value> {
+        let mut node = &self.root;
+        let mut found = None;
+        loop {
+            let index = node.values.partition_point(&goes_right);
+            found = node.values.get(index).or(found);
+            if node.is_leaf() {
+                return found;
+            }
+            node = &node.children[index];
+        }
+    }
+
+    /// Iterates over the values in `range` in order
+    pub fn range<R: RangeBounds<Value>>(&self, range: R) -> Iter<'_, Value> {
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.partition_point(|v| v <= end),
+            Bound::Excluded(end) => self.partition_point(|v| v < end),
+            Bound::Unbounded => None,
+        };
+        let mut iter = Iter { stack: vec![], end };
+        let first = match range.start_bound() {
+            Bound::Included(start) => self.partition_point(|v| v < start),
+            Bound::Excluded(start) => self.partition_point(|v| v <= start),
+            Bound::Unbounded => self.partition_point(|_| false),
+        };
+        // A reversed range has its end before its start, and the iterator
+        // would never reach it
+        match (first, end) {
+            (None, _) => return iter,
+            (Some(first), Some(end)) if end <= first => return iter,
+            _ => {}
+        }
+        let mut node = &self.root;
+        loop {
+            let index = match range.start_bound() {
+                Bound::Included(start) => node.values.partition_point(|v| v < start),
+                Bound::Excluded(start) => node.values.partition_point(|v| v <= start),
+                Bound::Unbounded => 0,
+            };
+            iter.stack.push((node, index));
+            if node.is_leaf() {
+                return iter;
+            }
+            node = &node.children[index];
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, Value> {
+        self.range(..)
+    }
+}
+
+/// In-order iterator over a range of a `BTree`
+pub struct Iter<'a, Value> {
+    /// The path to the next value, with the index of the next value to
+    /// yield at every level, whose child before it is being walked
+    stack: Vec<(&'a BTreeNode<Value>, usize)>,
+    /// The first value not to yield, `None` to go until the end
+    end: Option<&'a Value>,
+}
+
+impl<'a, Value> Iterator for Iter<'a, Value> {
+    type Item = &'a Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, index)) = self.stack.pop() {
+            if index == node.values.len() {
+                continue;
+            }
+            let value = &node.values[index];
+            if self.end.is_some_and(|end| std::ptr::eq(end, value)) {
+                self.stack.clear();
+                return None;
+            }
+            self.stack.push((node, index + 1));
+            if !node.is_leaf() {
+                let mut child = &node.children[index + 1];
+                loop {
+                    self.stack.push((child, 0));
+                    match child.children.first() {
+                        Some(grandchild) => child = grandchild,
+                        None => break,
+                    }
+                }
+            }
+            return Some(value);
+        }
+        None
+    }
+}
+
+impl<'a, Value: Ord, const FANOUT: usize> IntoIterator for &'a BTree<Value, FANOUT> {
+    type Item = &'a Value;
+    type IntoIter = Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<Value: Ord, const FANOUT: usize> Default for BTree<Value, FANOUT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Value: Ord, const FANOUT: usize> FromIterator<Value> for BTree<Value, FANOUT> {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<Value: Ord, const FANOUT: usize> Extend<Value> for BTree<Value, FANOUT> {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<Value: Ord + Debug, const FANOUT: usize> Debug for BTree<Value, FANOUT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    /// Checks the node sizes and that all leaves are at the same depth,
+    /// returning the height
+    fn check_invariants<Value: Ord>(
+        node: &BTreeNode<Value>,
+        fanout: usize,
+        is_root: bool,
+    ) -> usize {
+        assert!(node.values.len() < fanout);
+        if !is_root {
+            assert!(node.values.len() >= (fanout - 1) / 2);
+        }
+        assert!(node.values.windows(2).all(|pair| pair[0] < pair[1]));
+        if node.is_leaf() {
+            return 1;
+        }
+        assert_eq!(node.children.len(), node.values.len() + 1);
+        let heights: Vec<usize> = node
+            .children
+            .iter()
+            .map(|child| check_invariants(child, fanout, false))
+            .collect();
+        assert!(heights.iter().all(|&height| height == heights[0]));
+        1 + heights[0]
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut tree: BTree<u32, 3> = (0..100).rev().collect();
+        assert_eq!(tree.len(), 100);
+        assert_eq!(check_invariants(&tree.root, 3, true), tree.height());
+        assert!(tree.height() <= 7);
+        tree.insert(50);
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.find(&42), Some(&42));
+        assert_eq!(tree.delete(&42), Some(42));
+        assert_eq!(tree.delete(&42), None);
+        assert!(!tree.contains(&42) && tree.contains(&43));
+        for value in 0..100 {
+            tree.delete(&value);
+            check_invariants(&tree.root, 3, true);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.height(), 1);
+        assert_eq!(format!("{:?}", tree), "{}");
+    }
+
+    #[test]
+    fn test_range() {
+        let tree: BTree<u32, 4> = (0..60).map(|v| 3 * v).collect();
+        let expected = |lo: u32, hi: u32| {
+            (lo..hi)
+                .filter(|v| v % 3 == 0 && *v < 180)
+                .collect::<Vec<u32>>()
+        };
+        for lo in 0..190 {
+            for hi in lo..190 {
+                assert_eq!(
+                    tree.range(lo..hi).cloned().collect::<Vec<_>>(),
+                    expected(lo, hi)
+                );
+            }
+            assert_eq!(
+                tree.range(lo..).cloned().collect::<Vec<_>>(),
+                expected(lo, 1_000)
+            );
+            assert_eq!(
+                tree.range(..=lo).cloned().collect::<Vec<_>>(),
+                expected(0, lo + 1)
+            );
+            assert_eq!(
+                tree.range((Bound::Excluded(lo), Bound::Unbounded))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                expected(lo + 1, 1_000)
+            );
+        }
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let model: BTreeSet<u32> = (0..20).map(|v| 2 * v).collect();
+        let tree: BTree<u32, 3> = model.iter().cloned().collect();
+        let bounds = |v: u32| vec![Bound::Included(v), Bound::Excluded(v), Bound::Unbounded];
+        // `BTreeSet::range` panics for reversed and equal excluded bounds,
+        // which have to come out empty instead
+        for lo in 0..42 {
+            for hi in 0..42 {
+                for &start in bounds(lo).iter() {
+                    for &end in bounds(hi).iter() {
+                        let range = (start, end);
+                        assert_eq!(
+                            tree.range(range).collect::<Vec<_>>(),
+                            model
+                                .iter()
+                                .filter(|v| range.contains(v))
+                                .collect::<Vec<_>>(),
+                            "{:?}",
+                            range
+                        );
+                    }
+                }
+            }
+        }
+        let (lo, hi) = (7, 3);
+        assert_eq!(tree.range(lo..hi).count(), 0);
+    }
+
+    fn matches_btreeset<const FANOUT: usize>() {
+        let mut rng = StdRng::seed_from_u64(FANOUT as u64);
+        let mut tree: BTree<u32, FANOUT> = BTree::new();
+        let mut model = BTreeSet::new();
+        for _ in 0..5_000 {
+            let value = rng.gen_range(0..500);
+            match rng.gen_range(0..3) {
+                0 => assert_eq!(tree.delete(&value), model.take(&value)),
+                1 => assert_eq!(tree.contains(&value), model.contains(&value)),
+                _ => {
+                    tree.insert(value);
+                    model.insert(value);
+                }
+            }
+            assert_eq!(tree.len(), model.len());
+        }
+        check_invariants(&tree.root, FANOUT, true);
+        assert!(tree.iter().eq(model.iter()));
+        assert!(tree.range(100..400).eq(model.range(100..400)));
+    }
+
+    #[test]
+    fn test_matches_btreeset() {
+        matches_btreeset::<3>();
+        matches_btreeset::<4>();
+        matches_btreeset::<5>();
+        matches_btreeset::<16>();
+    }
+}
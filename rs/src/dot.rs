@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+use std::hash::Hash;
+
+use crate::bst::base::{Bst, BstNode, Direction, Link, TreeNode};
+use crate::dsu::Forest;
+
+/// Optional parts of the BST rendering
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Draw a dashed edge from every node to the node its parent link points at
+    pub parent_edges: bool,
+}
+
+/// Escapes a label so that it can be placed inside a double-quoted DOT string
+fn escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders `tree` as a Graphviz DOT digraph
+pub fn bst_to_dot<Tree>(tree: &Tree, options: DotOptions) -> String
+where
+    Tree: Bst + ?Sized,
+    <Tree::Node as BstNode>::Value: Display,
+{
+    bst_to_dot_with_labels(tree, options, |_| None)
+}
+
+/// Renders `tree` as a Graphviz DOT digraph, appending the output of
+/// `augmentation` (if any) as a second line of every node label
+///
+/// Nodes are named `n0`, `n1`, ... in pre-order. A missing child of a node
+/// with exactly one child is drawn as an invisible placeholder, so that
+/// left and right children stay on their respective sides in the layout.
+pub fn bst_to_dot_with_labels<Tree, F>(tree: &Tree, options: DotOptions, augmentation: F) -> String
+where
+    Tree: Bst + ?Sized,
+    <Tree::Node as BstNode>::Value: Display,
+    F: Fn(&Tree::Node) -> Option<String>,
+{
+    // Number the nodes first, so that parent edges can refer to any node
+    let mut nodes: Vec<Link<Tree::Node>> = vec![];
+    let mut stack: Vec<Link<Tree::Node>> = tree.get_root().into_iter().collect();
+    while let Some(node) = stack.pop() {
+        stack.extend(node.get_child(Direction::Right));
+        stack.extend(node.get_child(Direction::Left));
+        nodes.push(node);
+    }
+    let ids: HashMap<*const Tree::Node, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(id, node)| (Link::as_ptr(node), id))
+        .collect();
+
+    let mut out = String::from("digraph bst {\n    node [shape=circle];\n");
+    for (id, node) in nodes.iter().enumerate() {
+        let mut label = node.as_value().to_string();
+        if let Some(extra) = augmentation(node) {
+            label.push('\n');
+            label.push_str(&extra);
+        }
+        writeln!(out, "    n{} [label=\"{}\"];", id, escape(&label)).unwrap();
+    }
+    for (id, node) in nodes.iter().enumerate() {
+        let left = node.get_child(Direction::Left);
+        let right = node.get_child(Direction::Right);
+        for (direction, child) in [(Direction::Left, &left), (Direction::Right, &right)].iter() {
+            let side = if *direction == Direction::Left {
+                "L"
+            } else {
+                "R"
+            };
+            match child {
+                Some(child) => writeln!(
+                    out,
+                    "    n{} -> n{} [label=\"{}\"];",
+                    id,
+                    ids[&Link::as_ptr(child)],
+                    side
+                )
+                .unwrap(),
+                None if left.is_some() || right.is_some() => writeln!(
+                    out,
+                    "    n{}_{} [label=\"\", style=invis];\n    n{} -> n{}_{} [style=invis];",
+                    id, side, id, id, side
+                )
+                .unwrap(),
+                None => {}
+            }
+        }
+        if options.parent_edges {
+            if let Some(parent) = node.get_parent() {
+                match ids.get(&Link::as_ptr(&parent)) {
+                    Some(parent_id) => writeln!(
+                        out,
+                        "    n{} -> n{} [style=dashed, color=gray, constraint=false];",
+                        id, parent_id
+                    )
+                    .unwrap(),
+                    // A parent link pointing outside of the tree is exactly
+                    // the kind of bug these drawings are meant to reveal
+                    None => writeln!(
+                        out,
+                        "    n{}_p [label=\"{}\", shape=box, color=red];\n    n{} -> n{}_p [style=dashed, color=red, constraint=false];",
+                        id,
+                        escape(&parent.as_value().to_string()),
+                        id,
+                        id
+                    )
+                    .unwrap(),
+                }
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Renders the trees of `forest` spanned by `elements` as a Graphviz DOT
+/// digraph, with an edge from every element to its parent
+///
+/// Roots are drawn with a double outline. Only `Forest::get_parent` is
+/// used, so rendering does not trigger path compression.
+pub fn forest_to_dot<'a, T, F>(forest: &F, elements: &[&'a T]) -> String
+where
+    T: 'a + Hash + Eq + Display,
+    F: Forest<'a, T>,
+{
+    let ids: HashMap<&'a T, usize> = elements
+        .iter()
+        .enumerate()
+        .map(|(id, el)| (*el, id))
+        .collect();
+
+    let mut out = String::from("digraph forest {\n    rankdir=BT;\n");
+    for (id, el) in elements.iter().enumerate() {
+        let shape = if forest.get_parent(el) == *el {
+            "doublecircle"
+        } else {
+            "circle"
+        };
+        writeln!(
+            out,
+            "    e{} [label=\"{}\", shape={}];",
+            id,
+            escape(&el.to_string()),
+            shape
+        )
+        .unwrap();
+    }
+    for (id, el) in elements.iter().enumerate() {
+        let parent = forest.get_parent(el);
+        if parent != *el {
+            match ids.get(parent) {
+                Some(parent_id) => writeln!(out, "    e{} -> e{};", id, parent_id).unwrap(),
+                None => writeln!(
+                    out,
+                    "    e{}_p [label=\"{}\", shape=box, style=dashed];\n    e{} -> e{}_p;",
+                    id,
+                    escape(&parent.to_string()),
+                    id,
+                    id
+                )
+                .unwrap(),
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bst::simple::{SimpleBst, SimpleBstNode};
+    use crate::dsu::{ForestDsu, DSU};
+
+    #[test]
+    fn test_empty_bst_to_dot() {
+        let bst = SimpleBst::<SimpleBstNode<u32>>::new();
+        assert_eq!(
+            bst_to_dot(&bst, DotOptions::default()),
+            "digraph bst {\n    node [shape=circle];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_bst_to_dot() {
+        //   2
+        //  / \
+        // 1   4
+        //    /
+        //   3
+        let mut bst = SimpleBst::<SimpleBstNode<u32>>::new();
+        for v in [2, 4, 1, 3].iter() {
+            bst.insert(*v);
+        }
+        let expected = "digraph bst {
+    node [shape=circle];
+    n0 [label=\"2\"];
+    n1 [label=\"1\"];
+    n2 [label=\"4\"];
+    n3 [label=\"3\"];
+    n0 -> n1 [label=\"L\"];
+    n0 -> n2 [label=\"R\"];
+    n2 -> n3 [label=\"L\"];
+    n2_R [label=\"\", style=invis];
+    n2 -> n2_R [style=invis];
+}
+";
+        assert_eq!(bst_to_dot(&bst, DotOptions::default()), expected);
+    }
+
+    #[test]
+    fn test_bst_to_dot_with_parent_edges_and_labels() {
+        let mut bst = SimpleBst::<SimpleBstNode<&str>>::new();
+        bst.insert("b");
+        bst.insert("a\"");
+        let dot = bst_to_dot_with_labels(&bst, DotOptions { parent_edges: true }, |node| {
+            Some(format!("len={}", node.as_value().len()))
+        });
+        let expected = "digraph bst {
+    node [shape=circle];
+    n0 [label=\"b\\nlen=1\"];
+    n1 [label=\"a\\\"\\nlen=2\"];
+    n0 -> n1 [label=\"L\"];
+    n0_R [label=\"\", style=invis];
+    n0 -> n0_R [style=invis];
+    n1 -> n0 [style=dashed, color=gray, constraint=false];
+}
+";
+        assert_eq!(dot, expected);
+    }
+
+    #[test]
+    fn test_forest_to_dot() {
+        let elements = [1, 2, 3, 4];
+        let refs: Vec<&u32> = elements.iter().collect();
+        let mut dsu: ForestDsu<u32> = ForestDsu::new();
+        for el in refs.iter() {
+            dsu.insert(el);
+        }
+        // The naive DSU always hangs the second root under the first one:
+        // 3 <- 1 <- 2
+        dsu.join(&elements[0], &elements[1]);
+        dsu.join(&elements[2], &elements[1]);
+        let expected = "digraph forest {
+    rankdir=BT;
+    e0 [label=\"1\", shape=circle];
+    e1 [label=\"2\", shape=circle];
+    e2 [label=\"3\", shape=doublecircle];
+    e3 [label=\"4\", shape=doublecircle];
+    e0 -> e2;
+    e1 -> e0;
+}
+";
+        assert_eq!(forest_to_dot(&dsu, &refs), expected);
+    }
+}
@@ -0,0 +1,421 @@
+//! Euler-tour trees: a forest, which can gain and lose edges, answering
+//! connectivity and subtree queries in O(log n) expected time
+//!
+//! Every tree of the forest is stored as a cyclic sequence: its Euler
+//! tour, with one element per vertex and one per direction of every edge.
+//! The sequences are kept in treaps ordered by position, so a tour can be
+//! rotated to start anywhere, cut into pieces and glued back together by
+//! splitting and merging. Unlike in the other treaps, nodes have parent
+//! pointers, since operations start from a node rather than a position.
+
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+use std::collections::HashMap;
+
+use crate::bst::base::Direction;
+use crate::bst::lazy::Number;
+
+/// Vertex count and value sum of a piece of a tour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TourSummary<Value> {
+    pub size: usize,
+    pub sum: Value,
+}
+
+struct TourNode<Value> {
+    /// `Some` for the element of a vertex, `None` for the one of an edge
+    value: Option<Value>,
+    priority: u32,
+    /// Number of elements in the treap subtree
+    len: usize,
+    /// Summary of the vertices in the treap subtree
+    summary: TourSummary<Value>,
+    parent: Option<usize>,
+    left_child: Option<usize>,
+    right_child: Option<usize>,
+}
+
+/// A forest over vertices `0..len`, with a value at every vertex
+pub struct EulerTourTree<Value> {
+    nodes: Vec<TourNode<Value>>,
+    /// Indices of unused nodes, left over from cut edges
+    free_nodes: Vec<usize>,
+    /// The node of every vertex
+    vertices: Vec<usize>,
+    /// The node of every edge direction
+    edges: HashMap<(usize, usize), usize>,
+    rng: ThreadRng,
+}
+
+impl<Value> TourNode<Value> {
+    fn get_child(&self, direction: Direction) -> Option<usize> {
+        match direction {
+            Direction::Left => self.left_child,
+            Direction::Right => self.right_child,
+        }
+    }
+}
+
+impl<Value: Number> EulerTourTree<Value> {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            free_nodes: vec![],
+            vertices: vec![],
+            edges: HashMap::new(),
+            rng: thread_rng(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    fn new_node(&mut self, value: Option<Value>) -> usize {
+        let node = TourNode {
+            value,
+            priority: self.rng.gen(),
+            len: 1,
+            summary: TourSummary {
+                size: value.map_or(0, |_| 1),
+                sum: value.unwrap_or_else(Value::zero),
+            },
+            parent: None,
+            left_child: None,
+            right_child: None,
+        };
+        match self.free_nodes.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Adds a vertex without any edges, returning its index
+    pub fn add_vertex(&mut self, value: Value) -> usize {
+        let node = self.new_node(Some(value));
+        self.vertices.push(node);
+        self.vertices.len() - 1
+    }
+
+    pub fn get_value(&self, vertex: usize) -> Value {
+        self.nodes[self.vertices[vertex]]
+            .value
+            .expect("Vertex nodes always have a value")
+    }
+
+    pub fn set_value(&mut self, vertex: usize, value: Value) {
+        let mut maybe_node = Some(self.vertices[vertex]);
+        self.nodes[self.vertices[vertex]].value = Some(value);
+        while let Some(node) = maybe_node {
+            self.update(node);
+            maybe_node = self.nodes[node].parent;
+        }
+    }
+
+    fn len_of(&self, tree: Option<usize>) -> usize {
+        tree.map_or(0, |node| self.nodes[node].len)
+    }
+
+    fn update(&mut self, node: usize) {
+        let mut len = 1;
+        let mut summary = TourSummary {
+            size: self.nodes[node].value.map_or(0, |_| 1),
+            sum: self.nodes[node].value.unwrap_or_else(Value::zero),
+        };
+        for &direction in [Direction::Left, Direction::Right].iter() {
+            if let Some(child) = self.nodes[node].get_child(direction) {
+                len += self.nodes[child].len;
+                summary.size += self.nodes[child].summary.size;
+                summary.sum = summary.sum + self.nodes[child].summary.sum;
+            }
+        }
+        self.nodes[node].len = len;
+        self.nodes[node].summary = summary;
+    }
+
+    fn set_left_child(&mut self, node: usize, child: Option<usize>) {
+        self.nodes[node].left_child = child;
+        if let Some(child) = child {
+            self.nodes[child].parent = Some(node);
+        }
+    }
+
+    fn set_right_child(&mut self, node: usize, child: Option<usize>) {
+        self.nodes[node].right_child = child;
+        if let Some(child) = child {
+            self.nodes[child].parent = Some(node);
+        }
+    }
+
+    /// Splits the treap rooted at `tree` into its first `at` elements and
+    /// the rest
+    fn split(&mut self, tree: Option<usize>, at: usize) -> (Option<usize>, Option<usize>) {
+        let node = match tree {
+            None => return (None, None),
+            Some(node) => node,
+        };
+        self.nodes[node].parent = None;
+        let left_len = self.len_of(self.nodes[node].left_child);
+        if at <= left_len {
+            let (left, right) = self.split(self.nodes[node].left_child, at);
+            self.set_left_child(node, right);
+            self.update(node);
+            (left, Some(node))
+        } else {
+            let (left, right) = self.split(self.nodes[node].right_child, at - left_len - 1);
+            self.set_right_child(node, left);
+            self.update(node);
+            (Some(node), right)
+        }
+    }
+
+    /// Concatenates the treaps rooted at `left` and `right`
+    fn merge(&mut self, left: Option<usize>, right: Option<usize>) -> Option<usize> {
+        let (left, right) = match (left, right) {
+            (None, tree) | (tree, None) => return tree,
+            (Some(left), Some(right)) => (left, right),
+        };
+        if self.nodes[left].priority > self.nodes[right].priority {
+            let merged = self.merge(self.nodes[left].right_child, Some(right));
+            self.set_right_child(left, merged);
+            self.update(left);
+            Some(left)
+        } else {
+            let merged = self.merge(Some(left), self.nodes[right].left_child);
+            self.set_left_child(right, merged);
+            self.update(right);
+            Some(right)
+        }
+    }
+
+    fn root_of(&self, mut node: usize) -> usize {
+        while let Some(parent) = self.nodes[node].parent {
+            node = parent;
+        }
+        node
+    }
+
+    /// The position of `node` in its tour
+    fn position_of(&self, mut node: usize) -> usize {
+        let mut position = self.len_of(self.nodes[node].left_child);
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].right_child == Some(node) {
+                position += self.len_of(self.nodes[parent].left_child) + 1;
+            }
+            node = parent;
+        }
+        position
+    }
+
+    /// Rotates the tour of `node` to start at it, returning the new root
+    fn rotate_to_front(&mut self, node: usize) -> usize {
+        let root = self.root_of(node);
+        let position = self.position_of(node);
+        let (left, right) = self.split(Some(root), position);
+        self.merge(right, left)
+            .expect("The tour contains at least `node`")
+    }
+
+    pub fn connected(&self, a: usize, b: usize) -> bool {
+        self.root_of(self.vertices[a]) == self.root_of(self.vertices[b])
+    }
+
+    /// Adds an edge between `a` and `b`, unless they are connected
+    /// already, returning whether it was added
+    pub fn link(&mut self, a: usize, b: usize) -> bool {
+        if self.connected(a, b) {
+            return false;
+        }
+        // The new tour goes around the tree of `a`, over to `b`, around
+        // the tree of `b` and back
+        let tour_a = self.rotate_to_front(self.vertices[a]);
+        let tour_b = self.rotate_to_front(self.vertices[b]);
+        let there = self.new_node(None);
+        let back = self.new_node(None);
+        self.edges.insert((a, b), there);
+        self.edges.insert((b, a), back);
+        let tour = self.merge(Some(tour_a), Some(there));
+        let tour = self.merge(tour, Some(tour_b));
+        self.merge(tour, Some(back));
+        true
+    }
+
+    /// Removes the edge between `a` and `b`, returning whether there was
+    /// one
+    pub fn cut(&mut self, a: usize, b: usize) -> bool {
+        let (there, back) = match (self.edges.remove(&(a, b)), self.edges.remove(&(b, a))) {
+            (Some(there), Some(back)) => (there, back),
+            _ => return false,
+        };
+        // With the tour starting at `a`, the part of `b` lies between the
+        // two directions of the edge and the rest of the tour around them
+        let root = self.rotate_to_front(self.vertices[a]);
+        let there_position = self.position_of(there);
+        let back_position = self.position_of(back);
+        let (before, rest) = self.split(Some(root), there_position);
+        let (_there, rest) = self.split(rest, 1);
+        let (_tour_b, rest) = self.split(rest, back_position - there_position - 1);
+        let (_back, after) = self.split(rest, 1);
+        self.merge(before, after);
+        self.free_nodes.push(there);
+        self.free_nodes.push(back);
+        true
+    }
+
+    /// Summarizes the whole tree `vertex` is in
+    pub fn tree_summary(&self, vertex: usize) -> TourSummary<Value> {
+        self.nodes[self.root_of(self.vertices[vertex])].summary
+    }
+
+    /// Summarizes the subtree of `vertex`, when the tree is rooted at its
+    /// neighbour `parent`, or `None` if they are not neighbours
+    pub fn subtree_summary(&mut self, vertex: usize, parent: usize) -> Option<TourSummary<Value>> {
+        let there = *self.edges.get(&(parent, vertex))?;
+        let back = self.edges[&(vertex, parent)];
+        let root = self.rotate_to_front(self.vertices[parent]);
+        let there_position = self.position_of(there);
+        let back_position = self.position_of(back);
+        let (before, rest) = self.split(Some(root), there_position + 1);
+        let (subtree, after) = self.split(rest, back_position - there_position - 1);
+        let summary = subtree.map(|subtree| self.nodes[subtree].summary);
+        let rest = self.merge(subtree, after);
+        self.merge(before, rest);
+        summary
+    }
+}
+
+impl<Value: Number> Default for EulerTourTree<Value> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::BTreeSet;
+
+    /// The vertices reachable from `start` without going through `avoid`
+    fn brute_force_component(
+        edges: &BTreeSet<(usize, usize)>,
+        start: usize,
+        avoid: Option<usize>,
+    ) -> BTreeSet<usize> {
+        let mut seen: BTreeSet<usize> = vec![start].into_iter().collect();
+        let mut stack = vec![start];
+        while let Some(vertex) = stack.pop() {
+            for &(x, y) in edges.iter() {
+                for &(from, to) in [(x, y), (y, x)].iter() {
+                    if from == vertex && Some(to) != avoid && seen.insert(to) {
+                        stack.push(to);
+                    }
+                }
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn test_link_cut_and_subtrees() {
+        let mut forest = EulerTourTree::new();
+        for value in [1, 2, 4, 8, 16].iter() {
+            forest.add_vertex(*value);
+        }
+        //   0
+        //  / \
+        // 1   2 - 3
+        assert!(forest.link(0, 1));
+        assert!(forest.link(0, 2));
+        assert!(forest.link(3, 2));
+        assert!(!forest.link(1, 3));
+        assert!(forest.connected(1, 3));
+        assert!(!forest.connected(1, 4));
+        assert_eq!(forest.tree_summary(3), TourSummary { size: 4, sum: 15 });
+        assert_eq!(
+            forest.subtree_summary(2, 0),
+            Some(TourSummary { size: 2, sum: 12 })
+        );
+        assert_eq!(
+            forest.subtree_summary(0, 2),
+            Some(TourSummary { size: 2, sum: 3 })
+        );
+        assert_eq!(forest.subtree_summary(1, 2), None);
+
+        forest.set_value(3, 100);
+        assert_eq!(forest.get_value(3), 100);
+        assert_eq!(forest.subtree_summary(2, 0).map(|s| s.sum), Some(104));
+
+        assert!(!forest.cut(1, 2));
+        assert!(forest.cut(2, 0));
+        assert!(!forest.cut(0, 2));
+        assert!(!forest.connected(1, 3));
+        assert_eq!(forest.tree_summary(0), TourSummary { size: 2, sum: 3 });
+        assert_eq!(forest.tree_summary(2), TourSummary { size: 2, sum: 104 });
+        // The nodes of the cut edge get reused
+        assert!(forest.link(4, 1));
+        assert_eq!(forest.nodes.len(), 5 + 6);
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let n = 30;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut forest = EulerTourTree::new();
+        let mut values = vec![];
+        for _ in 0..n {
+            let value = rng.gen_range(-50..50);
+            forest.add_vertex(value);
+            values.push(value);
+        }
+        let mut edges = BTreeSet::new();
+        let summarize = |vertices: &BTreeSet<usize>, values: &[i64]| TourSummary {
+            size: vertices.len(),
+            sum: vertices.iter().map(|&v| values[v]).sum(),
+        };
+        for _ in 0..3_000 {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            let component = brute_force_component(&edges, a, None);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    assert_eq!(forest.link(a, b), !component.contains(&b));
+                    if !component.contains(&b) {
+                        edges.insert((a.min(b), a.max(b)));
+                    }
+                }
+                2 => {
+                    assert_eq!(forest.cut(a, b), edges.remove(&(a.min(b), a.max(b))));
+                }
+                3 => {
+                    let value = rng.gen_range(-50..50);
+                    forest.set_value(a, value);
+                    values[a] = value;
+                }
+                _ => {
+                    assert_eq!(forest.connected(a, b), component.contains(&b));
+                    assert_eq!(forest.tree_summary(a), summarize(&component, &values));
+                    let expected = if edges.contains(&(a.min(b), a.max(b))) {
+                        Some(summarize(
+                            &brute_force_component(&edges, a, Some(b)),
+                            &values,
+                        ))
+                    } else {
+                        None
+                    };
+                    assert_eq!(forest.subtree_summary(a, b), expected);
+                }
+            }
+        }
+    }
+}
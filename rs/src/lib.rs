@@ -1,6 +1,13 @@
+pub mod bptree;
 pub mod bst;
+pub mod btree;
+pub mod dot;
 pub mod doublylinkedlist;
 pub mod dsu;
+pub mod eulertour;
+pub mod linkcut;
 pub mod linkedlist;
 pub mod persistentlist;
+#[cfg(test)]
+pub(crate) mod testutil;
 pub mod unsafelinkedlist;
@@ -0,0 +1,389 @@
+//! A link-cut tree: a forest, which can gain and lose edges, answering
+//! connectivity and path queries in O(log n) amortized time
+//!
+//! The forest is split into preferred paths, each of which is stored in
+//! a splay tree ordered by depth. The root of every splay tree keeps a
+//! path-parent pointer to the node its path hangs from, which is stored
+//! in the same `parent` field, but without the parent having the node as
+//! a child.
+
+use crate::bst::base::{flip_direction, Direction};
+use crate::bst::lazy::{Number, Summary};
+
+struct LinkCutNode<Value> {
+    value: Value,
+    /// Summary of the splay subtree, which is a piece of a path
+    summary: Summary<Value>,
+    /// Splay tree parent or path-parent
+    parent: Option<usize>,
+    left_child: Option<usize>,
+    right_child: Option<usize>,
+    /// The children of this node are swapped already, but the subtrees
+    /// below them still have to be reversed
+    reversed: bool,
+}
+
+/// A forest over nodes `0..len`, with a value at every node
+pub struct LinkCutTree<Value> {
+    nodes: Vec<LinkCutNode<Value>>,
+}
+
+impl<Value> LinkCutNode<Value> {
+    fn get_child(&self, direction: Direction) -> Option<usize> {
+        match direction {
+            Direction::Left => self.left_child,
+            Direction::Right => self.right_child,
+        }
+    }
+
+    fn set_child(&mut self, direction: Direction, child: Option<usize>) {
+        match direction {
+            Direction::Left => self.left_child = child,
+            Direction::Right => self.right_child = child,
+        }
+    }
+
+    fn reverse(&mut self) {
+        std::mem::swap(&mut self.left_child, &mut self.right_child);
+        self.reversed = !self.reversed;
+    }
+}
+
+impl<Value: Number> LinkCutTree<Value> {
+    pub fn new() -> Self {
+        Self { nodes: vec![] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Adds a node without any edges, returning its index
+    pub fn add_node(&mut self, value: Value) -> usize {
+        self.nodes.push(LinkCutNode {
+            value,
+            summary: Summary::of(value),
+            parent: None,
+            left_child: None,
+            right_child: None,
+            reversed: false,
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn get_value(&self, node: usize) -> Value {
+        self.nodes[node].value
+    }
+
+    pub fn set_value(&mut self, node: usize, value: Value) {
+        // Only the splay root summarizes anything above itself
+        self.access(node);
+        self.nodes[node].value = value;
+        self.update(node);
+    }
+
+    /// The direction `node` hangs off its splay parent in, `None` for
+    /// the root of a splay tree
+    fn direction_from_parent(&self, node: usize) -> Option<Direction> {
+        let parent = &self.nodes[self.nodes[node].parent?];
+        if parent.left_child == Some(node) {
+            Some(Direction::Left)
+        } else if parent.right_child == Some(node) {
+            Some(Direction::Right)
+        } else {
+            None
+        }
+    }
+
+    fn update(&mut self, node: usize) {
+        let mut summary = Summary::of(self.nodes[node].value);
+        if let Some(left_child) = self.nodes[node].left_child {
+            summary = self.nodes[left_child].summary.combine(summary);
+        }
+        if let Some(right_child) = self.nodes[node].right_child {
+            summary = summary.combine(self.nodes[right_child].summary);
+        }
+        self.nodes[node].summary = summary;
+    }
+
+    /// Hands the pending reversal down to the children
+    fn push(&mut self, node: usize) {
+        if !self.nodes[node].reversed {
+            return;
+        }
+        self.nodes[node].reversed = false;
+        for &direction in [Direction::Left, Direction::Right].iter() {
+            if let Some(child) = self.nodes[node].get_child(direction) {
+                self.nodes[child].reverse();
+            }
+        }
+    }
+
+    /// Lifts `node` above its splay parent, keeping the in-order sequence
+    fn rotate(&mut self, node: usize) {
+        let direction = self
+            .direction_from_parent(node)
+            .expect("Splay roots cannot be rotated");
+        let parent = self.nodes[node].parent.unwrap();
+        let maybe_grandparent_direction = self.direction_from_parent(parent);
+        let grandparent = self.nodes[parent].parent;
+
+        let inner_child = self.nodes[node].get_child(flip_direction(direction));
+        self.nodes[parent].set_child(direction, inner_child);
+        if let Some(inner_child) = inner_child {
+            self.nodes[inner_child].parent = Some(parent);
+        }
+        self.nodes[node].set_child(flip_direction(direction), Some(parent));
+        self.nodes[parent].parent = Some(node);
+        // Takes over the path-parent as well, if `parent` was a splay root
+        self.nodes[node].parent = grandparent;
+        if let (Some(grandparent), Some(grandparent_direction)) =
+            (grandparent, maybe_grandparent_direction)
+        {
+            self.nodes[grandparent].set_child(grandparent_direction, Some(node));
+        }
+        self.update(parent);
+        self.update(node);
+    }
+
+    /// Makes `node` the root of its splay tree
+    fn splay(&mut self, node: usize) {
+        // Pending reversals have to reach `node` before anything moves
+        let mut path = vec![node];
+        let mut top = node;
+        while self.direction_from_parent(top).is_some() {
+            top = self.nodes[top].parent.unwrap();
+            path.push(top);
+        }
+        for &n in path.iter().rev() {
+            self.push(n);
+        }
+
+        while let Some(direction) = self.direction_from_parent(node) {
+            let parent = self.nodes[node].parent.unwrap();
+            if let Some(parent_direction) = self.direction_from_parent(parent) {
+                if direction == parent_direction {
+                    self.rotate(parent);
+                } else {
+                    self.rotate(node);
+                }
+            }
+            self.rotate(node);
+        }
+    }
+
+    /// Makes the path from the root of its tree to `node` preferred,
+    /// leaving `node` at the root of its splay tree without right child
+    fn access(&mut self, node: usize) {
+        let mut below = None;
+        let mut maybe_current = Some(node);
+        while let Some(current) = maybe_current {
+            self.splay(current);
+            self.nodes[current].right_child = below;
+            self.update(current);
+            below = Some(current);
+            maybe_current = self.nodes[current].parent;
+        }
+        self.splay(node);
+    }
+
+    /// Makes `node` the root of its tree
+    fn make_root(&mut self, node: usize) {
+        self.access(node);
+        self.nodes[node].reverse();
+    }
+
+    /// The root of the tree `node` is in
+    ///
+    /// Trees get rerooted by `link`, `cut` and `path`, so the root is only
+    /// good for comparing with other roots until the forest changes.
+    pub fn find_root(&mut self, node: usize) -> usize {
+        self.access(node);
+        let mut root = node;
+        loop {
+            self.push(root);
+            match self.nodes[root].left_child {
+                Some(left_child) => root = left_child,
+                None => break,
+            }
+        }
+        // Keeps the amortized bounds for repeated queries
+        self.splay(root);
+        root
+    }
+
+    pub fn connected(&mut self, a: usize, b: usize) -> bool {
+        a == b || self.find_root(a) == self.find_root(b)
+    }
+
+    /// Adds an edge between `a` and `b`, unless they are connected
+    /// already, returning whether it was added
+    pub fn link(&mut self, a: usize, b: usize) -> bool {
+        if self.connected(a, b) {
+            return false;
+        }
+        self.make_root(a);
+        self.nodes[a].parent = Some(b);
+        true
+    }
+
+    /// Removes the edge between `a` and `b`, returning whether there was
+    /// one
+    pub fn cut(&mut self, a: usize, b: usize) -> bool {
+        if a == b {
+            return false;
+        }
+        self.make_root(a);
+        self.access(b);
+        // The edge exists iff `a` comes right before `b` on the path
+        // from the root, i.e. `a` is the left child of `b` without a
+        // right child
+        if self.nodes[b].left_child != Some(a) {
+            return false;
+        }
+        self.push(a);
+        if self.nodes[a].right_child.is_some() {
+            return false;
+        }
+        self.nodes[b].left_child = None;
+        self.nodes[a].parent = None;
+        self.update(b);
+        true
+    }
+
+    /// Summarizes the values on the path from `a` to `b`, both included,
+    /// `None` if they are not connected
+    pub fn path(&mut self, a: usize, b: usize) -> Option<Summary<Value>> {
+        if !self.connected(a, b) {
+            return None;
+        }
+        self.make_root(a);
+        self.access(b);
+        Some(self.nodes[b].summary)
+    }
+}
+
+impl<Value: Number> Default for LinkCutTree<Value> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    /// The nodes on the path from `a` to `b` in the forest of `edges`
+    fn brute_force_path(
+        n: usize,
+        edges: &BTreeSet<(usize, usize)>,
+        a: usize,
+        b: usize,
+    ) -> Option<Vec<usize>> {
+        let mut previous = vec![None; n];
+        let mut queue = vec![a];
+        previous[a] = Some(a);
+        while let Some(node) = queue.pop() {
+            for &(x, y) in edges.iter() {
+                for &(from, to) in [(x, y), (y, x)].iter() {
+                    if from == node && previous[to].is_none() {
+                        previous[to] = Some(node);
+                        queue.push(to);
+                    }
+                }
+            }
+        }
+        previous[b]?;
+        let mut path = vec![b];
+        while *path.last().unwrap() != a {
+            path.push(previous[*path.last().unwrap()].unwrap());
+        }
+        Some(path)
+    }
+
+    #[test]
+    fn test_link_and_cut() {
+        let mut forest = LinkCutTree::new();
+        let nodes: Vec<usize> = (0..5).map(|i| forest.add_node(i * 10)).collect();
+        // 0 - 1 - 2   3 - 4
+        assert!(forest.link(nodes[0], nodes[1]));
+        assert!(forest.link(nodes[2], nodes[1]));
+        assert!(forest.link(nodes[3], nodes[4]));
+        assert!(!forest.link(nodes[0], nodes[2]));
+        assert!(forest.connected(0, 2));
+        assert!(!forest.connected(2, 3));
+        assert_eq!(forest.path(0, 2).map(|s| s.sum), Some(30));
+        assert_eq!(forest.path(2, 4), None);
+
+        assert!(forest.link(4, 2));
+        assert_eq!(forest.find_root(0), forest.find_root(3));
+        let path = forest.path(0, 3).unwrap();
+        assert_eq!((path.count, path.sum, path.min, path.max), (5, 100, 0, 40));
+        forest.set_value(1, -5);
+        assert_eq!(forest.path(3, 0).map(|s| s.min), Some(-5));
+        assert_eq!(forest.get_value(1), -5);
+
+        assert!(!forest.cut(0, 2));
+        assert!(forest.cut(1, 2));
+        assert!(!forest.cut(1, 2));
+        assert!(!forest.connected(0, 4));
+        assert!(forest.connected(2, 3));
+        assert_eq!(forest.path(1, 1).map(|s| s.sum), Some(-5));
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let n = 30;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut forest = LinkCutTree::new();
+        let mut values = vec![];
+        for _ in 0..n {
+            let value = rng.gen_range(-50..50);
+            forest.add_node(value);
+            values.push(value);
+        }
+        let mut edges = BTreeSet::new();
+        for _ in 0..3_000 {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            let path = brute_force_path(n, &edges, a, b);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    assert_eq!(forest.link(a, b), path.is_none());
+                    if path.is_none() {
+                        edges.insert((a.min(b), a.max(b)));
+                    }
+                }
+                2 => {
+                    assert_eq!(forest.cut(a, b), edges.remove(&(a.min(b), a.max(b))));
+                }
+                3 => {
+                    let value = rng.gen_range(-50..50);
+                    forest.set_value(a, value);
+                    values[a] = value;
+                }
+                _ => {
+                    assert_eq!(forest.connected(a, b), path.is_some());
+                    let expected = path.map(|path| {
+                        let path_values: Vec<i64> = path.iter().map(|&node| values[node]).collect();
+                        (
+                            path.len(),
+                            path_values.iter().sum::<i64>(),
+                            *path_values.iter().min().unwrap(),
+                            *path_values.iter().max().unwrap(),
+                        )
+                    });
+                    let actual = forest.path(a, b).map(|s| (s.count, s.sum, s.min, s.max));
+                    assert_eq!(actual, expected);
+                }
+            }
+        }
+    }
+}
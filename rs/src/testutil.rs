@@ -0,0 +1,9 @@
+//! Helpers shared by the tests of several modules
+
+use std::path::PathBuf;
+use std::process;
+
+/// A path in the temporary directory unique to the test `name`
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dibrova-{}-{}", process::id(), name))
+}